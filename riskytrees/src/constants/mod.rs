@@ -8,3 +8,9 @@ pub fn get_database_host() -> String {
         Err(err) => "mongodb://localhost:27017".to_owned()
     }
 }
+
+// Built-in analysis models. These ids are handed out by GET /models and stored
+// as a project's selectedModel.
+pub const ATTACKER_LIKELIHOOD_MODEL_ID: &str = "b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74";
+pub const RISK_OF_ATTACK_MODEL_ID: &str = "f1644cb9-b2a5-4abb-813f-98d0277e42f2";
+pub const EVITA_MODEL_ID: &str = "bf4397f7-93ae-4502-a4a2-397f40f5cc49";
@@ -9,7 +9,7 @@ use rocket::Data;
 use std::{collections::{HashMap, HashSet}, hash::Hash, vec};
 use uuid::Uuid;
 use bson::bson;
-use crate::{auth::generate_user_jwt, constants, errors::DatabaseError, expression_evaluator, models::{ApiAddMemberPayload, ApiProjectConfigResponseResult, ApiTreeDagItem, AuthPersonalTokensResponseResult}};
+use crate::{auth::generate_user_jwt, constants, errors::DatabaseError, expression_evaluator, model_evaluator, tree_analysis, models::{ApiAddMemberPayload, ApiProjectConfigResponseResult, ApiTreeDagItem, AuthPersonalTokensResponseResult}};
 use crate::errors;
 use crate::helpers;
 use crate::models;
@@ -368,6 +368,78 @@ pub async fn update_project_model(client: mongodb::Client, tenant: Tenant, proje
     
 }
 
+// Projects that never stored a matrix get the default 5x5 one
+pub async fn get_project_risk_matrix(client: &mongodb::Client, tenant: Tenant, project_id: &String) -> Result<models::RiskMatrix, errors::DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let project_collection = database.collection::<Document>("projects");
+
+    let matched_record = project_collection.find_one(
+        doc! {
+            "_id": mongodb::bson::oid::ObjectId::parse_str(&project_id).expect("Checked"),
+            "_tenant": tenant.name.to_owned()
+        },
+        None,
+    ).await?;
+
+    match matched_record {
+        Some(record) => {
+            match record.get_document("riskMatrix") {
+                Ok(matrix_doc) => {
+                    let matrix: Result<models::RiskMatrix, mongodb::bson::de::Error> = mongodb::bson::from_bson(mongodb::bson::Bson::Document(matrix_doc.clone()));
+
+                    match matrix {
+                        Ok(matrix) => Ok(matrix),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            Ok(models::RiskMatrix::default())
+                        }
+                    }
+                },
+                Err(_) => Ok(models::RiskMatrix::default())
+            }
+        },
+        None => Err(errors::DatabaseError {
+            message: "Could not find project".to_owned()
+        })
+    }
+}
+
+pub async fn update_project_risk_matrix(client: &mongodb::Client, tenant: Tenant, project_id: &String, matrix: &models::RiskMatrix) -> Result<models::RiskMatrix, errors::DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let project_collection = database.collection::<Document>("projects");
+
+    let matrix_bson = mongodb::bson::to_bson(matrix).map_err(|err| errors::DatabaseError {
+        message: err.to_string()
+    })?;
+
+    let res = project_collection.find_one_and_update(doc! {
+        "_id": mongodb::bson::oid::ObjectId::parse_str(&project_id).expect("Checked"),
+        "_tenant": tenant.name.to_owned()
+    }, doc! {
+        "$set": {
+            "riskMatrix": matrix_bson
+        }
+    }, None).await?;
+
+    match res {
+        Some(_) => get_project_risk_matrix(client, tenant, project_id).await,
+        None => Err(errors::DatabaseError {
+            message: "Could not find project to update".to_owned()
+        })
+    }
+}
+
+// Everything a model needs to compute or validate trees in this project
+pub async fn get_model_context(client: &mongodb::Client, tenant: &Tenant, project_id: &String) -> model_evaluator::ModelContext {
+    model_evaluator::ModelContext {
+        risk_matrix: get_project_risk_matrix(client, tenant.clone(), project_id).await.unwrap_or_default(),
+        custom_models: get_custom_models_for_tenant(client, tenant).await
+            .into_iter()
+            .map(model_evaluator::custom::FormulaModel::new)
+            .collect()
+    }
+}
+
 pub async fn create_project_tree(
     client: mongodb::Client,
     tenant: Tenant,
@@ -486,8 +558,33 @@ fn convert_bson_document_to_ModelAttribute_map(bson_doc: &Document) -> HashMap<S
     new_map
 }
 
-// Returns all the data contained in a single tree
-async fn get_full_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: String, project_id: &String, config_id: Option<String>) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+// The given stored config, or the project's selected one when none is given
+pub async fn load_config(client: &mongodb::Client, tenant: Tenant, project_id: &String, config_id: Option<String>) -> Result<models::ApiProjectConfigResponseResult, errors::DatabaseError> {
+    match config_id {
+        Some(ref config_id) => get_config_by_id(client, tenant, config_id).await,
+        None => get_selected_config(client, tenant, project_id).await
+    }
+}
+
+// Loads a tree with every node's condition resolved against the given config,
+// but without running any model. When the config couldn't be loaded every
+// conditional node is left unresolved.
+async fn get_resolved_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: String, config: &Result<models::ApiProjectConfigResponseResult, errors::DatabaseError>) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    let mut tree = get_unresolved_tree_data(client, tenant, tree_id).await?;
+
+    // Conditions can read other nodes, so they're resolved together once
+    // every node is loaded
+    match config {
+        Ok(config) => expression_evaluator::resolve_tree_conditions(&mut tree.nodes, &tree.rootNodeId, &config.attributes),
+        Err(err) => eprintln!("No config!")
+    }
+
+    Ok(tree)
+}
+
+// Loads a tree's nodes as stored. Only nodes without a condition count as
+// resolved.
+async fn get_unresolved_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: String) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
     let database = client.database(constants::DATABASE_NAME);
     let trees_collection = database.collection::<Document>("trees");
 
@@ -523,37 +620,41 @@ async fn get_full_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: S
                         };
 
                         let model_attributes = match node.get_document("modelAttributes") {
-                            Ok(val) => Some(convert_bson_document_to_ModelAttribute_map(val)),
+                            Ok(val) => {
+                                let mut attributes = convert_bson_document_to_ModelAttribute_map(val);
+                                model_evaluator::cvss::strip_base_score(&mut attributes);
+                                Some(attributes)
+                            },
                             Err(err) => None
                         };
 
-                        let mut condition_resolved = true; // Default to true
-                        if condition_attribute.is_some() {
-                            let config = match config_id {
-                                Some(ref config_id) => get_config_by_id(client, tenant.clone(), &config_id).await,
-                                None => get_selected_config(client, tenant.clone(), project_id).await
-                            };
-
-                            match config {
-                                Ok(config) => {
-                                    condition_resolved = expression_evaluator::evaluate(condition_attribute.expect("Already checked"), &config);
-                                },
-                                Err(err) => {
-                                    eprintln!("No config!");
-                                    condition_resolved = false;
-                                }
-                            }
-                        }
+                        let gate_type = match node.get_str("gateType") {
+                            Ok(val) => models::GateType::parse(val),
+                            Err(_) => models::GateType::Or
+                        };
 
+                        let node_type = match node.get_str("nodeType") {
+                            Ok(val) => models::NodeType::parse(val),
+                            Err(_) => models::NodeType::Attack
+                        };
 
                         nodes_vec.push(models::ApiFullComputedNodeData {
                             id: id.to_owned(),
                             title: title.to_owned(),
                             description: description.to_owned(),
                             conditionAttribute: condition_attribute.unwrap_or("").to_owned(),
-                            conditionResolved: condition_resolved,
+                            // Without a config only nodes that have no condition at all resolve
+                            conditionResolved: condition_attribute.is_none(),
+                            infeasible: false,
                             children: children.unwrap_or(Vec::new()),
-                            modelAttributes: model_attributes.unwrap_or(HashMap::new())
+                            gateType: gate_type,
+                            nodeType: node_type,
+                            modelAttributes: model_attributes.unwrap_or(HashMap::new()),
+                            computedAttributes: HashMap::new(),
+                            mitigatedAttributes: HashMap::new(),
+                            timeToCompromise: None,
+                            explanation: None,
+                            riskBucket: None
                         })
                     },
                     None => {
@@ -562,10 +663,15 @@ async fn get_full_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: S
                 }
 
             }
+
             Ok(models::ApiFullComputedTreeData {
                 title: title.to_owned(),
                 rootNodeId: root_node_id.to_owned(),
-                nodes: nodes_vec
+                nodes: nodes_vec,
+                rootScore: None,
+                mitigatedRootScore: None,
+                mitigationCost: 0.0,
+                contributingTrees: vec![]
             })
         },
         None => {
@@ -576,6 +682,85 @@ async fn get_full_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: S
     }
 }
 
+async fn get_full_tree_data(client: &mongodb::Client, tenant: Tenant, tree_id: String, project_id: &String, config_id: Option<String>, profile_id: Option<String>) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    let config = load_config(client, tenant.clone(), project_id, config_id).await;
+    get_full_tree_data_for_config(client, tenant, tree_id, project_id, &config, profile_id).await
+}
+
+async fn get_full_tree_data_for_config(client: &mongodb::Client, tenant: Tenant, tree_id: String, project_id: &String, config: &Result<models::ApiProjectConfigResponseResult, errors::DatabaseError>, profile_id: Option<String>) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    let mut tree = get_resolved_tree_data(client, tenant.clone(), tree_id, config).await?;
+
+    if let Some(profile_id) = profile_id {
+        let profile = get_attacker_profile(client, tenant.clone(), project_id, &profile_id).await?;
+        model_evaluator::feasibility::apply_profile(&mut tree, &mut [], &profile);
+    }
+
+    let selected_model = match get_project_by_id(client, tenant.clone(), project_id.to_string()).await {
+        Some(project) => project.selected_model,
+        None => None
+    };
+    let context = get_model_context(client, &tenant, project_id).await;
+    model_evaluator::compute(&mut tree, selected_model.as_ref(), &context);
+
+    Ok(tree)
+}
+
+// Follows children that point outside the tree and loads the trees they live
+// in, then keeps going from those trees' nodes. Each tree is loaded at most once
+// (tracked in seen_tree_ids, as in get_tree_relationships_down) so cycles
+// between trees terminate.
+async fn get_linked_trees(client: &mongodb::Client, tenant: Tenant, tree: &models::ApiFullComputedTreeData, tree_id: &String, config: &Result<models::ApiProjectConfigResponseResult, errors::DatabaseError>) -> Vec<model_evaluator::LinkedTree> {
+    let mut linked: Vec<model_evaluator::LinkedTree> = vec![];
+    let mut seen_tree_ids = HashSet::new();
+    let mut known_node_ids: HashSet<String> = tree.nodes.iter().map(|node| node.id.clone()).collect();
+    let mut pending: Vec<String> = tree.nodes.iter().flat_map(|node| node.children.clone()).collect();
+
+    seen_tree_ids.insert(tree_id.clone());
+
+    while let Some(node_id) = pending.pop() {
+        if known_node_ids.contains(&node_id) {
+            continue;
+        }
+        known_node_ids.insert(node_id.clone());
+
+        let linked_tree_id = match get_tree_from_node_id(client, tenant.clone(), node_id.clone()).await {
+            Ok(res) => match res.result {
+                Some(res) => res.treeId,
+                None => continue
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+
+        if seen_tree_ids.contains(&linked_tree_id) {
+            continue;
+        }
+        seen_tree_ids.insert(linked_tree_id.clone());
+
+        match get_resolved_tree_data(client, tenant.clone(), linked_tree_id.clone(), config).await {
+            Ok(linked_tree) => {
+                for node in &linked_tree.nodes {
+                    known_node_ids.insert(node.id.clone());
+                    pending.extend(node.children.iter().cloned());
+                }
+
+                linked.push(model_evaluator::LinkedTree {
+                    id: linked_tree_id,
+                    title: linked_tree.title,
+                    nodes: linked_tree.nodes
+                });
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    linked
+}
+
 pub async fn get_trees_by_project_id(
     client: &mongodb::Client,
     tenant: Tenant,
@@ -603,7 +788,7 @@ pub async fn get_tree_by_id(
     tree_id: String,
     project_id: String
 ) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
-    get_full_tree_data(client, tenant, tree_id, &project_id, None).await
+    get_full_tree_data(client, tenant, tree_id, &project_id, None, None).await
 
 }
 
@@ -612,10 +797,176 @@ pub async fn get_tree_by_id_with_config(
     tenant: Tenant,
     tree_id: String,
     project_id: String,
+    config_id: Option<String>,
+    profile_id: Option<String>
+) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    get_full_tree_data(client, tenant, tree_id, &project_id, config_id, profile_id).await
+
+}
+
+// A condition-resolved tree along with every tree reachable through its links
+pub async fn get_tree_with_links(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    project_id: &String,
     config_id: Option<String>
+) -> Result<(models::ApiFullComputedTreeData, Vec<model_evaluator::LinkedTree>), errors::DatabaseError> {
+    let config = load_config(client, tenant.clone(), project_id, config_id).await;
+    get_tree_with_links_for_config(client, tenant, tree_id, &config).await
+}
+
+async fn get_tree_with_links_for_config(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    config: &Result<models::ApiProjectConfigResponseResult, errors::DatabaseError>
+) -> Result<(models::ApiFullComputedTreeData, Vec<model_evaluator::LinkedTree>), errors::DatabaseError> {
+    let tree = get_resolved_tree_data(client, tenant.clone(), tree_id.clone(), config).await?;
+    let linked = get_linked_trees(client, tenant, &tree, &tree_id, config).await;
+
+    Ok((tree, linked))
+}
+
+// Computes the selected model across subtree links, so trees reached through
+// children in other trees count towards this tree's scores
+pub async fn get_tree_by_id_with_subtrees(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    project_id: String,
+    config_id: Option<String>,
+    profile_id: Option<String>
 ) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
-    get_full_tree_data(client, tenant, tree_id, &project_id, config_id).await
+    let config = load_config(client, tenant.clone(), &project_id, config_id).await;
+    get_tree_with_subtrees_for_config(client, tenant, tree_id, project_id, &config, profile_id).await
+}
+
+async fn get_tree_with_subtrees_for_config(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    project_id: String,
+    config: &Result<models::ApiProjectConfigResponseResult, errors::DatabaseError>,
+    profile_id: Option<String>
+) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    let (mut tree, mut linked) = get_tree_with_links_for_config(client, tenant.clone(), tree_id, config).await?;
+
+    if let Some(profile_id) = profile_id {
+        let profile = get_attacker_profile(client, tenant.clone(), &project_id, &profile_id).await?;
+        model_evaluator::feasibility::apply_profile(&mut tree, &mut linked, &profile);
+    }
+
+    let selected_model = match get_project_by_id(client, tenant.clone(), project_id.clone()).await {
+        Some(project) => project.selected_model,
+        None => None
+    };
+    let context = get_model_context(client, &tenant, &project_id).await;
+    model_evaluator::compute_with_links(&mut tree, &linked, selected_model.as_ref(), &context);
+
+    Ok(tree)
+}
+
+// Computes the tree against config attributes that were never stored, for
+// trying out a config before saving it. Nothing is written.
+pub async fn evaluate_tree_with_attributes(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    project_id: String,
+    attributes: serde_json::Value,
+    include_subtrees: bool,
+    profile_id: Option<String>
+) -> Result<models::ApiFullComputedTreeData, errors::DatabaseError> {
+    let config = Ok(ApiProjectConfigResponseResult {
+        id: "".to_owned(),
+        attributes,
+        name: None
+    });
+
+    if include_subtrees {
+        get_tree_with_subtrees_for_config(client, tenant, tree_id, project_id, &config, profile_id).await
+    } else {
+        get_full_tree_data_for_config(client, tenant, tree_id, &project_id, &config, profile_id).await
+    }
+}
+
+// Evaluates the tree once against every config related to the project, in
+// the order they were added. A config that can't be loaded gets a column
+// carrying the error instead of failing the whole matrix.
+pub async fn get_tree_config_matrix(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    tree_id: String,
+    project_id: String,
+    include_subtrees: bool,
+    profile_id: Option<String>
+) -> Result<models::ApiConfigMatrix, errors::DatabaseError> {
+    let mut evaluations = vec![];
+
+    for config_id in get_configs_for_project(client, tenant.clone(), &project_id).await {
+        let config = match get_config_by_id(client, tenant.clone(), &config_id).await {
+            Ok(config) => config,
+            Err(err) => {
+                evaluations.push(tree_analysis::config_matrix::ConfigEvaluation {
+                    config_id,
+                    name: None,
+                    tree: Err(format!("Could not load config: {}", err.message))
+                });
+                continue;
+            }
+        };
+        let name = config.name.clone();
+        let config = Ok(config);
+
+        let tree = if include_subtrees {
+            get_tree_with_subtrees_for_config(client, tenant.clone(), tree_id.clone(), project_id.clone(), &config, profile_id.clone()).await?
+        } else {
+            get_full_tree_data_for_config(client, tenant.clone(), tree_id.clone(), &project_id, &config, profile_id.clone()).await?
+        };
+
+        evaluations.push(tree_analysis::config_matrix::ConfigEvaluation {
+            config_id,
+            name,
+            tree: Ok(tree)
+        });
+    }
+
+    Ok(tree_analysis::config_matrix::build(&evaluations))
+}
+
+// Reports the config keys read by the conditions of one tree, or of every
+// tree in the project, against all of the project's stored configs
+pub async fn get_condition_key_report(
+    client: &mongodb::Client,
+    tenant: Tenant,
+    project_id: String,
+    tree_id: Option<String>
+) -> Result<models::ApiConditionKeyReport, errors::DatabaseError> {
+    let tree_ids = match tree_id {
+        Some(tree_id) => vec![tree_id],
+        None => get_trees_by_project_id(client, tenant.clone(), project_id.clone()).await?
+            .into_iter()
+            .map(|tree| tree.id)
+            .collect()
+    };
+
+    // Only the conditions are read, so nothing is resolved
+    let mut trees = vec![];
+    for tree_id in tree_ids {
+        let tree = get_unresolved_tree_data(client, tenant.clone(), tree_id.clone()).await?;
+        trees.push(expression_evaluator::usage::TreeConditions {
+            tree_id,
+            tree
+        });
+    }
+
+    let mut configs = vec![];
+    for config_id in get_configs_for_project(client, tenant.clone(), &project_id).await {
+        configs.push(get_config_by_id(client, tenant.clone(), &config_id).await?);
+    }
 
+    Ok(expression_evaluator::usage::report(&trees, &configs))
 }
 
 pub async fn update_tree_by_id(
@@ -638,7 +989,7 @@ pub async fn update_tree_by_id(
     }, None).await;
 
 
-    get_full_tree_data(client, tenant, tree_id, &project_id, None).await
+    get_full_tree_data(client, tenant, tree_id, &project_id, None, None).await
 }
 
 // DANGER: Not tenantized
@@ -838,7 +1189,7 @@ pub async fn get_tree_relationships_down(client: &mongodb::Client, tenant: Tenan
 }
 
 pub async fn get_nodes_from_tree(client: &mongodb::Client, tenant: Tenant, treeId: &String, projectId: &String) -> Vec<models::ApiFullComputedNodeData> {
-    let data = get_full_tree_data(client, tenant, treeId.to_string(), projectId, None).await;
+    let data = get_full_tree_data(client, tenant, treeId.to_string(), projectId, None, None).await;
 
     match data {
         Ok(res) => {
@@ -1452,6 +1803,269 @@ pub async fn get_orgs(client: &mongodb::Client, tenants: Vec<Tenant>) -> Result<
     Ok(result)
 }
 
+// Resolves the org's tenant and checks the caller is a member of it
+async fn get_accessible_org_tenant(client: &mongodb::Client, tenants: &Vec<Tenant>, org_id: &String) -> Result<Tenant, DatabaseError> {
+    match get_tenant_for_org(client, org_id).await {
+        Ok(needed_tenant) => {
+            if tenants.contains(&needed_tenant) {
+                Ok(needed_tenant)
+            } else {
+                Err(errors::DatabaseError {
+                    message: "No access to org".to_string()
+                })
+            }
+        },
+        Err(_) => Err(errors::DatabaseError {
+            message: "Finding tenant for org failed.".to_string()
+        })
+    }
+}
+
+fn custom_model_from_doc(record: &Document) -> Option<models::CustomModelDefinition> {
+    match mongodb::bson::from_bson::<models::CustomModelDefinition>(mongodb::bson::Bson::Document(record.clone())) {
+        Ok(mut definition) => {
+            definition.id = record.get_object_id("_id").ok()?.to_string();
+            Some(definition)
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            None
+        }
+    }
+}
+
+// Custom models are stored under the org's tenant, so this serves both the org
+// endpoints and projects owned by the org.
+pub async fn get_custom_models_for_tenant(client: &mongodb::Client, tenant: &Tenant) -> Vec<models::CustomModelDefinition> {
+    let database = client.database(constants::DATABASE_NAME);
+    let model_collection = database.collection::<Document>("models");
+    let mut result = vec![];
+
+    match model_collection.find(doc! {
+        "_tenant": tenant.name.to_owned()
+    }, None).await {
+        Ok(mut records) => {
+            while let Some(record) = records.next().await {
+                if let Ok(record) = record {
+                    if let Some(definition) = custom_model_from_doc(&record) {
+                        result.push(definition);
+                    }
+                }
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err)
+        }
+    }
+
+    result
+}
+
+pub async fn get_custom_models_for_org(client: &mongodb::Client, tenants: Vec<Tenant>, org_id: &String) -> Result<Vec<models::CustomModelDefinition>, DatabaseError> {
+    let needed_tenant = get_accessible_org_tenant(client, &tenants, org_id).await?;
+
+    Ok(get_custom_models_for_tenant(client, &needed_tenant).await)
+}
+
+pub async fn create_custom_model(client: &mongodb::Client, tenants: Vec<Tenant>, org_id: &String, data: &models::CustomModelPayload) -> Result<models::CustomModelDefinition, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let model_collection = database.collection::<Document>("models");
+
+    let needed_tenant = get_accessible_org_tenant(client, &tenants, org_id).await?;
+    let mut definition = models::CustomModelDefinition::from_payload("".to_owned(), org_id.clone(), data);
+
+    let mut new_doc = mongodb::bson::to_document(&definition).map_err(|err| errors::DatabaseError {
+        message: err.to_string()
+    })?;
+    new_doc.remove("id");
+    new_doc.insert("_tenant", needed_tenant.name.to_owned());
+
+    let insert_result = model_collection.insert_one(new_doc, None).await?;
+
+    match insert_result.inserted_id.as_object_id() {
+        Some(oid) => {
+            definition.id = oid.to_string();
+            Ok(definition)
+        },
+        None => Err(errors::DatabaseError {
+            message: "No object ID found.".to_string(),
+        }),
+    }
+}
+
+pub async fn update_custom_model(client: &mongodb::Client, tenants: Vec<Tenant>, org_id: &String, model_id: &String, data: &models::CustomModelPayload) -> Result<models::CustomModelDefinition, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let model_collection = database.collection::<Document>("models");
+
+    let needed_tenant = get_accessible_org_tenant(client, &tenants, org_id).await?;
+    let definition = models::CustomModelDefinition::from_payload(model_id.clone(), org_id.clone(), data);
+
+    let mut new_doc = mongodb::bson::to_document(&definition).map_err(|err| errors::DatabaseError {
+        message: err.to_string()
+    })?;
+    new_doc.remove("id");
+
+    let res = model_collection.find_one_and_update(doc! {
+        "_id": mongodb::bson::oid::ObjectId::parse_str(model_id).expect("Checked"),
+        "_tenant": needed_tenant.name.to_owned()
+    }, doc! {
+        "$set": new_doc
+    }, None).await?;
+
+    match res {
+        Some(_) => Ok(definition),
+        None => Err(errors::DatabaseError {
+            message: "Could not find model to update".to_owned()
+        })
+    }
+}
+
+pub async fn delete_custom_model(client: &mongodb::Client, tenants: Vec<Tenant>, org_id: &String, model_id: &String) -> Result<bool, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let model_collection = database.collection::<Document>("models");
+
+    let needed_tenant = get_accessible_org_tenant(client, &tenants, org_id).await?;
+
+    let res = model_collection.delete_one(doc! {
+        "_id": mongodb::bson::oid::ObjectId::parse_str(model_id).expect("Checked"),
+        "_tenant": needed_tenant.name.to_owned()
+    }, None).await?;
+
+    Ok(res.deleted_count > 0)
+}
+
+fn attacker_profile_from_doc(record: &Document) -> Option<models::AttackerProfile> {
+    match mongodb::bson::from_bson::<models::AttackerProfile>(mongodb::bson::Bson::Document(record.clone())) {
+        Ok(mut profile) => {
+            profile.id = record.get_object_id("_id").ok()?.to_string();
+            Some(profile)
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            None
+        }
+    }
+}
+
+// Attacker profiles live next to configs: one set per project, under the
+// project's tenant
+pub async fn get_attacker_profiles(client: &mongodb::Client, tenant: Tenant, project_id: &String) -> Vec<models::AttackerProfile> {
+    let database = client.database(constants::DATABASE_NAME);
+    let profile_collection = database.collection::<Document>("profiles");
+    let mut result = vec![];
+
+    match profile_collection.find(doc! {
+        "projectId": project_id.to_owned(),
+        "_tenant": tenant.name.to_owned()
+    }, None).await {
+        Ok(mut records) => {
+            while let Some(record) = records.next().await {
+                if let Ok(record) = record {
+                    if let Some(profile) = attacker_profile_from_doc(&record) {
+                        result.push(profile);
+                    }
+                }
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err)
+        }
+    }
+
+    result
+}
+
+pub async fn get_attacker_profile(client: &mongodb::Client, tenant: Tenant, project_id: &String, profile_id: &String) -> Result<models::AttackerProfile, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let profile_collection = database.collection::<Document>("profiles");
+
+    // Profile ids also come in through query strings, so they aren't checked
+    let profile_oid = match mongodb::bson::oid::ObjectId::parse_str(profile_id) {
+        Ok(oid) => oid,
+        Err(err) => return Err(errors::DatabaseError {
+            message: "Couldn't find profile".to_owned()
+        })
+    };
+
+    let record = profile_collection.find_one(doc! {
+        "_id": profile_oid,
+        "projectId": project_id.to_owned(),
+        "_tenant": tenant.name.to_owned()
+    }, None).await?;
+
+    match record.as_ref().and_then(attacker_profile_from_doc) {
+        Some(profile) => Ok(profile),
+        None => Err(errors::DatabaseError {
+            message: "Couldn't find profile".to_owned()
+        })
+    }
+}
+
+pub async fn create_attacker_profile(client: &mongodb::Client, tenant: Tenant, project_id: &String, data: &models::AttackerProfilePayload) -> Result<models::AttackerProfile, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let profile_collection = database.collection::<Document>("profiles");
+
+    let mut profile = models::AttackerProfile::from_payload("".to_owned(), project_id.clone(), data);
+
+    let mut new_doc = mongodb::bson::to_document(&profile).map_err(|err| errors::DatabaseError {
+        message: err.to_string()
+    })?;
+    new_doc.remove("id");
+    new_doc.insert("_tenant", tenant.name.to_owned());
+
+    let insert_result = profile_collection.insert_one(new_doc, None).await?;
+
+    match insert_result.inserted_id.as_object_id() {
+        Some(oid) => {
+            profile.id = oid.to_string();
+            Ok(profile)
+        },
+        None => Err(errors::DatabaseError {
+            message: "No object ID found.".to_string(),
+        }),
+    }
+}
+
+pub async fn update_attacker_profile(client: &mongodb::Client, tenant: Tenant, project_id: &String, profile_id: &String, data: &models::AttackerProfilePayload) -> Result<models::AttackerProfile, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let profile_collection = database.collection::<Document>("profiles");
+
+    let profile = models::AttackerProfile::from_payload(profile_id.clone(), project_id.clone(), data);
+
+    let mut new_doc = mongodb::bson::to_document(&profile).map_err(|err| errors::DatabaseError {
+        message: err.to_string()
+    })?;
+    new_doc.remove("id");
+
+    let res = profile_collection.find_one_and_update(doc! {
+        "_id": mongodb::bson::oid::ObjectId::parse_str(profile_id).expect("Checked"),
+        "projectId": project_id.to_owned(),
+        "_tenant": tenant.name.to_owned()
+    }, doc! {
+        "$set": new_doc
+    }, None).await?;
+
+    match res {
+        Some(_) => Ok(profile),
+        None => Err(errors::DatabaseError {
+            message: "Could not find profile to update".to_owned()
+        })
+    }
+}
+
+pub async fn delete_attacker_profile(client: &mongodb::Client, tenant: Tenant, project_id: &String, profile_id: &String) -> Result<bool, DatabaseError> {
+    let database = client.database(constants::DATABASE_NAME);
+    let profile_collection = database.collection::<Document>("profiles");
+
+    let res = profile_collection.delete_one(doc! {
+        "_id": mongodb::bson::oid::ObjectId::parse_str(profile_id).expect("Checked"),
+        "projectId": project_id.to_owned(),
+        "_tenant": tenant.name.to_owned()
+    }, None).await?;
+
+    Ok(res.deleted_count > 0)
+}
+
 pub async fn get_tenants_for_user(client: &mongodb::Client, email: &String) -> Vec<Tenant> {
     let database = client.database(constants::DATABASE_NAME);
     let tenant_collection = database.collection::<Document>("tenants");
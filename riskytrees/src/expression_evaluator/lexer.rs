@@ -0,0 +1,209 @@
+use super::{ConditionError, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(serde_json::Number),
+    String(String),
+    Identifier(String),
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Dot,
+    Not,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret
+}
+
+impl TokenKind {
+    // How the token reads in error messages
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::Number(number) => format!("number {}", number),
+            TokenKind::String(string) => format!("string {:?}", string),
+            TokenKind::Identifier(name) => format!("'{}'", name),
+            TokenKind::LeftParen => "'('".to_owned(),
+            TokenKind::RightParen => "')'".to_owned(),
+            TokenKind::LeftBracket => "'['".to_owned(),
+            TokenKind::RightBracket => "']'".to_owned(),
+            TokenKind::Comma => "','".to_owned(),
+            TokenKind::Dot => "'.'".to_owned(),
+            TokenKind::Not => "'!'".to_owned(),
+            TokenKind::And => "'&&'".to_owned(),
+            TokenKind::Or => "'||'".to_owned(),
+            TokenKind::Equal => "'=='".to_owned(),
+            TokenKind::NotEqual => "'!='".to_owned(),
+            TokenKind::Less => "'<'".to_owned(),
+            TokenKind::LessEqual => "'<='".to_owned(),
+            TokenKind::Greater => "'>'".to_owned(),
+            TokenKind::GreaterEqual => "'>='".to_owned(),
+            TokenKind::Plus => "'+'".to_owned(),
+            TokenKind::Minus => "'-'".to_owned(),
+            TokenKind::Star => "'*'".to_owned(),
+            TokenKind::Slash => "'/'".to_owned(),
+            TokenKind::Percent => "'%'".to_owned(),
+            TokenKind::Caret => "'^'".to_owned()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span
+}
+
+// Splits a condition into tokens. Spans count characters rather than bytes so
+// they line up with what users see in the editor.
+pub fn tokenize(condition: &str) -> Result<Vec<Token>, ConditionError> {
+    let chars: Vec<char> = condition.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let start = pos;
+
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        let kind = if c.is_ascii_digit() {
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
+                pos += 1;
+            }
+
+            let mut is_float = false;
+            if pos + 1 < chars.len() && chars[pos] == '.' && chars[pos + 1].is_ascii_digit() {
+                is_float = true;
+                pos += 1;
+                while pos < chars.len() && chars[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+            }
+
+            let text: String = chars[start..pos].iter().collect();
+            let number = if is_float {
+                text.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+            } else {
+                text.parse::<i64>().ok().map(serde_json::Number::from)
+            };
+
+            match number {
+                Some(number) => TokenKind::Number(number),
+                None => return Err(ConditionError::new(format!("Number {} is out of range", text), Span::new(start, pos)))
+            }
+        } else if c == '"' || c == '\'' {
+            let (string, end) = string_literal(&chars, start)?;
+            pos = end;
+            TokenKind::String(string)
+        } else if c.is_alphabetic() || c == '_' {
+            while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            TokenKind::Identifier(chars[start..pos].iter().collect())
+        } else {
+            let next = chars.get(pos + 1).copied();
+            let (kind, width) = match (c, next) {
+                ('=', Some('=')) => (TokenKind::Equal, 2),
+                ('!', Some('=')) => (TokenKind::NotEqual, 2),
+                ('<', Some('=')) => (TokenKind::LessEqual, 2),
+                ('>', Some('=')) => (TokenKind::GreaterEqual, 2),
+                ('&', Some('&')) => (TokenKind::And, 2),
+                ('|', Some('|')) => (TokenKind::Or, 2),
+                ('!', _) => (TokenKind::Not, 1),
+                ('<', _) => (TokenKind::Less, 1),
+                ('>', _) => (TokenKind::Greater, 1),
+                ('(', _) => (TokenKind::LeftParen, 1),
+                (')', _) => (TokenKind::RightParen, 1),
+                ('[', _) => (TokenKind::LeftBracket, 1),
+                (']', _) => (TokenKind::RightBracket, 1),
+                (',', _) => (TokenKind::Comma, 1),
+                ('.', _) => (TokenKind::Dot, 1),
+                ('+', _) => (TokenKind::Plus, 1),
+                ('-', _) => (TokenKind::Minus, 1),
+                ('*', _) => (TokenKind::Star, 1),
+                ('/', _) => (TokenKind::Slash, 1),
+                ('%', _) => (TokenKind::Percent, 1),
+                ('^', _) => (TokenKind::Caret, 1),
+                ('=', _) => return Err(ConditionError::new("Unexpected '=', use '==' to compare".to_owned(), Span::new(start, start + 1))),
+                ('&', _) => return Err(ConditionError::new("Unexpected '&', use '&&'".to_owned(), Span::new(start, start + 1))),
+                ('|', _) => return Err(ConditionError::new("Unexpected '|', use '||'".to_owned(), Span::new(start, start + 1))),
+                _ => return Err(ConditionError::new(format!("Unexpected character '{}'", c), Span::new(start, start + 1)))
+            };
+            pos += width;
+            kind
+        };
+
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, pos)
+        });
+    }
+
+    Ok(tokens)
+}
+
+// Reads a string literal opening at `start`, which may be quoted with either
+// kind of quote. Returns the unescaped string and the position just past the
+// closing quote.
+fn string_literal(chars: &[char], start: usize) -> Result<(String, usize), ConditionError> {
+    let quote = chars[start];
+    let mut string = String::new();
+    let mut pos = start + 1;
+
+    loop {
+        match chars.get(pos) {
+            None => return Err(ConditionError::new("Unterminated string".to_owned(), Span::new(start, pos))),
+            Some(c) if *c == quote => return Ok((string, pos + 1)),
+            Some('\\') => {
+                let escaped = match chars.get(pos + 1) {
+                    Some('"') => '"',
+                    Some('\'') => '\'',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => {
+                        let digits: String = chars.iter().skip(pos + 2).take(4).collect();
+                        let code = if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_hexdigit()) { u32::from_str_radix(&digits, 16).ok() } else { None };
+
+                        match code.and_then(std::char::from_u32) {
+                            Some(c) => {
+                                string.push(c);
+                                pos += 6;
+                                continue;
+                            },
+                            None => return Err(ConditionError::new("Invalid unicode escape, expected \\u followed by 4 hex digits".to_owned(), Span::new(pos, (pos + 6).min(chars.len()))))
+                        }
+                    },
+                    Some(other) => return Err(ConditionError::new(format!("Unknown escape '\\{}'", other), Span::new(pos, pos + 2))),
+                    None => return Err(ConditionError::new("Unterminated string".to_owned(), Span::new(start, pos + 1)))
+                };
+                string.push(escaped);
+                pos += 2;
+            },
+            Some(c) => {
+                string.push(*c);
+                pos += 1;
+            }
+        }
+    }
+}
@@ -1,95 +1,768 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 
-use crate::models::ApiProjectConfigResponseResult;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
 
-use evalexpr::*;
-use rocket::http::ext::IntoCollection;
+use crate::models::{ApiConditionDiagnostic, ApiConditionExplanation, ApiConditionKey, ApiConditionValidation, ApiConditionValue, ApiFullComputedNodeData, ApiFullComputedTreeData, ApiFullNodeData, ApiNodeIssue, ApiPruning, ModelAttribute, PruneReason};
 
-// Conditions may contain references to json object lookups. This should always
-// be in the form:
+pub mod lexer;
+pub mod parser;
+pub mod usage;
+
+pub use parser::{parse, BinaryOperator, Expression, ExpressionKind, Function, NodeProperty, NodeTarget, PathSegment, UnaryOperator};
+
+// Conditions decide whether a node applies under a config. They're written as
 //
 // config["<key>"]...["<keyN>"] <OP> ...
 //
-// We want this converted to treat each lookup item as a single variable e.g.
-// config_key_..._keyN <OP> ...
-pub fn de_json_condition(condition: &str) -> String {
-    let mut alerted_string = "".to_owned();
-
-    let mut in_lookup_key = false;
-    for c in condition.chars() {
-        if c == '[' && in_lookup_key == false {
-            in_lookup_key = true;
-            alerted_string += "_";
-        } else if c == ']' && in_lookup_key == true {
-            in_lookup_key = false;
-        } else if in_lookup_key == true && (c == '"' || c == '\'') {
-            // Do nothing - skip quote characters
-        } else {
-            alerted_string.push(c);
+// and parsed into an Expression that is evaluated directly against the config's
+// JSON attributes, so keys may contain any character and nested objects are
+// never flattened into a single variable name.
+//
+// Reading a key the config doesn't set is an error, which leaves the node
+// unresolved and shows up in validation and explanations. Wrap the lookup in
+// default(config["key"], <fallback>) to give it a value instead.
+
+// A range of character positions within a condition, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionError {
+    pub message: String,
+    pub span: Span,
+    // Raised by a lookup of a key or position the config doesn't have, which
+    // default() falls back on
+    pub missing_key: bool
+}
+
+impl ConditionError {
+    pub fn new(message: String, span: Span) -> ConditionError {
+        ConditionError { message, span, missing_key: false }
+    }
+
+    pub fn diagnostic(&self) -> ApiConditionDiagnostic {
+        ApiConditionDiagnostic {
+            message: self.message.clone(),
+            start: self.span.start,
+            end: self.span.end
         }
     }
+}
 
-    alerted_string
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.span.start)
+    }
 }
 
-pub fn get_key_vals(obj: &serde_json::map::Map<String, serde_json::value::Value>) -> HashMap<String, serde_json::value::Value> {
-    let mut result = HashMap::new();
-    for (key, val) in obj.into_iter() {
-        if val.is_object() {
-            // Recurse
-            let sub_obj = val.as_object().expect("Asserted");
-            let sub_key_vals = get_key_vals(sub_obj);
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object"
+    }
+}
 
-            for (sub_key, sub_val) in sub_key_vals {
-                result.insert(key.clone() + "_" + &sub_key, sub_val);
-            }
+// Numbers compare by value, so 150 and 150.0 are equal, including inside
+// arrays and objects
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64() == right.as_f64(),
+        (Value::Array(left), Value::Array(right)) => left.len() == right.len()
+            && left.iter().zip(right).all(|(left, right)| values_equal(left, right)),
+        (Value::Object(left), Value::Object(right)) => left.len() == right.len()
+            && left.iter().all(|(key, value)| right.get(key).map_or(false, |other| values_equal(value, other))),
+        _ => left == right
+    }
+}
 
-        } else if val.is_number() || val.is_string() || val.is_boolean() {
-            result.insert(key.clone(), val.clone());
+// Keeps whole number results as integers so they print the way they were written
+fn number_value(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::from(value as i64)
+    } else {
+        serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LookupError {
+    // The path runs past the end of the config
+    Missing(String),
+    // The path indexes into a value that isn't an object or array
+    WrongType(String)
+}
+
+impl LookupError {
+    fn into_condition_error(self, span: Span) -> ConditionError {
+        match self {
+            LookupError::Missing(message) => ConditionError {
+                message,
+                span,
+                missing_key: true
+            },
+            LookupError::WrongType(message) => ConditionError::new(message, span)
         }
     }
+}
+
+// Follows a config path through the config's attributes
+pub fn lookup<'a>(attributes: &'a Value, path: &[PathSegment]) -> Result<&'a Value, LookupError> {
+    let mut current = attributes;
+
+    for (depth, segment) in path.iter().enumerate() {
+        let parent = parser::format_config_path(&path[..depth]);
+
+        current = match (segment, current) {
+            (PathSegment::Key(key), Value::Object(object)) => match object.get(key) {
+                Some(value) => value,
+                None => return Err(LookupError::Missing(format!("{} is not set", parser::format_config_path(&path[..=depth]))))
+            },
+            (PathSegment::Index(index), Value::Array(array)) => match array.get(*index) {
+                Some(value) => value,
+                None => return Err(LookupError::Missing(format!("{} is out of range, the array has {} items", parser::format_config_path(&path[..=depth]), array.len())))
+            },
+            (PathSegment::Key(_), other) => return Err(LookupError::WrongType(format!("{} is a {}, not an object", parent, type_name(other)))),
+            (PathSegment::Index(_), other) => return Err(LookupError::WrongType(format!("{} is a {}, not an array", parent, type_name(other))))
+        };
+    }
+
+    Ok(current)
+}
 
-    result
+// What a tree condition can see of a node while the tree's conditions are
+// being resolved
+pub struct NodeState {
+    pub modelAttributes: HashMap<String, ModelAttribute>,
+    // None until the node's own condition has been resolved
+    pub resolved: Option<bool>
 }
 
-pub fn evaluate(condition: &str, config: &ApiProjectConfigResponseResult) -> bool {
-    // Empty conditions always resolve
-    if condition.to_owned().len() == 0 {
-        return true;
+// Everything a condition can read: the config and, for conditions on tree
+// nodes, the tree's nodes and the node the condition belongs to
+pub struct Scope<'a> {
+    pub config: &'a Value,
+    pub nodes: Option<&'a HashMap<String, NodeState>>,
+    pub node_id: Option<&'a str>
+}
+
+impl<'a> Scope<'a> {
+    // A scope with only config values, where node references can't resolve
+    pub fn config(config: &'a Value) -> Scope<'a> {
+        Scope {
+            config,
+            nodes: None,
+            node_id: None
+        }
+    }
+
+    fn node_value(&self, target: &NodeTarget, property: &NodeProperty) -> Result<Value, LookupError> {
+        let reference = parser::format_node_reference(target, property);
+        let (nodes, node_id) = match (self.nodes, target) {
+            (Some(nodes), NodeTarget::Id(id)) => (nodes, id.as_str()),
+            (Some(nodes), NodeTarget::Current) => match self.node_id {
+                Some(node_id) => (nodes, node_id),
+                None => return Err(LookupError::WrongType(format!("{} only works in a node's condition", reference)))
+            },
+            (None, _) => return Err(LookupError::WrongType(format!("{} only works in a node's condition", reference)))
+        };
+
+        let node = match nodes.get(node_id) {
+            Some(node) => node,
+            None => return Err(LookupError::Missing(format!("There is no node {:?} in this tree", node_id)))
+        };
+
+        match property {
+            NodeProperty::Resolved => match node.resolved {
+                Some(resolved) => Ok(Value::Bool(resolved)),
+                None => Err(LookupError::WrongType(format!("{} isn't known yet, conditions may not depend on themselves", reference)))
+            },
+            NodeProperty::ModelAttribute(key) => match node.modelAttributes.get(key) {
+                Some(attribute) => Ok(attribute_value(attribute)),
+                None => Err(LookupError::Missing(format!("{} is not set", reference)))
+            }
+        }
     }
+}
 
-    let normalized_condition = de_json_condition(condition);
-    println!("Evaluate {}", normalized_condition);
-    let mut context = HashMapContext::new();
+fn attribute_value(attribute: &ModelAttribute) -> Value {
+    if let Some(value) = attribute.value_float {
+        number_value(value)
+    } else if let Some(value) = attribute.value_int {
+        Value::from(value)
+    } else {
+        attribute.value_string.clone().map_or(Value::Null, Value::String)
+    }
+}
 
-    // Generate context map
-    match config.attributes.as_object() {
-        Some(obj) => {
-            let key_vals = get_key_vals(obj);
+impl Expression {
+    pub fn evaluate(&self, scope: &Scope) -> Result<Value, ConditionError> {
+        match &self.kind {
+            ExpressionKind::Literal(value) => Ok(value.clone()),
+            ExpressionKind::Config(path) => lookup(scope.config, path)
+                .map(|value| value.clone())
+                .map_err(|err| err.into_condition_error(self.span)),
+            ExpressionKind::Node(target, property) => scope.node_value(target, property)
+                .map_err(|err| err.into_condition_error(self.span)),
+            ExpressionKind::Array(items) => items.iter()
+                .map(|item| item.evaluate(scope))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            ExpressionKind::Call(function, arguments) => self.evaluate_call(*function, arguments, scope),
+            ExpressionKind::Unary(operator, operand) => {
+                let value = operand.evaluate(scope)?;
 
-            for (key, val) in key_vals {
-                if val.is_number() {
-                    context.set_value("config_".to_owned() + &key, val.as_f64().expect("Asserted").into()).expect("To always work");
-                } else if val.is_string() {
-                    context.set_value("config_".to_owned() + &key, val.as_str().expect("Asserted").into()).expect("To always work");
-                } else if val.is_boolean() {
-                    context.set_value("config_".to_owned() + &key, val.as_bool().expect("Asserted").into()).expect("To always work");
+                match (operator, &value) {
+                    (UnaryOperator::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+                    (UnaryOperator::Negate, Value::Number(number)) => Ok(number_value(-number.as_f64().unwrap_or(0.0))),
+                    (UnaryOperator::Not, other) => Err(ConditionError::new(format!("'!' needs a boolean but got a {}", type_name(other)), self.span)),
+                    (UnaryOperator::Negate, other) => Err(ConditionError::new(format!("'-' needs a number but got a {}", type_name(other)), self.span))
                 }
+            },
+            ExpressionKind::Binary(operator, left, right) => self.evaluate_binary(*operator, left, right, scope)
+        }
+    }
+
+    // Evaluates the expression as a condition, which must come out as a boolean
+    pub fn resolve(&self, scope: &Scope) -> Result<bool, ConditionError> {
+        match self.evaluate(scope)? {
+            Value::Bool(value) => Ok(value),
+            other => Err(ConditionError::new(format!("Conditions must be true or false, but this one is a {}", type_name(&other)), self.span))
+        }
+    }
+
+    fn evaluate_call(&self, function: Function, arguments: &[Expression], scope: &Scope) -> Result<Value, ConditionError> {
+        match function {
+            Function::Len => match arguments[0].evaluate(scope)? {
+                Value::String(string) => Ok(Value::from(string.chars().count())),
+                Value::Array(array) => Ok(Value::from(array.len())),
+                Value::Object(object) => Ok(Value::from(object.len())),
+                other => Err(ConditionError::new(format!("len() needs a string, array or object but got a {}", type_name(&other)), arguments[0].span))
+            },
+            Function::Default => match arguments[0].evaluate(scope) {
+                Ok(Value::Null) => arguments[1].evaluate(scope),
+                Err(err) if err.missing_key => arguments[1].evaluate(scope),
+                result => result
             }
+        }
+    }
 
-        },
-        None => {
-            eprintln!("Config wasn't an object!");
+    fn evaluate_binary(&self, operator: BinaryOperator, left: &Expression, right: &Expression, scope: &Scope) -> Result<Value, ConditionError> {
+        let boolean = |expression: &Expression| match expression.evaluate(scope)? {
+            Value::Bool(value) => Ok(value),
+            other => Err(ConditionError::new(format!("'{}' needs booleans but got a {}", operator.symbol(), type_name(&other)), expression.span))
+        };
+
+        // && and || short-circuit, so the right side is only checked when needed
+        match operator {
+            BinaryOperator::And => return Ok(Value::Bool(boolean(left)? && boolean(right)?)),
+            BinaryOperator::Or => return Ok(Value::Bool(boolean(left)? || boolean(right)?)),
+            _ => {}
+        }
+
+        let left_value = left.evaluate(scope)?;
+        let right_value = right.evaluate(scope)?;
+        let mismatch = || ConditionError::new(
+            format!("Cannot apply '{}' to a {} and a {}", operator.symbol(), type_name(&left_value), type_name(&right_value)),
+            self.span
+        );
+
+        match operator {
+            BinaryOperator::Equal => Ok(Value::Bool(values_equal(&left_value, &right_value))),
+            BinaryOperator::NotEqual => Ok(Value::Bool(!values_equal(&left_value, &right_value))),
+            BinaryOperator::In => match (&left_value, &right_value) {
+                (item, Value::Array(array)) => Ok(Value::Bool(array.iter().any(|other| values_equal(item, other)))),
+                (Value::String(part), Value::String(string)) => Ok(Value::Bool(string.contains(part.as_str()))),
+                (Value::String(key), Value::Object(object)) => Ok(Value::Bool(object.contains_key(key))),
+                _ => Err(mismatch())
+            },
+            BinaryOperator::Less | BinaryOperator::LessEqual | BinaryOperator::Greater | BinaryOperator::GreaterEqual => {
+                let ordering = match (&left_value, &right_value) {
+                    (Value::Number(left), Value::Number(right)) => left.as_f64().partial_cmp(&right.as_f64()),
+                    (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+                    _ => None
+                }.ok_or_else(mismatch)?;
+
+                Ok(Value::Bool(match operator {
+                    BinaryOperator::Less => ordering.is_lt(),
+                    BinaryOperator::LessEqual => ordering.is_le(),
+                    BinaryOperator::Greater => ordering.is_gt(),
+                    _ => ordering.is_ge()
+                }))
+            },
+            BinaryOperator::Add => match (&left_value, &right_value) {
+                (Value::String(left), Value::String(right)) => Ok(Value::String(left.clone() + right)),
+                (Value::Number(left), Value::Number(right)) => Ok(number_value(left.as_f64().unwrap_or(0.0) + right.as_f64().unwrap_or(0.0))),
+                _ => Err(mismatch())
+            },
+            _ => {
+                let (left, right) = match (&left_value, &right_value) {
+                    (Value::Number(left), Value::Number(right)) => (left.as_f64().unwrap_or(0.0), right.as_f64().unwrap_or(0.0)),
+                    _ => return Err(mismatch())
+                };
+
+                if (operator == BinaryOperator::Divide || operator == BinaryOperator::Modulo) && right == 0.0 {
+                    return Err(ConditionError::new("Division by zero".to_owned(), self.span));
+                }
+
+                Ok(number_value(match operator {
+                    BinaryOperator::Subtract => left - right,
+                    BinaryOperator::Multiply => left * right,
+                    BinaryOperator::Divide => left / right,
+                    BinaryOperator::Modulo => left % right,
+                    _ => left.powf(right)
+                }))
+            }
         }
     }
+}
 
-    match eval_boolean_with_context(&normalized_condition, &context) {
-        Ok(res) => {
-            res
+// Checks a condition without needing a tree: syntax errors with their
+// positions, the config keys it reads and, when config attributes are given,
+// which of those keys exist and what the condition resolves to
+pub fn validate(condition: &str, attributes: Option<&Value>) -> ApiConditionValidation {
+    if condition.trim().is_empty() {
+        return ApiConditionValidation {
+            valid: true,
+            normalized: Some("".to_owned()),
+            errors: vec![],
+            keys: vec![],
+            resolved: attributes.map(|_| true),
+            evaluationError: None
+        };
+    }
+
+    let expression = match parse(condition) {
+        Ok(expression) => expression,
+        Err(err) => return ApiConditionValidation {
+            valid: false,
+            normalized: None,
+            errors: vec![err.diagnostic()],
+            keys: vec![],
+            resolved: None,
+            evaluationError: None
+        }
+    };
+
+    let keys = expression.config_paths().iter()
+        .map(|path| ApiConditionKey {
+            key: parser::format_config_path(path),
+            path: path_values(path),
+            exists: attributes.map(|attributes| lookup(attributes, path).is_ok())
+        })
+        .collect();
+
+    let (resolved, evaluation_error) = match attributes {
+        Some(attributes) => match expression.resolve(&Scope::config(attributes)) {
+            Ok(value) => (Some(value), None),
+            Err(err) => (None, Some(err.diagnostic()))
         },
+        None => (None, None)
+    };
+
+    ApiConditionValidation {
+        valid: true,
+        normalized: Some(expression.to_string()),
+        errors: vec![],
+        keys,
+        resolved,
+        evaluationError: evaluation_error
+    }
+}
+
+// Object keys as strings and array positions as numbers
+fn path_values(path: &[PathSegment]) -> Vec<Value> {
+    path.iter()
+        .map(|segment| match segment {
+            PathSegment::Key(key) => Value::from(key.clone()),
+            PathSegment::Index(index) => Value::from(*index)
+        })
+        .collect()
+}
+
+// None for an empty condition, which always resolves
+fn parse_condition(condition: &str) -> Option<Result<Expression, ConditionError>> {
+    if condition.trim().is_empty() {
+        None
+    } else {
+        Some(parse(condition))
+    }
+}
+
+// The nodes whose resolution each node's condition reads
+fn resolved_dependencies(parsed: &[(String, Option<Result<Expression, ConditionError>>)]) -> HashMap<String, Vec<String>> {
+    parsed.iter()
+        .map(|(node_id, expression)| {
+            let dependencies = match expression {
+                Some(Ok(expression)) => expression.node_references().into_iter()
+                    .filter(|(_, property)| *property == NodeProperty::Resolved)
+                    .map(|(target, _)| match target {
+                        NodeTarget::Current => node_id.clone(),
+                        NodeTarget::Id(id) => id
+                    })
+                    .collect(),
+                _ => vec![]
+            };
+            (node_id.clone(), dependencies)
+        })
+        .collect()
+}
+
+// Finds the nodes whose condition ends up depending on its own resolution,
+// along with one chain of nodes leading from each back to itself
+pub fn condition_cycles(dependencies: &HashMap<String, Vec<String>>) -> HashMap<String, Vec<String>> {
+    let mut cycles = HashMap::new();
+
+    for node_id in dependencies.keys() {
+        let mut previous: HashMap<&String, &String> = HashMap::new();
+        let mut queue: VecDeque<&String> = VecDeque::new();
+        queue.push_back(node_id);
+
+        while let Some(current) = queue.pop_front() {
+            let next_ids = dependencies.get(current).map(|ids| ids.as_slice()).unwrap_or(&[]);
+
+            if next_ids.contains(node_id) {
+                let mut steps = Vec::new();
+                let mut step = current;
+                while step != node_id {
+                    steps.push(step.clone());
+                    step = previous[step];
+                }
+                steps.reverse();
+
+                let cycle = std::iter::once(node_id.clone())
+                    .chain(steps)
+                    .chain(std::iter::once(node_id.clone()))
+                    .collect();
+                cycles.insert(node_id.clone(), cycle);
+                break;
+            }
+
+            for next_id in next_ids {
+                if next_id != node_id && !previous.contains_key(next_id) {
+                    previous.insert(next_id, current);
+                    queue.push_back(next_id);
+                }
+            }
+        }
+    }
+
+    cycles
+}
+
+fn cycle_message(cycle: &[String]) -> String {
+    format!("Condition depends on its own resolution through {}", cycle.join(" -> "))
+}
+
+fn tree_parents<'a>(children: impl Iterator<Item = (&'a String, &'a Vec<String>)>) -> HashMap<String, Vec<String>> {
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    for (node_id, node_children) in children {
+        for child in node_children {
+            parents.entry(child.clone()).or_insert_with(Vec::new).push(node_id.clone());
+        }
+    }
+    parents
+}
+
+// Whether a node is still part of the tree is only known once its parents
+// are, so each node depends on its parents as well as on the nodes its
+// condition reads. Returns those dependencies with the cycles that run through
+// at least one condition; loops made of children alone aren't a condition's
+// doing.
+fn tree_dependencies(parsed: &[(String, Option<Result<Expression, ConditionError>>)], parents: &HashMap<String, Vec<String>>) -> (HashMap<String, Vec<String>>, HashMap<String, Vec<String>>) {
+    let references = resolved_dependencies(parsed);
+    let mut dependencies = references.clone();
+    for (node_id, node_dependencies) in dependencies.iter_mut() {
+        node_dependencies.extend(parents.get(node_id).cloned().unwrap_or_default());
+    }
+
+    let cycles = condition_cycles(&dependencies).into_iter()
+        .filter(|(_, cycle)| cycle.windows(2).any(|pair| references.get(&pair[0]).map_or(false, |ids| ids.contains(&pair[1]))))
+        .collect();
+
+    (dependencies, cycles)
+}
+
+// Resolves every node's condition against the config. Nodes are resolved after
+// their parents and the nodes their conditions read with node["id"].resolved,
+// and conditions that depend on themselves don't resolve. Conditions see
+// node["id"].resolved as whether that node is still part of the tree: it is the
+// root or under a parent that is, and its own condition resolved.
+pub fn resolve_tree_conditions(nodes: &mut [ApiFullComputedNodeData], root_node_id: &str, config: &Value) {
+    let parsed: Vec<(String, Option<Result<Expression, ConditionError>>)> = nodes.iter()
+        .map(|node| (node.id.clone(), parse_condition(&node.conditionAttribute)))
+        .collect();
+    let parents = tree_parents(nodes.iter().map(|node| (&node.id, &node.children)));
+    let (dependencies, cycles) = tree_dependencies(&parsed, &parents);
+    let mut own_resolved: HashMap<&String, bool> = HashMap::new();
+
+    let mut states: HashMap<String, NodeState> = nodes.iter()
+        .map(|node| (node.id.clone(), NodeState {
+            modelAttributes: node.modelAttributes.clone(),
+            resolved: if cycles.contains_key(&node.id) { Some(false) } else { None }
+        }))
+        .collect();
+
+    // Depth first, so every node comes after the nodes it depends on. Cycles
+    // are already settled, which keeps the walk from looping.
+    let mut order: Vec<&String> = Vec::new();
+    let mut visited: HashSet<&String> = HashSet::new();
+    fn visit<'a>(node_id: &'a String, dependencies: &'a HashMap<String, Vec<String>>, cycles: &HashMap<String, Vec<String>>, visited: &mut HashSet<&'a String>, order: &mut Vec<&'a String>) {
+        if cycles.contains_key(node_id) || !visited.insert(node_id) {
+            return;
+        }
+        for dependency in dependencies.get(node_id).into_iter().flatten() {
+            visit(dependency, dependencies, cycles, visited, order);
+        }
+        order.push(node_id);
+    }
+    for (node_id, _) in &parsed {
+        visit(node_id, &dependencies, &cycles, &mut visited, &mut order);
+    }
+
+    let expressions: HashMap<&String, &Option<Result<Expression, ConditionError>>> = parsed.iter()
+        .map(|(node_id, expression)| (node_id, expression))
+        .collect();
+
+    for node_id in order {
+        let expression = match expressions.get(node_id) {
+            Some(expression) => expression,
+            // Referenced, but not a node of this tree
+            None => continue
+        };
+
+        let resolved = match expression {
+            None => true,
+            Some(Ok(expression)) => {
+                let scope = Scope {
+                    config,
+                    nodes: Some(&states),
+                    node_id: Some(node_id)
+                };
+
+                match expression.resolve(&scope) {
+                    Ok(resolved) => resolved,
+                    Err(err) => {
+                        eprintln!("Condition error on node {}: {}", node_id, err);
+                        false
+                    }
+                }
+            },
+            Some(Err(err)) => {
+                eprintln!("Condition error on node {}: {}", node_id, err);
+                false
+            }
+        };
+
+        let reachable = node_id == root_node_id || parents.get(node_id).map_or(false, |node_parents| {
+            node_parents.iter().any(|parent| states.get(parent).and_then(|state| state.resolved) == Some(true))
+        });
+
+        own_resolved.insert(node_id, resolved);
+        if let Some(state) = states.get_mut(node_id) {
+            state.resolved = Some(resolved && reachable);
+        }
+    }
+
+    // The rollups prune below unresolved nodes themselves, so nodes report
+    // their own condition
+    for node in nodes.iter_mut() {
+        node.conditionResolved = own_resolved.get(&node.id).copied().unwrap_or(false);
+    }
+}
+
+// Conditions that don't parse, refer to nodes that aren't in the tree or
+// depend on their own resolution never resolve, so tree PUT warns about them
+pub fn validate_tree_conditions(nodes: &[ApiFullNodeData]) -> Vec<ApiNodeIssue> {
+    let parsed: Vec<(String, Option<Result<Expression, ConditionError>>)> = nodes.iter()
+        .map(|node| (node.id.clone(), parse_condition(&node.conditionAttribute)))
+        .collect();
+    let parents = tree_parents(nodes.iter().map(|node| (&node.id, &node.children)));
+    let (_, cycles) = tree_dependencies(&parsed, &parents);
+    let node_ids: HashSet<&String> = nodes.iter().map(|node| &node.id).collect();
+
+    let mut issues = Vec::new();
+    for (node_id, expression) in &parsed {
+        let issue = |message: String| ApiNodeIssue {
+            nodeId: node_id.clone(),
+            field: "conditionAttribute".to_owned(),
+            message
+        };
+
+        match expression {
+            Some(Err(err)) => issues.push(issue(err.to_string())),
+            Some(Ok(expression)) => {
+                for (target, _) in expression.node_references() {
+                    if let NodeTarget::Id(id) = target {
+                        if !node_ids.contains(&id) {
+                            issues.push(issue(format!("There is no node {:?} in this tree", id)));
+                        }
+                    }
+                }
+
+                if let Some(cycle) = cycles.get(node_id) {
+                    issues.push(issue(cycle_message(cycle)));
+                }
+            },
+            None => {}
+        }
+    }
+
+    issues
+}
+
+// Describes how a condition evaluates in a scope, or without any scope when no
+// config could be loaded
+pub fn explain(condition: &str, scope: Option<&Scope>) -> ApiConditionExplanation {
+    let mut explanation = ApiConditionExplanation {
+        normalized: None,
+        values: vec![],
+        missingKeys: vec![],
+        error: None,
+        prunedBy: None
+    };
+
+    if condition.trim().is_empty() {
+        explanation.normalized = Some("".to_owned());
+        return explanation;
+    }
+
+    let expression = match parse(condition) {
+        Ok(expression) => expression,
         Err(err) => {
-            eprintln!("Eval error");
-            return false;
+            explanation.error = Some(err.diagnostic());
+            return explanation;
+        }
+    };
+    explanation.normalized = Some(expression.to_string());
+
+    let scope = match scope {
+        Some(scope) => scope,
+        None => {
+            explanation.error = Some(ConditionError::new("No config could be loaded to evaluate against".to_owned(), expression.span).diagnostic());
+            return explanation;
+        }
+    };
+
+    for path in expression.config_paths() {
+        match lookup(scope.config, &path) {
+            Ok(value) => explanation.values.push(ApiConditionValue {
+                key: parser::format_config_path(&path),
+                value: value.clone()
+            }),
+            Err(_) => explanation.missingKeys.push(parser::format_config_path(&path))
+        }
+    }
+
+    for (target, property) in expression.node_references() {
+        match scope.node_value(&target, &property) {
+            Ok(value) => explanation.values.push(ApiConditionValue {
+                key: parser::format_node_reference(&target, &property),
+                value
+            }),
+            Err(LookupError::Missing(_)) => explanation.missingKeys.push(parser::format_node_reference(&target, &property)),
+            Err(LookupError::WrongType(_)) => {}
+        }
+    }
+
+    if let Err(err) = expression.resolve(scope) {
+        explanation.error = Some(err.diagnostic());
+    }
+
+    explanation
+}
+
+// Explains every node's condition and works out what pruned the nodes that
+// drop out of the tree: their own condition, or the closest ancestor whose
+// condition failed when no path from the root reaches them through resolved
+// nodes
+pub fn explain_tree(tree: &mut ApiFullComputedTreeData, attributes: Option<&Value>) {
+    let resolved: HashMap<String, bool> = tree.nodes.iter().map(|node| (node.id.clone(), node.conditionResolved)).collect();
+    let parsed: Vec<(String, Option<Result<Expression, ConditionError>>)> = tree.nodes.iter()
+        .map(|node| (node.id.clone(), parse_condition(&node.conditionAttribute)))
+        .collect();
+    let parents = tree_parents(tree.nodes.iter().map(|node| (&node.id, &node.children)));
+    let (_, cycles) = tree_dependencies(&parsed, &parents);
+
+    // Nodes the model rollup visits
+    let mut active = HashSet::new();
+    let mut pending = vec![tree.rootNodeId.clone()];
+    while let Some(node_id) = pending.pop() {
+        if !resolved.get(&node_id).copied().unwrap_or(false) || !active.insert(node_id.clone()) {
+            continue;
+        }
+        if let Some(node) = tree.nodes.iter().find(|node| node.id == node_id) {
+            pending.extend(node.children.iter().cloned());
         }
     }
-}
\ No newline at end of file
+
+    // Conditions read whether other nodes are still active, as they did when
+    // the tree was resolved
+    let states: HashMap<String, NodeState> = tree.nodes.iter()
+        .map(|node| (node.id.clone(), NodeState {
+            modelAttributes: node.modelAttributes.clone(),
+            resolved: Some(active.contains(&node.id))
+        }))
+        .collect();
+
+    let closest_unresolved_ancestor = |node_id: &String| {
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<String> = parents.get(node_id).cloned().unwrap_or_default().into();
+
+        while let Some(parent_id) = queue.pop_front() {
+            if !seen.insert(parent_id.clone()) {
+                continue;
+            }
+            if resolved.get(&parent_id) == Some(&false) {
+                return Some(parent_id);
+            }
+            queue.extend(parents.get(&parent_id).cloned().unwrap_or_default());
+        }
+
+        None
+    };
+
+    for node in tree.nodes.iter_mut() {
+        let scope = attributes.map(|config| Scope {
+            config,
+            nodes: Some(&states),
+            node_id: Some(&node.id)
+        });
+        let mut explanation = explain(&node.conditionAttribute, scope.as_ref());
+
+        if let Some(cycle) = cycles.get(&node.id) {
+            let end = node.conditionAttribute.chars().count();
+            explanation.error = Some(ConditionError::new(cycle_message(cycle), Span::new(0, end)).diagnostic());
+        }
+
+        explanation.prunedBy = if !node.conditionResolved {
+            Some(ApiPruning {
+                reason: PruneReason::Condition,
+                nodeId: node.id.clone()
+            })
+        } else if !active.contains(&node.id) {
+            closest_unresolved_ancestor(&node.id).map(|ancestor_id| ApiPruning {
+                reason: PruneReason::Ancestor,
+                nodeId: ancestor_id
+            })
+        } else {
+            None
+        };
+
+        node.explanation = Some(explanation);
+    }
+}
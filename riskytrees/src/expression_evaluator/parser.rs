@@ -0,0 +1,565 @@
+use std::fmt;
+
+use super::{ConditionError, Span};
+use super::lexer::{self, Token, TokenKind};
+
+// One step of a config lookup, either an object key or an array position
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    In,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power
+}
+
+impl BinaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Or => "||",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::In => "in",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Power => "^"
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual
+                | BinaryOperator::Less | BinaryOperator::LessEqual
+                | BinaryOperator::Greater | BinaryOperator::GreaterEqual
+                | BinaryOperator::In => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 5,
+            BinaryOperator::Power => 6
+        }
+    }
+
+    fn from_token(kind: &TokenKind) -> Option<BinaryOperator> {
+        match kind {
+            TokenKind::Or => Some(BinaryOperator::Or),
+            TokenKind::And => Some(BinaryOperator::And),
+            TokenKind::Equal => Some(BinaryOperator::Equal),
+            TokenKind::NotEqual => Some(BinaryOperator::NotEqual),
+            TokenKind::Less => Some(BinaryOperator::Less),
+            TokenKind::LessEqual => Some(BinaryOperator::LessEqual),
+            TokenKind::Greater => Some(BinaryOperator::Greater),
+            TokenKind::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+            TokenKind::Plus => Some(BinaryOperator::Add),
+            TokenKind::Minus => Some(BinaryOperator::Subtract),
+            TokenKind::Star => Some(BinaryOperator::Multiply),
+            TokenKind::Slash => Some(BinaryOperator::Divide),
+            TokenKind::Percent => Some(BinaryOperator::Modulo),
+            TokenKind::Caret => Some(BinaryOperator::Power),
+            TokenKind::Identifier(name) if name == "in" => Some(BinaryOperator::In),
+            _ => None
+        }
+    }
+}
+
+// Binds tighter than any binary operator
+const UNARY_PRECEDENCE: u8 = 7;
+
+// How deeply expressions may nest, both while parsing and in the parsed tree.
+// Everything that handles an Expression recurses, so without a bound a long
+// enough condition would overflow the stack. Each level of parsing takes
+// several KB of stack in debug builds, which keeps this well below what a 2MB
+// worker stack could hold.
+pub const MAX_NESTING: usize = 128;
+
+// The node a node reference reads from
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeTarget {
+    // `self`, the node the condition belongs to
+    Current,
+    // node["<id>"], another node of the same tree
+    Id(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeProperty {
+    // .resolved, whether the node is still part of the tree: its condition and
+    // those of the nodes above it resolved
+    Resolved,
+    // .modelAttributes["<key>"]
+    ModelAttribute(String)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    // Number of characters, items or keys
+    Len,
+    // default(value, fallback) is fallback when value is null or reads a
+    // config key that isn't set
+    Default
+}
+
+impl Function {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Len => "len",
+            Function::Default => "default"
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Len => 1,
+            Function::Default => 2
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "len" => Some(Function::Len),
+            "default" => Some(Function::Default),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
+    Literal(serde_json::Value),
+    // config["a"]["b"]... with the path below config
+    Config(Vec<PathSegment>),
+    Node(NodeTarget, NodeProperty),
+    Array(Vec<Expression>),
+    Call(Function, Vec<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+    // Levels in the tree from here down, a leaf being 1
+    depth: usize
+}
+
+impl Expression {
+    fn new(kind: ExpressionKind, span: Span) -> Expression {
+        let depth = 1 + children(&kind).iter().map(|child| child.depth).max().unwrap_or(0);
+
+        Expression {
+            kind,
+            span,
+            depth
+        }
+    }
+
+    // Visits the expression and everything below it, left to right
+    pub fn walk(&self, visit: &mut dyn FnMut(&Expression)) {
+        visit(self);
+
+        for child in children(&self.kind) {
+            child.walk(visit);
+        }
+    }
+
+    // Every config path the expression reads, in the order they first appear
+    pub fn config_paths(&self) -> Vec<Vec<PathSegment>> {
+        let mut paths = Vec::new();
+        self.walk(&mut |expression| {
+            if let ExpressionKind::Config(path) = &expression.kind {
+                if !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        });
+        paths
+    }
+
+    // Every node reference in the expression, in the order they first appear
+    pub fn node_references(&self) -> Vec<(NodeTarget, NodeProperty)> {
+        let mut references = Vec::new();
+        self.walk(&mut |expression| {
+            if let ExpressionKind::Node(target, property) = &expression.kind {
+                let reference = (target.clone(), property.clone());
+                if !references.contains(&reference) {
+                    references.push(reference);
+                }
+            }
+        });
+        references
+    }
+
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExpressionKind::Binary(operator, _, _) => operator.precedence(),
+            ExpressionKind::Unary(_, _) => UNARY_PRECEDENCE,
+            _ => u8::MAX
+        }
+    }
+}
+
+fn children(kind: &ExpressionKind) -> Vec<&Expression> {
+    match kind {
+        ExpressionKind::Literal(_) | ExpressionKind::Config(_) | ExpressionKind::Node(_, _) => vec![],
+        ExpressionKind::Array(items) | ExpressionKind::Call(_, items) => items.iter().collect(),
+        ExpressionKind::Unary(_, operand) => vec![operand],
+        ExpressionKind::Binary(_, left, right) => vec![left, right]
+    }
+}
+
+// Renders a config path the way it's written in conditions, e.g. config["a"][0]
+pub fn format_config_path(path: &[PathSegment]) -> String {
+    let mut formatted = "config".to_owned();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => formatted += &format!("[{}]", serde_json::Value::String(key.clone())),
+            PathSegment::Index(index) => formatted += &format!("[{}]", index)
+        }
+    }
+    formatted
+}
+
+// Renders a node reference the way it's written in conditions, e.g.
+// node["n1"].resolved
+pub fn format_node_reference(target: &NodeTarget, property: &NodeProperty) -> String {
+    let target = match target {
+        NodeTarget::Current => "self".to_owned(),
+        NodeTarget::Id(id) => format!("node[{}]", serde_json::Value::String(id.clone()))
+    };
+
+    match property {
+        NodeProperty::Resolved => format!("{}.resolved", target),
+        NodeProperty::ModelAttribute(key) => format!("{}.modelAttributes[{}]", target, serde_json::Value::String(key.clone()))
+    }
+}
+
+// The normalized form of an expression: double quoted strings, single spaces
+// around binary operators and only the parentheses precedence requires
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExpressionKind::Literal(value) => write!(f, "{}", value),
+            ExpressionKind::Config(path) => write!(f, "{}", format_config_path(path)),
+            ExpressionKind::Node(target, property) => write!(f, "{}", format_node_reference(target, property)),
+            ExpressionKind::Array(items) => write!(f, "[{}]", join(items)),
+            ExpressionKind::Call(function, arguments) => write!(f, "{}({})", function.name(), join(arguments)),
+            ExpressionKind::Unary(operator, operand) => {
+                let symbol = match operator {
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::Negate => "-"
+                };
+                if operand.precedence() < UNARY_PRECEDENCE {
+                    write!(f, "{}({})", symbol, operand)
+                } else {
+                    write!(f, "{}{}", symbol, operand)
+                }
+            },
+            ExpressionKind::Binary(operator, left, right) => {
+                // Power groups to the right, everything else to the left
+                let right_associative = *operator == BinaryOperator::Power;
+                let wrap_left = left.precedence() < operator.precedence()
+                    || (right_associative && left.precedence() == operator.precedence());
+                let wrap_right = right.precedence() < operator.precedence()
+                    || (!right_associative && right.precedence() == operator.precedence());
+
+                if wrap_left {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, " {} ", operator.symbol())?;
+                if wrap_right {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+fn join(expressions: &[Expression]) -> String {
+    expressions.iter().map(|expression| expression.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn parse(condition: &str) -> Result<Expression, ConditionError> {
+    let tokens = lexer::tokenize(condition)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: condition.chars().count(),
+        depth: 0
+    };
+
+    let expression = parser.expression(0)?;
+    match parser.peek() {
+        Some(token) => Err(ConditionError::new(format!("Unexpected {} after the end of the expression", token.kind.describe()), token.span)),
+        None => Ok(expression)
+    }
+}
+
+fn too_deep(span: Span) -> ConditionError {
+    ConditionError::new(format!("Condition nests more than {} levels deep", MAX_NESTING), span)
+}
+
+fn check_depth(expression: Expression) -> Result<Expression, ConditionError> {
+    if expression.depth > MAX_NESTING {
+        Err(too_deep(expression.span))
+    } else {
+        Ok(expression)
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // Length of the condition, where errors about missing input point
+    end: usize,
+    // How many expressions are being parsed inside each other
+    depth: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind, context: &str) -> Result<Token, ConditionError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(token),
+            Some(token) => Err(ConditionError::new(format!("Expected {} {} but found {}", kind.describe(), context, token.kind.describe()), token.span)),
+            None => Err(ConditionError::new(format!("Expected {} {} but the condition ended", kind.describe(), context), Span::new(self.end, self.end)))
+        }
+    }
+
+    // Runs parse one level deeper, failing once either the parser's recursion
+    // or the parsed tree goes past MAX_NESTING
+    fn nested(&mut self, parse: impl FnOnce(&mut Parser) -> Result<Expression, ConditionError>) -> Result<Expression, ConditionError> {
+        if self.depth >= MAX_NESTING {
+            let span = self.peek().map_or(Span::new(self.end, self.end), |token| token.span);
+            return Err(too_deep(span));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result.and_then(check_depth)
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Expression, ConditionError> {
+        self.nested(|parser| parser.binary(min_precedence))
+    }
+
+    // Precedence climbing over the binary operators
+    fn binary(&mut self, min_precedence: u8) -> Result<Expression, ConditionError> {
+        let mut left = self.unary()?;
+
+        loop {
+            let operator = match self.peek().and_then(|token| BinaryOperator::from_token(&token.kind)) {
+                Some(operator) if operator.precedence() >= min_precedence => operator,
+                _ => break
+            };
+            self.next();
+
+            let next_precedence = if operator == BinaryOperator::Power { operator.precedence() } else { operator.precedence() + 1 };
+            let right = self.expression(next_precedence)?;
+            let span = Span::new(left.span.start, right.span.end);
+
+            // Chains such as 1 + 1 + ... + 1 deepen the tree without recursing
+            left = check_depth(Expression::new(ExpressionKind::Binary(operator, Box::new(left), Box::new(right)), span))?;
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ConditionError> {
+        let operator = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Not) => Some(UnaryOperator::Not),
+            Some(TokenKind::Minus) => Some(UnaryOperator::Negate),
+            _ => None
+        };
+
+        match operator {
+            Some(operator) => {
+                let start = self.next().expect("Peeked").span.start;
+                let operand = self.nested(|parser| parser.unary())?;
+                let span = Span::new(start, operand.span.end);
+
+                Ok(Expression::new(ExpressionKind::Unary(operator, Box::new(operand)), span))
+            },
+            None => self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, ConditionError> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(ConditionError::new("Expected a value but the condition ended".to_owned(), Span::new(self.end, self.end)))
+        };
+
+        match token.kind {
+            TokenKind::Number(number) => Ok(Expression::new(ExpressionKind::Literal(serde_json::Value::Number(number)), token.span)),
+            TokenKind::String(string) => Ok(Expression::new(ExpressionKind::Literal(serde_json::Value::String(string)), token.span)),
+            TokenKind::Identifier(name) => match name.as_str() {
+                "true" | "false" => Ok(Expression::new(ExpressionKind::Literal(serde_json::Value::Bool(name == "true")), token.span)),
+                "null" => Ok(Expression::new(ExpressionKind::Literal(serde_json::Value::Null), token.span)),
+                "config" => self.config_lookup(token.span),
+                "node" => {
+                    self.expect(TokenKind::LeftBracket, "after 'node'")?;
+                    let id = self.quoted("a quoted node id inside []")?;
+                    self.expect(TokenKind::RightBracket, "to close the node id")?;
+                    self.node_property(NodeTarget::Id(id), token.span)
+                },
+                "self" => self.node_property(NodeTarget::Current, token.span),
+                _ => match Function::from_name(&name) {
+                    Some(function) => self.call(function, token.span),
+                    // Conditions used to be rewritten into flattened variable names
+                    None if name.starts_with("config_") => {
+                        let lookup: String = name["config_".len()..].split('_').map(|key| format!("[{}]", serde_json::Value::String(key.to_owned()))).collect();
+                        Err(ConditionError::new(format!("'{}' is the old flattened form of a config lookup, write it as config{} (keys that contain '_' stay whole inside one [])", name, lookup), token.span))
+                    },
+                    None => Err(ConditionError::new(format!("Unknown name '{}', values come from config[\"<key>\"], node[\"<id>\"] or self", name), token.span))
+                }
+            },
+            TokenKind::LeftBracket => {
+                let (items, end) = self.list(TokenKind::RightBracket, "to close the array")?;
+
+                Ok(Expression::new(ExpressionKind::Array(items), Span::new(token.span.start, end)))
+            },
+            TokenKind::LeftParen => {
+                let inner = self.expression(0)?;
+                let close = self.expect(TokenKind::RightParen, "to close '('")?;
+
+                Ok(Expression::new(inner.kind, Span::new(token.span.start, close.span.end)))
+            },
+            other => Err(ConditionError::new(format!("Expected a value but found {}", other.describe()), token.span))
+        }
+    }
+
+    fn quoted(&mut self, expected: &str) -> Result<String, ConditionError> {
+        match self.next() {
+            Some(Token { kind: TokenKind::String(string), .. }) => Ok(string),
+            Some(token) => Err(ConditionError::new(format!("Expected {} but found {}", expected, token.kind.describe()), token.span)),
+            None => Err(ConditionError::new(format!("Expected {} but the condition ended", expected), Span::new(self.end, self.end)))
+        }
+    }
+
+    // The .resolved or .modelAttributes["key"] following a node
+    fn node_property(&mut self, target: NodeTarget, start_span: Span) -> Result<Expression, ConditionError> {
+        self.expect(TokenKind::Dot, "followed by resolved or modelAttributes")?;
+
+        let (property, end) = match self.next() {
+            Some(Token { kind: TokenKind::Identifier(name), span }) if name == "resolved" => (NodeProperty::Resolved, span.end),
+            Some(Token { kind: TokenKind::Identifier(name), .. }) if name == "modelAttributes" => {
+                self.expect(TokenKind::LeftBracket, "after 'modelAttributes'")?;
+                let key = self.quoted("a quoted attribute key inside []")?;
+                let close = self.expect(TokenKind::RightBracket, "to close the attribute key")?;
+                (NodeProperty::ModelAttribute(key), close.span.end)
+            },
+            Some(token) => return Err(ConditionError::new(format!("Expected resolved or modelAttributes but found {}", token.kind.describe()), token.span)),
+            None => return Err(ConditionError::new("Expected resolved or modelAttributes but the condition ended".to_owned(), Span::new(self.end, self.end)))
+        };
+
+        Ok(Expression::new(ExpressionKind::Node(target, property), Span::new(start_span.start, end)))
+    }
+
+    fn call(&mut self, function: Function, name_span: Span) -> Result<Expression, ConditionError> {
+        self.expect(TokenKind::LeftParen, &format!("after '{}'", function.name()))?;
+        let (arguments, end) = self.list(TokenKind::RightParen, "to close the call")?;
+        let span = Span::new(name_span.start, end);
+
+        if arguments.len() != function.arity() {
+            return Err(ConditionError::new(format!("{}() takes {} argument{} but was given {}", function.name(), function.arity(), if function.arity() == 1 { "" } else { "s" }, arguments.len()), span));
+        }
+
+        Ok(Expression::new(ExpressionKind::Call(function, arguments), span))
+    }
+
+    // Comma separated expressions up to and including `close`, which may
+    // directly follow the opening token. Returns them with the end of `close`.
+    fn list(&mut self, close: TokenKind, context: &str) -> Result<(Vec<Expression>, usize), ConditionError> {
+        let mut items = Vec::new();
+
+        if let Some(token) = self.peek().filter(|token| token.kind == close).cloned() {
+            self.next();
+            return Ok((items, token.span.end));
+        }
+
+        loop {
+            items.push(self.expression(0)?);
+
+            match self.next() {
+                Some(Token { kind: TokenKind::Comma, .. }) => continue,
+                Some(token) if token.kind == close => return Ok((items, token.span.end)),
+                Some(token) => return Err(ConditionError::new(format!("Expected ',' or {} {} but found {}", close.describe(), context, token.kind.describe()), token.span)),
+                None => return Err(ConditionError::new(format!("Expected ',' or {} {} but the condition ended", close.describe(), context), Span::new(self.end, self.end)))
+            }
+        }
+    }
+
+    // The ["key"] and [0] steps following `config`
+    fn config_lookup(&mut self, config_span: Span) -> Result<Expression, ConditionError> {
+        let mut path = Vec::new();
+        let mut end = config_span.end;
+
+        while let Some(TokenKind::LeftBracket) = self.peek().map(|token| &token.kind) {
+            self.next();
+
+            let segment = match self.next() {
+                Some(Token { kind: TokenKind::String(key), .. }) => PathSegment::Key(key),
+                Some(Token { kind: TokenKind::Number(number), span }) => match number.as_u64() {
+                    Some(index) => PathSegment::Index(index as usize),
+                    None => return Err(ConditionError::new("Array positions must be whole numbers of 0 or more".to_owned(), span))
+                },
+                Some(token) => return Err(ConditionError::new(format!("Expected a quoted key or a position inside [] but found {}", token.kind.describe()), token.span)),
+                None => return Err(ConditionError::new("Expected a quoted key inside [] but the condition ended".to_owned(), Span::new(self.end, self.end)))
+            };
+            path.push(segment);
+
+            end = self.expect(TokenKind::RightBracket, "to close the lookup")?.span.end;
+        }
+
+        if path.is_empty() {
+            return Err(ConditionError::new("Expected a lookup such as config[\"key\"] after 'config'".to_owned(), config_span));
+        }
+
+        Ok(Expression::new(ExpressionKind::Config(path), Span::new(config_span.start, end)))
+    }
+}
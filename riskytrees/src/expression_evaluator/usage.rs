@@ -0,0 +1,99 @@
+use serde_json::Value;
+
+use crate::models::{ApiConditionKeyReport, ApiConditionKeyUsage, ApiConditionNodeRef, ApiConfigKeyUsage, ApiFullComputedTreeData, ApiProjectConfigResponseResult};
+
+use super::{lookup, parse_condition, parser, path_values, PathSegment};
+
+// A tree whose conditions are included in the report
+pub struct TreeConditions {
+    pub tree_id: String,
+    pub tree: ApiFullComputedTreeData
+}
+
+// Collects the config keys read by every condition in the trees, with the
+// nodes reading them, and the keys of each stored config that none of them
+// read. Reading an object counts as reading everything inside it, and reading
+// inside an array counts as reading the whole array.
+pub fn report(trees: &[TreeConditions], configs: &[ApiProjectConfigResponseResult]) -> ApiConditionKeyReport {
+    let mut usages: Vec<(Vec<PathSegment>, Vec<ApiConditionNodeRef>)> = vec![];
+    let mut invalid_conditions = vec![];
+
+    for tree in trees {
+        for node in &tree.tree.nodes {
+            let node_ref = ApiConditionNodeRef {
+                treeId: tree.tree_id.clone(),
+                nodeId: node.id.clone(),
+                title: node.title.clone()
+            };
+
+            match parse_condition(&node.conditionAttribute) {
+                Some(Ok(expression)) => {
+                    for path in expression.config_paths() {
+                        match usages.iter_mut().find(|(used, _)| *used == path) {
+                            Some((_, nodes)) => nodes.push(node_ref.clone()),
+                            None => usages.push((path, vec![node_ref.clone()]))
+                        }
+                    }
+                },
+                Some(Err(_)) => invalid_conditions.push(node_ref),
+                None => {}
+            }
+        }
+    }
+
+    let mut keys: Vec<ApiConditionKeyUsage> = usages.iter()
+        .map(|(path, nodes)| ApiConditionKeyUsage {
+            key: parser::format_config_path(path),
+            path: path_values(path),
+            nodes: nodes.clone(),
+            missingFrom: configs.iter()
+                .filter(|config| lookup(&config.attributes, path).is_err())
+                .map(|config| config.id.clone())
+                .collect()
+        })
+        .collect();
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let configs = configs.iter()
+        .map(|config| {
+            let mut leaves = vec![];
+            leaf_paths(&config.attributes, &mut vec![], &mut leaves);
+
+            let mut unused_keys: Vec<String> = leaves.iter()
+                .filter(|leaf| !usages.iter().any(|(used, _)| leaf.starts_with(used) || used.starts_with(leaf)))
+                .map(|leaf| parser::format_config_path(leaf))
+                .collect();
+            unused_keys.sort();
+
+            ApiConfigKeyUsage {
+                configId: config.id.clone(),
+                name: config.name.clone(),
+                unusedKeys: unused_keys
+            }
+        })
+        .collect();
+
+    ApiConditionKeyReport {
+        keys,
+        configs,
+        invalidConditions: invalid_conditions
+    }
+}
+
+// The path to every value in a config that isn't a non-empty object
+fn leaf_paths(value: &Value, prefix: &mut Vec<PathSegment>, leaves: &mut Vec<Vec<PathSegment>>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                prefix.push(PathSegment::Key(key.clone()));
+                leaf_paths(child, prefix, leaves);
+                prefix.pop();
+            }
+        },
+        _ => {
+            if !prefix.is_empty() {
+                leaves.push(prefix.clone());
+            }
+        }
+    }
+}
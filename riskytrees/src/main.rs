@@ -18,6 +18,8 @@ mod helpers;
 mod models;
 mod auth;
 mod expression_evaluator;
+mod model_evaluator;
+mod tree_analysis;
 mod history;
 mod recommendations;
 
@@ -715,8 +717,8 @@ async fn projects_trees_get(id: String, key: auth::ApiKey) -> Json<models::ApiLi
     }
 }
 
-#[get("/projects/<id>/trees/<tree_id>?<config_id>")]
-async fn projects_trees_tree_get(id: String, tree_id: String, key: auth::ApiKey, config_id: Option<String>) -> Json<models::ApiTreeComputedResponse> {
+#[get("/projects/<id>/trees/<tree_id>?<config_id>&<include_subtrees>&<profile_id>&<explain>")]
+async fn projects_trees_tree_get(id: String, tree_id: String, key: auth::ApiKey, config_id: Option<String>, include_subtrees: Option<bool>, profile_id: Option<String>, explain: Option<bool>) -> Json<models::ApiTreeComputedResponse> {
     if key.email == "" {
         Json(models::ApiTreeComputedResponse {
             ok: false,
@@ -730,9 +732,30 @@ async fn projects_trees_tree_get(id: String, tree_id: String, key: auth::ApiKey,
                 // Tenancy applies by default, but if this tree is public, override the tenant
                 let tree: Result<models::ApiFullComputedTreeData, errors::DatabaseError> = match get_publicity_for_tree_by_id(&client, tree_id.clone()).await {
                     Ok(res) => {
-                        match res {
-                            true => database::get_tree_by_id_with_config(&client, database::get_tenant_for_tree(&client, &tree_id.clone()).await.expect("Always exists"), tree_id, id.clone(), config_id).await,
-                            false => database::get_tree_by_id_with_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), tree_id.to_owned(), id.to_owned(), config_id).await        
+                        let project_tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await;
+                        let has_project_access = project_tenant.is_some();
+                        let tenant = match res {
+                            true => database::get_tenant_for_tree(&client, &tree_id.clone()).await.expect("Always exists"),
+                            false => project_tenant.unwrap_or(database::Tenant {name: key.email.clone( )})
+                        };
+
+                        // Linked trees are loaded with the owner's tenant and needn't be
+                        // public themselves, so outsiders only get the public tree
+                        let tree = if include_subtrees.unwrap_or(false) && has_project_access {
+                            database::get_tree_by_id_with_subtrees(&client, tenant.clone(), tree_id.to_owned(), id.to_owned(), config_id.clone(), profile_id).await
+                        } else {
+                            database::get_tree_by_id_with_config(&client, tenant.clone(), tree_id.to_owned(), id.to_owned(), config_id.clone(), profile_id).await
+                        };
+
+                        // Explanations show the config's raw values, which outsiders
+                        // looking at a public tree shouldn't see
+                        match tree {
+                            Ok(mut tree) if explain.unwrap_or(false) && has_project_access => {
+                                let config = database::load_config(&client, tenant, &id, config_id).await;
+                                expression_evaluator::explain_tree(&mut tree, config.ok().map(|config| config.attributes).as_ref());
+                                Ok(tree)
+                            },
+                            tree => tree
                         }
                     },
                     Err(err) => {
@@ -766,13 +789,15 @@ async fn projects_trees_tree_get(id: String, tree_id: String, key: auth::ApiKey,
     }
 }
 
-#[put("/projects/<id>/trees/<tree_id>", data = "<body>")]
-async fn projects_trees_tree_put(id: String, tree_id: String, body: Json<models::ApiFullTreeData>, key: auth::ApiKey) -> Json<models::ApiTreeComputedResponse> {
+#[put("/projects/<id>/trees/<tree_id>?<lenient>", data = "<body>")]
+async fn projects_trees_tree_put(id: String, tree_id: String, lenient: Option<bool>, body: Json<models::ApiFullTreeData>, key: auth::ApiKey) -> Json<models::ApiTreeUpdateResponse> {
     if key.email.clone() == "" {
-        Json(models::ApiTreeComputedResponse {
+        Json(models::ApiTreeUpdateResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
+            errors: vec![],
+            warnings: vec![]
         })
     } else {
         let db_client = database::get_instance().await;
@@ -785,7 +810,43 @@ async fn projects_trees_tree_put(id: String, tree_id: String, body: Json<models:
                         let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
                         let title = body.title.to_owned();
                         let root_node_id = body.rootNodeId.to_owned();
-                        let nodes = body.nodes.clone();
+                        let mut nodes = body.nodes.clone();
+
+                        // The CVSS base score is derived from the vector, so
+                        // one sent back from an earlier GET isn't kept
+                        for node in nodes.iter_mut() {
+                            model_evaluator::cvss::strip_base_score(&mut node.modelAttributes);
+                        }
+
+                        // CVSS vectors have to parse whatever model is selected
+                        let cvss_errors = model_evaluator::cvss::validate_tree_vectors(&nodes);
+                        if !cvss_errors.is_empty() {
+                            return Json(models::ApiTreeUpdateResponse {
+                                ok: false,
+                                message: "Invalid CVSS vectors".to_owned(),
+                                result: None,
+                                errors: cvss_errors,
+                                warnings: vec![]
+                            });
+                        }
+
+                        // Check attributes against the selected model before anything is saved
+                        let model_context = database::get_model_context(&client, &tenant, &id).await;
+                        let issues = match project.selected_model.as_ref().and_then(|model_id| model_context.find_model(model_id)) {
+                            Some(model) => model_evaluator::validate_tree_attributes(model, &nodes),
+                            None => vec![]
+                        };
+                        let condition_warnings = expression_evaluator::validate_tree_conditions(&nodes);
+
+                        if !issues.is_empty() && !lenient.unwrap_or(false) {
+                            return Json(models::ApiTreeUpdateResponse {
+                                ok: false,
+                                message: "Model attributes failed validation".to_owned(),
+                                result: None,
+                                errors: issues,
+                                warnings: vec![]
+                            });
+                        }
 
                         // Save current tree state for undo
                         history::record_tree_update(&client, tenant.to_owned(), tree_id.clone(), body.into_inner()).await;
@@ -798,33 +859,41 @@ async fn projects_trees_tree_put(id: String, tree_id: String, body: Json<models:
                         }).await;
                         match tree {
                             Ok(tree) => {
-                                Json(models::ApiTreeComputedResponse {
+                                Json(models::ApiTreeUpdateResponse {
                                     ok: true,
                                     message: "Found tree".to_owned(),
                                     result: Some(tree),
+                                    errors: vec![],
+                                    warnings: issues.into_iter().chain(condition_warnings).collect()
                                 })
                             },
                             Err(err) => {
-                                Json(models::ApiTreeComputedResponse {
+                                Json(models::ApiTreeUpdateResponse {
                                     ok: false,
                                     message: "Could not find tree using id".to_owned(),
                                     result: None,
+                                    errors: vec![],
+                                    warnings: vec![]
                                 })
                             }
                         }
     
                     }
-                    None => Json(models::ApiTreeComputedResponse {
+                    None => Json(models::ApiTreeUpdateResponse {
                         ok: false,
                         message: "Could not find project".to_owned(),
                         result: None,
+                        errors: vec![],
+                        warnings: vec![]
                     }),
                 }
             }
-            Err(e) => Json(models::ApiTreeComputedResponse {
+            Err(e) => Json(models::ApiTreeUpdateResponse {
                 ok: false,
                 message: "Could not connect to DB".to_owned(),
                 result: None,
+                errors: vec![],
+                warnings: vec![]
             }),
         }    
     }
@@ -1058,11 +1127,23 @@ async fn projects_model_put(id: String, body: Json<models::SelectedModelResult>,
         let db_client = database::get_instance().await;
         match db_client {
             Ok(client) => {
-                let project = database::get_project_by_id(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), id.clone()).await;
+                let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+                let project = database::get_project_by_id(&client, tenant.clone(), id.clone()).await;
 
                 match project {
                     Some(project) => {
-                        match database::update_project_model(client.clone(), database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), id.to_owned(), body.modelId.to_owned()).await {
+                        // Only built-in models and the project's own org's
+                        // custom models can compute its trees
+                        let model_context = database::get_model_context(&client, &tenant, &id).await;
+                        if model_context.find_model(&body.modelId).is_none() {
+                            return Json(models::ApiSelectedModelResponse {
+                                ok: false,
+                                message: "Model is not available to this project".to_owned(),
+                                result: None,
+                            });
+                        }
+
+                        match database::update_project_model(client.clone(), tenant, id.to_owned(), body.modelId.to_owned()).await {
                             Ok(_) => {
                                 Json(models::ApiSelectedModelResponse {
                                     ok: true,
@@ -1098,34 +1179,127 @@ async fn projects_model_put(id: String, body: Json<models::SelectedModelResult>,
     }
 }
 
-#[get("/models")]
-async fn models_get(key: auth::ApiKey) -> Json<models::ApiListModelResponse> {
+#[get("/projects/<id>/model/matrix")]
+async fn projects_model_matrix_get(id: String, key: auth::ApiKey) -> Json<models::ApiRiskMatrixResponse> {
     if key.email == "" {
-        Json(models::ApiListModelResponse {
+        Json(models::ApiRiskMatrixResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+        match db_client {
+            Ok(client) => {
+                match database::get_project_risk_matrix(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &id).await {
+                    Ok(matrix) => Json(models::ApiRiskMatrixResponse {
+                        ok: true,
+                        message: "Found risk matrix".to_owned(),
+                        result: Some(matrix),
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiRiskMatrixResponse {
+                            ok: false,
+                            message: "Could not find project".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            }
+            Err(e) => Json(models::ApiRiskMatrixResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            }),
+        }
+    }
+}
+
+#[put("/projects/<id>/model/matrix", data = "<body>")]
+async fn projects_model_matrix_put(id: String, body: Json<models::RiskMatrix>, key: auth::ApiKey) -> Json<models::ApiRiskMatrixResponse> {
+    if key.email == "" {
+        Json(models::ApiRiskMatrixResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
         })
     } else {
         let db_client = database::get_instance().await;
+        match db_client {
+            Ok(client) => {
+                let matrix = body.into_inner();
 
-        let model_list = vec![
-            models::ListModelResponseItem {
-                id: "b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74".to_owned(), // V4 UUID
-                title: "Attacker Likelihood".to_owned(),
-            },
-            models::ListModelResponseItem {
-                id: "f1644cb9-b2a5-4abb-813f-98d0277e42f2".to_owned(), // V4 UUID
-                title: "Risk of Attack".to_owned(),
-            },
-            models::ListModelResponseItem {
-                id: "bf4397f7-93ae-4502-a4a2-397f40f5cc49".to_owned(), // V4 UUID
-                title: "EVITA".to_owned(),
+                match matrix.validate() {
+                    Ok(_) => {
+                        match database::update_project_risk_matrix(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &id, &matrix).await {
+                            Ok(matrix) => Json(models::ApiRiskMatrixResponse {
+                                ok: true,
+                                message: "Updated risk matrix".to_owned(),
+                                result: Some(matrix),
+                            }),
+                            Err(err) => {
+                                eprintln!("{}", err);
+                                Json(models::ApiRiskMatrixResponse {
+                                    ok: false,
+                                    message: "Could not update project".to_owned(),
+                                    result: None,
+                                })
+                            }
+                        }
+                    },
+                    Err(err) => Json(models::ApiRiskMatrixResponse {
+                        ok: false,
+                        message: format!("Invalid risk matrix: {}", err),
+                        result: None,
+                    })
+                }
             }
-        ];
+            Err(e) => Json(models::ApiRiskMatrixResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            }),
+        }
+    }
+}
+
+#[get("/models?<project_id>")]
+async fn models_get(project_id: Option<String>, key: auth::ApiKey) -> Json<models::ApiListModelResponse> {
+    if key.email == "" {
+        Json(models::ApiListModelResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        let mut model_list = model_evaluator::registry().list();
     
         match db_client {
             Ok(client) => {
+                // Custom models can only be used by projects of the org that
+                // defined them, so they're listed for a given project
+                if let Some(project_id) = project_id {
+                    let tenant = match database::filter_tenant_for_project(&client, key.tenants.clone(), project_id.clone()).await {
+                        Some(tenant) => tenant,
+                        None => return Json(models::ApiListModelResponse {
+                            ok: false,
+                            message: "Could not find project".to_owned(),
+                            result: None,
+                        })
+                    };
+
+                    for definition in database::get_custom_models_for_tenant(&client, &tenant).await {
+                        model_list.push(models::ListModelResponseItem {
+                            id: definition.id,
+                            title: definition.title,
+                            attributes: definition.attributes
+                        });
+                    }
+                }
+
                 Json(models::ApiListModelResponse {
                     ok: true,
                     message: "Got models".to_owned(),
@@ -1228,6 +1402,62 @@ async fn node_recommend_post(body: Json<models::ApiRecommendNodeChildrenPayload>
 
 }
 
+#[post("/conditions/validate", data = "<body>")]
+async fn conditions_validate_post(body: Json<models::ApiConditionValidatePayload>, key: auth::ApiKey) -> Json<models::ApiConditionValidateResponse> {
+    if key.email == "" {
+        return Json(models::ApiConditionValidateResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    // Stored configs are found through their project
+    if body.configId.is_some() && body.projectId.is_none() {
+        return Json(models::ApiConditionValidateResponse {
+            ok: false,
+            message: "projectId is required with configId".to_owned(),
+            result: None,
+        });
+    }
+
+    let attributes = match (&body.attributes, &body.projectId) {
+        (Some(attributes), _) => Some(attributes.clone()),
+        (None, Some(project_id)) => {
+            let client = match database::get_instance().await {
+                Ok(client) => client,
+                Err(err) => return Json(models::ApiConditionValidateResponse {
+                    ok: false,
+                    message: "Could not connect to DB".to_owned(),
+                    result: None,
+                })
+            };
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), project_id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            let config = match &body.configId {
+                Some(config_id) => database::get_config_by_id(&client, tenant, config_id).await,
+                None => database::get_selected_config(&client, tenant, project_id).await
+            };
+
+            match config {
+                Ok(config) => Some(config.attributes),
+                Err(err) => return Json(models::ApiConditionValidateResponse {
+                    ok: false,
+                    message: "Could not find config".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        (None, None) => None
+    };
+
+    Json(models::ApiConditionValidateResponse {
+        ok: true,
+        message: "Validated condition".to_owned(),
+        result: Some(expression_evaluator::validate(&body.condition, attributes.as_ref()))
+    })
+}
+
 #[get("/projects/<projectId>/trees/<treeId>/dag/down")]
 async fn projects_trees_tree_dag_down_get(projectId: String, treeId: String, key: auth::ApiKey) -> Json<models::ApiTreeDagResponse> {
     if key.email == "" {
@@ -1279,10 +1509,10 @@ async fn projects_trees_tree_dag_down_get(projectId: String, treeId: String, key
 
 }
 
-#[get("/projects/<projectId>/configs")]
-async fn projects_configs_list(projectId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigListResponse> {
+#[get("/projects/<id>/trees/<tree_id>/cutsets?<config_id>")]
+async fn projects_trees_tree_cutsets_get(id: String, tree_id: String, config_id: Option<String>, key: auth::ApiKey) -> Json<models::ApiCutSetsResponse> {
     if key.email == "" {
-        Json(models::ApiProjectConfigListResponse {
+        Json(models::ApiCutSetsResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
@@ -1292,145 +1522,706 @@ async fn projects_configs_list(projectId: String, key: auth::ApiKey) -> Json<mod
 
         match db_client {
             Ok(client) => {
-                let matching_configs = database::get_configs_for_project(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId).await;
-    
-                Json(models::ApiProjectConfigListResponse {
-                    ok: true,
-                    message: "Got configs".to_string(),
-                    result: Some(ApiProjectConfigListResponseResult {
-                        ids: matching_configs
-                    })
-                })
-            },
-            Err(err) => Json(models::ApiProjectConfigListResponse {
-                ok: false,
-                message: "Could not connect to DB".to_owned(),
-                result: None,
-            })
-        }    
-    }
+                let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
 
-}
+                match database::get_project_by_id(&client, tenant.clone(), id.clone()).await {
+                    Some(project) => {
+                        match database::get_tree_with_links(&client, tenant.clone(), tree_id, &id, config_id).await {
+                            Ok((tree, linked)) => {
+                                let model_context = database::get_model_context(&client, &tenant, &id).await;
+                                let model = project.selected_model.as_ref().and_then(|model_id| model_context.find_model(model_id));
 
-#[post("/projects/<projectId>/configs", data = "<body>")]
-async fn projects_configs_post(projectId: String, body: Json<models::ApiProjectConfigPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
-    if key.email == "" {
-        Json(models::ApiProjectConfigResponse {
-            ok: false,
-            message: "Could not find a tenant".to_owned(),
-            result: None,
-        })
-    } else {
-        let db_client = database::get_instance().await;
+                                let nodes = model_evaluator::combined_nodes(&tree, &linked);
+                                let cut_sets = tree_analysis::cutsets::minimal_cut_sets(&nodes, &tree.rootNodeId);
 
-        match db_client {
-            Ok(client) => {
-                let thing = body.into_inner();
-    
-                let new_config_id: Result<String, errors::DatabaseError> = database::new_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &thing).await;
-                match new_config_id {
-                    Ok(new_config_id) => {
-                        Json(models::ApiProjectConfigResponse {
-                            ok: true,
-                            message: "Created config".to_owned(),
-                            result: Some(models::ApiProjectConfigResponseResult {
-                                id: new_config_id,
-                                attributes: serde_json::json!(thing.attributes),
-                                name: None
+                                Json(models::ApiCutSetsResponse {
+                                    ok: true,
+                                    message: "Computed cut sets".to_owned(),
+                                    result: Some(models::ApiCutSetsResult {
+                                        modelId: model.map(|model| model.id().to_owned()),
+                                        cutSets: tree_analysis::cutsets::score_cut_sets(&nodes, &cut_sets, model),
+                                        truncated: cut_sets.truncated
+                                    })
+                                })
+                            },
+                            Err(err) => Json(models::ApiCutSetsResponse {
+                                ok: false,
+                                message: "Could not find tree using id".to_owned(),
+                                result: None,
                             })
-                        })
+                        }
                     },
-                    Err(err) => Json(models::ApiProjectConfigResponse {
+                    None => Json(models::ApiCutSetsResponse {
                         ok: false,
-                        message: "Creation of config failed".to_owned(),
+                        message: "Could not find project".to_owned(),
                         result: None,
                     })
                 }
             },
-            Err(err) => Json(models::ApiProjectConfigResponse {
+            Err(err) => Json(models::ApiCutSetsResponse {
                 ok: false,
                 message: "Could not connect to DB".to_owned(),
                 result: None,
             })
-        }    
+        }
     }
-
 }
 
-#[put("/projects/<projectId>/configs/<configId>", data = "<body>")]
-async fn projects_configs_put(projectId: String, configId: String, body: Json<models::ApiProjectConfigPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+#[get("/projects/<id>/trees/<tree_id>/paths/best?<objective>&<config_id>")]
+async fn projects_trees_tree_best_path_get(id: String, tree_id: String, objective: String, config_id: Option<String>, key: auth::ApiKey) -> Json<models::ApiAttackPathResponse> {
     if key.email == "" {
-        Json(models::ApiProjectConfigResponse {
+        return Json(models::ApiAttackPathResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
+        });
+    }
+
+    let objective = match tree_analysis::paths::PathObjective::parse(&objective) {
+        Some(objective) => objective,
+        None => return Json(models::ApiAttackPathResponse {
+            ok: false,
+            message: "objective must be one of cost, likelihood or time".to_owned(),
+            result: None,
         })
-    } else {
-        let db_client = database::get_instance().await;
+    };
 
-        match db_client {
-            Ok(client) => {
-                let thing = body.into_inner();
-                let new_config = database::update_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &configId, &thing).await;
-    
-                match new_config {
-                    Ok(updated_id) => {
-                        Json(models::ApiProjectConfigResponse {
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            match database::get_tree_with_links(&client, tenant, tree_id, &id, config_id).await {
+                Ok((tree, linked)) => {
+                    let nodes = model_evaluator::combined_nodes(&tree, &linked);
+
+                    match tree_analysis::paths::best_attack_path(&nodes, &tree.rootNodeId, objective) {
+                        Some(path) => Json(models::ApiAttackPathResponse {
                             ok: true,
-                            message: "Updated config".to_owned(),
-                            result: Some(models::ApiProjectConfigResponseResult {
-                                id: updated_id,
-                                attributes: serde_json::json!(thing.attributes),
-                                name: thing.name
-                            }),
-                        })
-                    },
-                    Err(err) => {
-                        Json(models::ApiProjectConfigResponse {
+                            message: "Found attack path".to_owned(),
+                            result: Some(models::ApiAttackPath {
+                                objective: objective.as_str().to_owned(),
+                                nodeIds: path.node_ids,
+                                value: path.value
+                            })
+                        }),
+                        None => Json(models::ApiAttackPathResponse {
                             ok: false,
-                            message: "Update config failed".to_owned(),
+                            message: format!("No attack path has a {} estimate", objective.attribute()),
                             result: None,
                         })
                     }
-                }
-            },
-            Err(err) => Json(models::ApiProjectConfigResponse {
-                ok: false,
-                message: "Could not connect to DB".to_owned(),
-                result: None,
-            })
-        }    
+                },
+                Err(err) => Json(models::ApiAttackPathResponse {
+                    ok: false,
+                    message: "Could not find tree using id".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiAttackPathResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
     }
-
 }
 
-#[get("/projects/<projectId>/configs/<configId>")]
-async fn projects_configs_get(projectId: String, configId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+#[post("/projects/<id>/trees/<tree_id>/simulate", data = "<body>")]
+async fn projects_trees_tree_simulate_post(id: String, tree_id: String, body: Json<models::ApiSimulationPayload>, key: auth::ApiKey) -> Json<models::ApiSimulationResponse> {
     if key.email == "" {
-        Json(models::ApiProjectConfigResponse {
+        return Json(models::ApiSimulationResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
-        })
-    } else {
-        let db_client = database::get_instance().await;
+        });
+    }
 
-        match db_client {
-            Ok(client) => {
-                let config = database::get_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &configId).await;
-    
-                match config {
-                    Ok(resp) => {
-                        Json(models::ApiProjectConfigResponse {
-                            ok: true,
-                            message: "Got config".to_owned(),
-                            result: Some(resp),
-                        })
-                    },
-                    Err(err) => {
+    let iterations = body.iterations.unwrap_or(tree_analysis::simulation::DEFAULT_ITERATIONS);
+    if iterations == 0 || iterations > tree_analysis::simulation::MAX_ITERATIONS {
+        return Json(models::ApiSimulationResponse {
+            ok: false,
+            message: format!("iterations must be between 1 and {}", tree_analysis::simulation::MAX_ITERATIONS),
+            result: None,
+        });
+    }
+
+    let bins = body.bins.unwrap_or(tree_analysis::simulation::DEFAULT_HISTOGRAM_BINS).clamp(1, 100);
+    let seed = body.seed.unwrap_or_else(|| rand::random());
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            match database::get_tree_with_links(&client, tenant, tree_id, &id, body.configId.clone()).await {
+                Ok((tree, linked)) => {
+                    let nodes = model_evaluator::combined_nodes(&tree, &linked);
+                    let node_ids = body.nodeIds.clone().unwrap_or(vec![tree.rootNodeId.clone()]);
+
+                    // Up to MAX_ITERATIONS rollups, so keep it off the async workers
+                    let simulated = rocket::tokio::task::spawn_blocking(move || {
+                        tree_analysis::simulation::simulate(&nodes, &node_ids, iterations, seed, bins)
+                    }).await;
+
+                    match simulated {
+                        Ok(simulated_nodes) => Json(models::ApiSimulationResponse {
+                            ok: true,
+                            message: "Simulated tree".to_owned(),
+                            result: Some(models::ApiSimulationResult {
+                                iterations: iterations,
+                                seed: seed,
+                                nodes: simulated_nodes
+                            })
+                        }),
+                        Err(err) => Json(models::ApiSimulationResponse {
+                            ok: false,
+                            message: "Simulation failed".to_owned(),
+                            result: None,
+                        })
+                    }
+                },
+                Err(err) => Json(models::ApiSimulationResponse {
+                    ok: false,
+                    message: "Could not find tree using id".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiSimulationResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+#[get("/projects/<id>/trees/<tree_id>/sensitivity?<config_id>&<include_subtrees>&<perturbation>")]
+async fn projects_trees_tree_sensitivity_get(id: String, tree_id: String, config_id: Option<String>, include_subtrees: Option<bool>, perturbation: Option<f64>, key: auth::ApiKey) -> Json<models::ApiSensitivityResponse> {
+    if key.email == "" {
+        return Json(models::ApiSensitivityResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    let perturbation = perturbation.unwrap_or(tree_analysis::sensitivity::DEFAULT_PERTURBATION);
+    if !(perturbation > 0.0 && perturbation <= 1.0) {
+        return Json(models::ApiSensitivityResponse {
+            ok: false,
+            message: "perturbation must be above 0 and at most 1".to_owned(),
+            result: None,
+        });
+    }
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            let project = match database::get_project_by_id(&client, tenant.clone(), id.clone()).await {
+                Some(project) => project,
+                None => return Json(models::ApiSensitivityResponse {
+                    ok: false,
+                    message: "Could not find project".to_owned(),
+                    result: None,
+                })
+            };
+
+            let model_context = database::get_model_context(&client, &tenant, &id).await;
+            let model_id = match project.selected_model.filter(|model_id| model_context.find_model(model_id).is_some()) {
+                Some(model_id) => model_id,
+                None => return Json(models::ApiSensitivityResponse {
+                    ok: false,
+                    message: "Project has no model selected".to_owned(),
+                    result: None,
+                })
+            };
+
+            match database::get_tree_with_links(&client, tenant.clone(), tree_id, &id, config_id).await {
+                Ok((tree, linked)) => {
+                    // Same aggregation as tree GET, which only follows links when asked to
+                    let linked = if include_subtrees.unwrap_or(false) { linked } else { vec![] };
+
+                    // Recomputes the tree twice for every leaf attribute, so keep
+                    // it off the async workers
+                    let report = rocket::tokio::task::spawn_blocking(move || {
+                        model_context.find_model(&model_id)
+                            .map(|model| tree_analysis::sensitivity::sensitivity(&tree, &linked, model, &model_context, perturbation))
+                    }).await;
+
+                    match report {
+                        Ok(Some(report)) => Json(models::ApiSensitivityResponse {
+                            ok: true,
+                            message: "Computed sensitivity".to_owned(),
+                            result: Some(report)
+                        }),
+                        _ => Json(models::ApiSensitivityResponse {
+                            ok: false,
+                            message: "Could not compute sensitivity".to_owned(),
+                            result: None,
+                        })
+                    }
+                },
+                Err(err) => Json(models::ApiSensitivityResponse {
+                    ok: false,
+                    message: "Could not find tree using id".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiSensitivityResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+#[post("/projects/<id>/trees/<tree_id>/mitigations/optimize", data = "<body>")]
+async fn projects_trees_tree_mitigations_optimize_post(id: String, tree_id: String, body: Json<models::ApiMitigationPayload>, key: auth::ApiKey) -> Json<models::ApiMitigationPlanResponse> {
+    if key.email == "" {
+        return Json(models::ApiMitigationPlanResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    if !(body.budget >= 0.0) {
+        return Json(models::ApiMitigationPlanResponse {
+            ok: false,
+            message: "budget must not be negative".to_owned(),
+            result: None,
+        });
+    }
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            let project = match database::get_project_by_id(&client, tenant.clone(), id.clone()).await {
+                Some(project) => project,
+                None => return Json(models::ApiMitigationPlanResponse {
+                    ok: false,
+                    message: "Could not find project".to_owned(),
+                    result: None,
+                })
+            };
+
+            let model_context = database::get_model_context(&client, &tenant, &id).await;
+            let model_id = match project.selected_model.filter(|model_id| model_context.find_model(model_id).is_some()) {
+                Some(model_id) => model_id,
+                None => return Json(models::ApiMitigationPlanResponse {
+                    ok: false,
+                    message: "Project has no model selected".to_owned(),
+                    result: None,
+                })
+            };
+
+            match database::get_tree_with_links(&client, tenant.clone(), tree_id, &id, body.configId.clone()).await {
+                Ok((tree, linked)) => {
+                    let linked = if body.includeSubtrees.unwrap_or(false) { linked } else { vec![] };
+                    let budget = body.budget;
+
+                    // The exact search recomputes the tree up to 2^12 times, so
+                    // keep it off the async workers
+                    let plan = rocket::tokio::task::spawn_blocking(move || {
+                        model_context.find_model(&model_id)
+                            .map(|model| tree_analysis::mitigation::optimize(&tree, &linked, model, &model_context, budget))
+                    }).await;
+
+                    match plan {
+                        Ok(Some(plan)) => Json(models::ApiMitigationPlanResponse {
+                            ok: true,
+                            message: "Optimized mitigations".to_owned(),
+                            result: Some(plan)
+                        }),
+                        _ => Json(models::ApiMitigationPlanResponse {
+                            ok: false,
+                            message: "Could not optimize mitigations".to_owned(),
+                            result: None,
+                        })
+                    }
+                },
+                Err(err) => Json(models::ApiMitigationPlanResponse {
+                    ok: false,
+                    message: "Could not find tree using id".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiMitigationPlanResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+// Takes the same body as config POST, so a config that was tried here can be
+// saved as is
+#[post("/projects/<id>/trees/<tree_id>/evaluate?<include_subtrees>&<profile_id>", data = "<body>")]
+async fn projects_trees_tree_evaluate_post(id: String, tree_id: String, include_subtrees: Option<bool>, profile_id: Option<String>, body: Json<models::ApiProjectConfigPayload>, key: auth::ApiKey) -> Json<models::ApiTreeComputedResponse> {
+    if key.email == "" {
+        return Json(models::ApiTreeComputedResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    if !body.attributes.is_object() {
+        return Json(models::ApiTreeComputedResponse {
+            ok: false,
+            message: "attributes must be an object".to_owned(),
+            result: None,
+        });
+    }
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            match database::evaluate_tree_with_attributes(&client, tenant, tree_id, id, body.attributes.clone(), include_subtrees.unwrap_or(false), profile_id).await {
+                Ok(tree) => Json(models::ApiTreeComputedResponse {
+                    ok: true,
+                    message: "Evaluated tree".to_owned(),
+                    result: Some(tree)
+                }),
+                Err(err) => Json(models::ApiTreeComputedResponse {
+                    ok: false,
+                    message: "Could not find tree using id".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiTreeComputedResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+#[get("/projects/<id>/trees/<tree_id>/configs/matrix?<include_subtrees>&<profile_id>")]
+async fn projects_trees_tree_config_matrix_get(id: String, tree_id: String, include_subtrees: Option<bool>, profile_id: Option<String>, key: auth::ApiKey) -> Json<models::ApiConfigMatrixResponse> {
+    if key.email == "" {
+        return Json(models::ApiConfigMatrixResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            match database::get_tree_config_matrix(&client, tenant, tree_id, id, include_subtrees.unwrap_or(false), profile_id).await {
+                Ok(matrix) => Json(models::ApiConfigMatrixResponse {
+                    ok: true,
+                    message: "Computed config matrix".to_owned(),
+                    result: Some(matrix)
+                }),
+                Err(err) => Json(models::ApiConfigMatrixResponse {
+                    ok: false,
+                    message: "Could not evaluate tree against project configs".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiConfigMatrixResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+// Without tree_id every tree of the project is included
+#[get("/projects/<id>/conditions/keys?<tree_id>")]
+async fn projects_conditions_keys_get(id: String, tree_id: Option<String>, key: auth::ApiKey) -> Json<models::ApiConditionKeyReportResponse> {
+    if key.email == "" {
+        return Json(models::ApiConditionKeyReportResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        });
+    }
+
+    let db_client = database::get_instance().await;
+
+    match db_client {
+        Ok(client) => {
+            let tenant = database::filter_tenant_for_project(&client, key.tenants.clone(), id.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )});
+
+            match database::get_condition_key_report(&client, tenant, id, tree_id).await {
+                Ok(report) => Json(models::ApiConditionKeyReportResponse {
+                    ok: true,
+                    message: "Collected condition keys".to_owned(),
+                    result: Some(report)
+                }),
+                Err(err) => Json(models::ApiConditionKeyReportResponse {
+                    ok: false,
+                    message: "Could not collect condition keys".to_owned(),
+                    result: None,
+                })
+            }
+        },
+        Err(err) => Json(models::ApiConditionKeyReportResponse {
+            ok: false,
+            message: "Could not connect to DB".to_owned(),
+            result: None,
+        })
+    }
+}
+
+#[get("/projects/<projectId>/configs")]
+async fn projects_configs_list(projectId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigListResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigListResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let matching_configs = database::get_configs_for_project(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId).await;
+    
+                Json(models::ApiProjectConfigListResponse {
+                    ok: true,
+                    message: "Got configs".to_string(),
+                    result: Some(ApiProjectConfigListResponseResult {
+                        ids: matching_configs
+                    })
+                })
+            },
+            Err(err) => Json(models::ApiProjectConfigListResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }    
+    }
+
+}
+
+#[post("/projects/<projectId>/configs", data = "<body>")]
+async fn projects_configs_post(projectId: String, body: Json<models::ApiProjectConfigPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let thing = body.into_inner();
+    
+                let new_config_id: Result<String, errors::DatabaseError> = database::new_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &thing).await;
+                match new_config_id {
+                    Ok(new_config_id) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: true,
+                            message: "Created config".to_owned(),
+                            result: Some(models::ApiProjectConfigResponseResult {
+                                id: new_config_id,
+                                attributes: serde_json::json!(thing.attributes),
+                                name: None
+                            })
+                        })
+                    },
+                    Err(err) => Json(models::ApiProjectConfigResponse {
+                        ok: false,
+                        message: "Creation of config failed".to_owned(),
+                        result: None,
+                    })
+                }
+            },
+            Err(err) => Json(models::ApiProjectConfigResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }    
+    }
+
+}
+
+#[put("/projects/<projectId>/configs/<configId>", data = "<body>")]
+async fn projects_configs_put(projectId: String, configId: String, body: Json<models::ApiProjectConfigPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let thing = body.into_inner();
+                let new_config = database::update_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &configId, &thing).await;
+    
+                match new_config {
+                    Ok(updated_id) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: true,
+                            message: "Updated config".to_owned(),
+                            result: Some(models::ApiProjectConfigResponseResult {
+                                id: updated_id,
+                                attributes: serde_json::json!(thing.attributes),
+                                name: thing.name
+                            }),
+                        })
+                    },
+                    Err(err) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: false,
+                            message: "Update config failed".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiProjectConfigResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }    
+    }
+
+}
+
+#[get("/projects/<projectId>/configs/<configId>")]
+async fn projects_configs_get(projectId: String, configId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let config = database::get_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &configId).await;
+    
+                match config {
+                    Ok(resp) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: true,
+                            message: "Got config".to_owned(),
+                            result: Some(resp),
+                        })
+                    },
+                    Err(err) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: false,
+                            message: "Get config failed".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiProjectConfigResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }    
+    }
+
+}
+
+#[get("/projects/<projectId>/config")]
+async fn projects_config_get(projectId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let config = database::get_selected_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId).await;
+    
+                match config {
+                    Ok(config) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: true,
+                            message: "Found selected config".to_owned(),
+                            result: Some(config),
+                        })
+                    },
+                    Err(err) => Json(models::ApiProjectConfigResponse {
+                        ok: false,
+                        message: "Error finding selected config".to_owned(),
+                        result: None,
+                    })
+                }
+            },
+            Err(err) => Json(models::ApiProjectConfigResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[put("/projects/<projectId>/config", data = "<body>")]
+async fn projects_config_put(projectId: String, body: Json<models::ApiProjectConfigIdPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+    if key.email == "" {
+        Json(models::ApiProjectConfigResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                let thing: models::ApiProjectConfigIdPayload = body.into_inner();
+                let res = database::update_project_selected_config( &client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &thing).await;
+    
+                match res {
+                    Ok(res) => {
+                        Json(models::ApiProjectConfigResponse {
+                            ok: true,
+                            message: "Found selected config".to_owned(),
+                            result: Some(res),
+                        })
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
                         Json(models::ApiProjectConfigResponse {
                             ok: false,
-                            message: "Get config failed".to_owned(),
+                            message: "Error updating config".to_owned(),
                             result: None,
                         })
                     }
@@ -1446,10 +2237,10 @@ async fn projects_configs_get(projectId: String, configId: String, key: auth::Ap
 
 }
 
-#[get("/projects/<projectId>/config")]
-async fn projects_config_get(projectId: String, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+#[get("/projects/<projectId>/profiles")]
+async fn projects_profiles_list(projectId: String, key: auth::ApiKey) -> Json<models::ApiAttackerProfileListResponse> {
     if key.email == "" {
-        Json(models::ApiProjectConfigResponse {
+        Json(models::ApiAttackerProfileListResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
@@ -1459,24 +2250,99 @@ async fn projects_config_get(projectId: String, key: auth::ApiKey) -> Json<model
 
         match db_client {
             Ok(client) => {
-                let config = database::get_selected_config(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId).await;
-    
-                match config {
-                    Ok(config) => {
-                        Json(models::ApiProjectConfigResponse {
-                            ok: true,
-                            message: "Found selected config".to_owned(),
-                            result: Some(config),
+                let profiles = database::get_attacker_profiles(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId).await;
+
+                Json(models::ApiAttackerProfileListResponse {
+                    ok: true,
+                    message: "Got profiles".to_owned(),
+                    result: Some(models::AttackerProfileList {
+                        profiles: profiles
+                    })
+                })
+            },
+            Err(err) => Json(models::ApiAttackerProfileListResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[post("/projects/<projectId>/profiles", data = "<body>")]
+async fn projects_profiles_post(projectId: String, body: Json<models::AttackerProfilePayload>, key: auth::ApiKey) -> Json<models::ApiAttackerProfileResponse> {
+    if key.email == "" {
+        Json(models::ApiAttackerProfileResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let payload = body.into_inner();
+        if let Err(err) = payload.validate() {
+            return Json(models::ApiAttackerProfileResponse {
+                ok: false,
+                message: err,
+                result: None,
+            });
+        }
+
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::create_attacker_profile(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &payload).await {
+                    Ok(res) => Json(models::ApiAttackerProfileResponse {
+                        ok: true,
+                        message: "Created profile".to_owned(),
+                        result: Some(res)
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiAttackerProfileResponse {
+                            ok: false,
+                            message: "Error creating profile".to_owned(),
+                            result: None,
                         })
-                    },
-                    Err(err) => Json(models::ApiProjectConfigResponse {
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiAttackerProfileResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[get("/projects/<projectId>/profiles/<profileId>")]
+async fn projects_profiles_get(projectId: String, profileId: String, key: auth::ApiKey) -> Json<models::ApiAttackerProfileResponse> {
+    if key.email == "" {
+        Json(models::ApiAttackerProfileResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::get_attacker_profile(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &profileId).await {
+                    Ok(res) => Json(models::ApiAttackerProfileResponse {
+                        ok: true,
+                        message: "Got profile".to_owned(),
+                        result: Some(res)
+                    }),
+                    Err(err) => Json(models::ApiAttackerProfileResponse {
                         ok: false,
-                        message: "Error finding selected config".to_owned(),
+                        message: "Could not find profile".to_owned(),
                         result: None,
                     })
                 }
             },
-            Err(err) => Json(models::ApiProjectConfigResponse {
+            Err(err) => Json(models::ApiAttackerProfileResponse {
                 ok: false,
                 message: "Could not connect to DB".to_owned(),
                 result: None,
@@ -1485,48 +2351,94 @@ async fn projects_config_get(projectId: String, key: auth::ApiKey) -> Json<model
     }
 }
 
-#[put("/projects/<projectId>/config", data = "<body>")]
-async fn projects_config_put(projectId: String, body: Json<models::ApiProjectConfigIdPayload>, key: auth::ApiKey) -> Json<models::ApiProjectConfigResponse> {
+#[put("/projects/<projectId>/profiles/<profileId>", data = "<body>")]
+async fn projects_profiles_put(projectId: String, profileId: String, body: Json<models::AttackerProfilePayload>, key: auth::ApiKey) -> Json<models::ApiAttackerProfileResponse> {
     if key.email == "" {
-        Json(models::ApiProjectConfigResponse {
+        Json(models::ApiAttackerProfileResponse {
             ok: false,
             message: "Could not find a tenant".to_owned(),
             result: None,
         })
     } else {
+        let payload = body.into_inner();
+        if let Err(err) = payload.validate() {
+            return Json(models::ApiAttackerProfileResponse {
+                ok: false,
+                message: err,
+                result: None,
+            });
+        }
+
         let db_client = database::get_instance().await;
 
         match db_client {
             Ok(client) => {
-                let thing: models::ApiProjectConfigIdPayload = body.into_inner();
-                let res = database::update_project_selected_config( &client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &thing).await;
-    
-                match res {
-                    Ok(res) => {
-                        Json(models::ApiProjectConfigResponse {
-                            ok: true,
-                            message: "Found selected config".to_owned(),
-                            result: Some(res),
-                        })
-                    },
+                match database::update_attacker_profile(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &profileId, &payload).await {
+                    Ok(res) => Json(models::ApiAttackerProfileResponse {
+                        ok: true,
+                        message: "Updated profile".to_owned(),
+                        result: Some(res)
+                    }),
                     Err(err) => {
                         eprintln!("{}", err);
-                        Json(models::ApiProjectConfigResponse {
+                        Json(models::ApiAttackerProfileResponse {
                             ok: false,
-                            message: "Error updating config".to_owned(),
+                            message: "Error updating profile".to_owned(),
                             result: None,
                         })
                     }
                 }
             },
-            Err(err) => Json(models::ApiProjectConfigResponse {
+            Err(err) => Json(models::ApiAttackerProfileResponse {
                 ok: false,
                 message: "Could not connect to DB".to_owned(),
                 result: None,
             })
-        }    
+        }
     }
+}
+
+#[delete("/projects/<projectId>/profiles/<profileId>")]
+async fn projects_profiles_delete(projectId: String, profileId: String, key: auth::ApiKey) -> Json<models::ApiAttackerProfileResponse> {
+    if key.email == "" {
+        Json(models::ApiAttackerProfileResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
 
+        match db_client {
+            Ok(client) => {
+                match database::delete_attacker_profile(&client, database::filter_tenant_for_project(&client, key.tenants.clone(), projectId.clone()).await.unwrap_or(database::Tenant {name: key.email.clone( )}), &projectId, &profileId).await {
+                    Ok(true) => Json(models::ApiAttackerProfileResponse {
+                        ok: true,
+                        message: "Deleted profile".to_owned(),
+                        result: None
+                    }),
+                    Ok(false) => Json(models::ApiAttackerProfileResponse {
+                        ok: false,
+                        message: "Could not find profile".to_owned(),
+                        result: None
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiAttackerProfileResponse {
+                            ok: false,
+                            message: "Error trying to delete profile".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiAttackerProfileResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
 }
 
 #[post("/orgs", data = "<body>")]
@@ -1915,10 +2827,188 @@ async fn org_delete(org_id: String, key: auth::ApiKey) -> Json<models::ApiDelete
     }
 }
 
+#[get("/orgs/<org_id>/models")]
+async fn org_models_get(org_id: String, key: auth::ApiKey) -> Json<models::ApiCustomModelListResponse> {
+    if key.email == "" {
+        Json(models::ApiCustomModelListResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::get_custom_models_for_org(&client, key.tenants.clone(), &org_id).await {
+                    Ok(res) => Json(models::ApiCustomModelListResponse {
+                        ok: true,
+                        message: "Got models".to_owned(),
+                        result: Some(models::CustomModelList {
+                            models: res
+                        })
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiCustomModelListResponse {
+                            ok: false,
+                            message: "Error getting models for org".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiCustomModelListResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[post("/orgs/<org_id>/models", data = "<body>")]
+async fn org_models_post(org_id: String, body: Json<models::CustomModelPayload>, key: auth::ApiKey) -> Json<models::ApiCustomModelResponse> {
+    if key.email == "" {
+        Json(models::ApiCustomModelResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let payload = body.into_inner();
+        if let Err(err) = model_evaluator::custom::validate_definition(&models::CustomModelDefinition::from_payload("".to_owned(), org_id.clone(), &payload)) {
+            return Json(models::ApiCustomModelResponse {
+                ok: false,
+                message: err,
+                result: None,
+            });
+        }
+
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::create_custom_model(&client, key.tenants.clone(), &org_id, &payload).await {
+                    Ok(res) => Json(models::ApiCustomModelResponse {
+                        ok: true,
+                        message: "Created model".to_owned(),
+                        result: Some(res)
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiCustomModelResponse {
+                            ok: false,
+                            message: "Error creating model".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiCustomModelResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[put("/orgs/<org_id>/models/<model_id>", data = "<body>")]
+async fn org_models_put(org_id: String, model_id: String, body: Json<models::CustomModelPayload>, key: auth::ApiKey) -> Json<models::ApiCustomModelResponse> {
+    if key.email == "" {
+        Json(models::ApiCustomModelResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let payload = body.into_inner();
+        if let Err(err) = model_evaluator::custom::validate_definition(&models::CustomModelDefinition::from_payload(model_id.clone(), org_id.clone(), &payload)) {
+            return Json(models::ApiCustomModelResponse {
+                ok: false,
+                message: err,
+                result: None,
+            });
+        }
+
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::update_custom_model(&client, key.tenants.clone(), &org_id, &model_id, &payload).await {
+                    Ok(res) => Json(models::ApiCustomModelResponse {
+                        ok: true,
+                        message: "Updated model".to_owned(),
+                        result: Some(res)
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiCustomModelResponse {
+                            ok: false,
+                            message: "Error updating model".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiCustomModelResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
+#[delete("/orgs/<org_id>/models/<model_id>")]
+async fn org_models_delete(org_id: String, model_id: String, key: auth::ApiKey) -> Json<models::ApiCustomModelResponse> {
+    if key.email == "" {
+        Json(models::ApiCustomModelResponse {
+            ok: false,
+            message: "Could not find a tenant".to_owned(),
+            result: None,
+        })
+    } else {
+        let db_client = database::get_instance().await;
+
+        match db_client {
+            Ok(client) => {
+                match database::delete_custom_model(&client, key.tenants.clone(), &org_id, &model_id).await {
+                    Ok(true) => Json(models::ApiCustomModelResponse {
+                        ok: true,
+                        message: "Deleted model".to_owned(),
+                        result: None
+                    }),
+                    Ok(false) => Json(models::ApiCustomModelResponse {
+                        ok: false,
+                        message: "Could not find model".to_owned(),
+                        result: None
+                    }),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Json(models::ApiCustomModelResponse {
+                            ok: false,
+                            message: "Error trying to delete model".to_owned(),
+                            result: None,
+                        })
+                    }
+                }
+            },
+            Err(err) => Json(models::ApiCustomModelResponse {
+                ok: false,
+                message: "Could not connect to DB".to_owned(),
+                result: None,
+            })
+        }
+    }
+}
+
 
 #[launch]
 async fn rocket() -> _ {
     rustls::crypto::aws_lc_rs::default_provider().install_default();
+    model_evaluator::registry();
     rocket::build()
         .mount(
             "/",
@@ -1944,20 +3034,40 @@ async fn rocket() -> _ {
                 projects_trees_tree_public_get,
                 projects_trees_tree_public_put,
                 projects_trees_tree_dag_down_get,
+                projects_trees_tree_cutsets_get,
+                projects_trees_tree_best_path_get,
+                projects_trees_tree_simulate_post,
+                projects_trees_tree_sensitivity_get,
+                projects_trees_tree_mitigations_optimize_post,
+                projects_trees_tree_evaluate_post,
+                projects_trees_tree_config_matrix_get,
+                projects_conditions_keys_get,
                 projects_model_get,
                 projects_model_put,
+                projects_model_matrix_get,
+                projects_model_matrix_put,
                 projects_configs_list,
                 projects_configs_post,
                 projects_configs_put,
                 projects_configs_get,
                 projects_config_get,
                 projects_config_put,
+                projects_profiles_list,
+                projects_profiles_post,
+                projects_profiles_get,
+                projects_profiles_put,
+                projects_profiles_delete,
                 models_get,
                 node_get,
                 node_recommend_post,
+                conditions_validate_post,
                 orgs_post,
                 orgs_get,
                 org_delete,
+                org_models_get,
+                org_models_post,
+                org_models_put,
+                org_models_delete,
                 orgs_put,
                 org_members_get,
                 orgs_members_post,
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::models::{ApiFullComputedNodeData, ModelAttributeSchema, NodeType};
+use crate::model_evaluator;
+use crate::tree_analysis::paths::COST_ATTRIBUTE;
+
+pub const EFFECTIVENESS_ATTRIBUTE: &str = "effectiveness";
+
+// The modelAttributes a countermeasure node carries, whatever the selected model
+pub fn countermeasure_attributes() -> Vec<ModelAttributeSchema> {
+    vec![
+        ModelAttributeSchema::number(EFFECTIVENESS_ATTRIBUTE, Some(0.0), Some(1.0)),
+        ModelAttributeSchema::number(COST_ATTRIBUTE, Some(0.0), None)
+    ]
+}
+
+pub fn is_countermeasure(node: &ApiFullComputedNodeData) -> bool {
+    node.nodeType == NodeType::Countermeasure
+}
+
+// Share of attacks the countermeasure stops, 0 when it isn't rated
+pub fn effectiveness(node: &ApiFullComputedNodeData) -> f64 {
+    node.modelAttributes.get(EFFECTIVENESS_ATTRIBUTE)
+        .and_then(|attribute| attribute.as_f64())
+        .map_or(0.0, |value| value.clamp(0.0, 1.0))
+}
+
+// Combined effectiveness of the countermeasures attached to each attack node.
+// Countermeasures are treated as independent, so an attack gets past all of
+// them with probability prod(1 - effectiveness). Unresolved countermeasures
+// don't apply and nodes without any are left out.
+pub fn mitigations(nodes: &[ApiFullComputedNodeData]) -> HashMap<String, f64> {
+    let node_indices = model_evaluator::index_nodes(nodes);
+    let mut mitigations = HashMap::new();
+
+    for node in nodes.iter().filter(|node| !is_countermeasure(node)) {
+        let countermeasures: Vec<&ApiFullComputedNodeData> = node.children.iter()
+            .filter_map(|child| node_indices.get(child).map(|index| &nodes[*index]))
+            .filter(|child| is_countermeasure(child) && child.conditionResolved)
+            .collect();
+
+        if !countermeasures.is_empty() {
+            let bypassed: f64 = countermeasures.iter().map(|countermeasure| 1.0 - effectiveness(countermeasure)).product();
+            mitigations.insert(node.id.clone(), 1.0 - bypassed);
+        }
+    }
+
+    mitigations
+}
+
+// Total cost of every countermeasure that applies under the current config
+pub fn mitigation_cost(nodes: &[ApiFullComputedNodeData]) -> f64 {
+    nodes.iter()
+        .filter(|node| is_countermeasure(node) && node.conditionResolved)
+        .filter_map(|node| node.modelAttributes.get(COST_ATTRIBUTE).and_then(|attribute| attribute.as_f64()))
+        .map(|cost| cost.max(0.0))
+        .sum()
+}
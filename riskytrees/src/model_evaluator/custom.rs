@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use evalexpr::*;
+
+use crate::models::{ApiFullComputedNodeData, CustomModelDefinition, GateType, ModelAttribute, ModelAttributeSchema};
+use crate::model_evaluator::{AnalysisModel, ModelContext};
+
+// Wraps an org's CustomModelDefinition so it computes like the built-in models.
+// Formulas are plain evalexpr, separate from the condition language in
+// expression_evaluator, so they can't read config values. Formulas that fail
+// to evaluate (say a leaf is missing an attribute without a default) leave
+// that node out of the rollup rather than failing the request.
+pub struct FormulaModel {
+    pub definition: CustomModelDefinition
+}
+
+impl FormulaModel {
+    pub fn new(definition: CustomModelDefinition) -> FormulaModel {
+        FormulaModel {
+            definition: definition
+        }
+    }
+}
+
+impl AnalysisModel for FormulaModel {
+    fn id(&self) -> &str {
+        &self.definition.id
+    }
+
+    fn title(&self) -> &str {
+        &self.definition.title
+    }
+
+    fn attributes(&self) -> Vec<ModelAttributeSchema> {
+        self.definition.attributes.clone()
+    }
+
+    fn leaf_value(&self, attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+        let mut context = formula_context();
+
+        for (key, attribute) in attributes {
+            let value = match attribute.value_string {
+                Some(ref value) if attribute.value_float.is_none() && attribute.value_int.is_none() => match value.trim().parse::<f64>() {
+                    Ok(number) => Value::Float(number),
+                    Err(_) => Value::String(value.clone())
+                },
+                _ => match attribute.as_f64() {
+                    Some(number) => Value::Float(number),
+                    None => continue
+                }
+            };
+
+            if context.set_value(key.clone(), value).is_err() {
+                return None;
+            }
+        }
+
+        evaluate_number(&self.definition.leafFormula, &context)
+    }
+
+    fn combine(&self, gate: GateType, child_values: Vec<f64>) -> Option<f64> {
+        let mut context = formula_context();
+        let values = child_values.into_iter().map(Value::Float).collect();
+
+        context.set_value("values".to_owned(), Value::Tuple(values)).ok()?;
+        context.set_value("gate".to_owned(), Value::String(gate.as_str().to_owned())).ok()?;
+
+        evaluate_number(&self.definition.aggregationFormula, &context)
+    }
+
+    fn annotate(&self, _node: &ApiFullComputedNodeData, value: f64, _root: Option<&ApiFullComputedNodeData>, _context: &ModelContext) -> HashMap<String, ModelAttribute> {
+        let mut computed = HashMap::new();
+        computed.insert(self.definition.scoreAttribute.clone(), ModelAttribute::from_float(value));
+        computed
+    }
+
+    fn score_attribute(&self) -> &str {
+        &self.definition.scoreAttribute
+    }
+}
+
+// Checks a definition before it is stored. Attribute keys become formula
+// variables so they need to be plain identifiers.
+pub fn validate_definition(definition: &CustomModelDefinition) -> Result<(), String> {
+    if definition.title.trim().is_empty() {
+        return Err("Model title must not be empty".to_owned());
+    }
+
+    if definition.scoreAttribute.trim().is_empty() {
+        return Err("scoreAttribute must not be empty".to_owned());
+    }
+
+    for attribute in &definition.attributes {
+        let mut chars = attribute.key.chars();
+        let valid_key = match chars.next() {
+            Some(first) => (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            None => false
+        };
+
+        if !valid_key {
+            return Err(format!("Attribute key \"{}\" must be a plain identifier", attribute.key));
+        }
+
+        if attribute.key == "values" || attribute.key == "gate" {
+            return Err(format!("Attribute key \"{}\" is reserved", attribute.key));
+        }
+
+        if attribute.valueType != "number" && attribute.valueType != "string" {
+            return Err(format!("Attribute \"{}\" must have a valueType of number or string", attribute.key));
+        }
+
+        if let (Some(min), Some(max)) = (attribute.min, attribute.max) {
+            if min > max {
+                return Err(format!("Attribute \"{}\" has a min above its max", attribute.key));
+            }
+        }
+    }
+
+    if let Err(err) = build_operator_tree(&definition.leafFormula) {
+        return Err(format!("Could not parse leafFormula: {}", err));
+    }
+
+    if let Err(err) = build_operator_tree(&definition.aggregationFormula) {
+        return Err(format!("Could not parse aggregationFormula: {}", err));
+    }
+
+    Ok(())
+}
+
+// evalexpr's min and max already take tuples; sum, product and count round out
+// what aggregation formulas usually need.
+fn formula_context() -> HashMapContext {
+    let mut context = HashMapContext::new();
+
+    context.set_function("sum".to_owned(), Function::new(|argument| {
+        Ok(Value::Float(numbers(argument)?.into_iter().sum()))
+    })).expect("Function names are valid");
+    context.set_function("product".to_owned(), Function::new(|argument| {
+        Ok(Value::Float(numbers(argument)?.into_iter().product()))
+    })).expect("Function names are valid");
+    context.set_function("count".to_owned(), Function::new(|argument| {
+        Ok(Value::Int(numbers(argument)?.len() as i64))
+    })).expect("Function names are valid");
+
+    context
+}
+
+fn numbers(argument: &Value) -> EvalexprResult<Vec<f64>> {
+    match argument {
+        Value::Tuple(values) => values.iter().map(|value| value.as_number()).collect(),
+        Value::Empty => Ok(Vec::new()),
+        value => Ok(vec![value.as_number()?])
+    }
+}
+
+fn evaluate_number(formula: &str, context: &HashMapContext) -> Option<f64> {
+    match eval_with_context(formula, context) {
+        Ok(value) => value.as_number().ok().filter(|number| number.is_finite()),
+        Err(_) => None
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use crate::models::{ApiFullComputedNodeData, ApiFullNodeData, ApiNodeIssue, ModelAttribute};
+use crate::model_evaluator::cvss_v4;
+
+// A node's CVSS vector string and the base score derived from it. The score
+// is worked out from the vector whenever a tree is computed and only ever
+// returned in computedAttributes, so it can't go stale.
+pub const CVSS_ATTRIBUTE: &str = "cvss";
+pub const CVSS_BASE_SCORE_ATTRIBUTE: &str = "cvssBaseScore";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CvssVersion {
+    V3_0,
+    V3_1,
+    V4_0
+}
+
+// (metric, allowed values, part of the base metrics and so required)
+type MetricDefinition = (&'static str, &'static [&'static str], bool);
+
+const CVSS_V3_METRICS: [MetricDefinition; 22] = [
+    ("AV", &["N", "A", "L", "P"], true),
+    ("AC", &["L", "H"], true),
+    ("PR", &["N", "L", "H"], true),
+    ("UI", &["N", "R"], true),
+    ("S", &["U", "C"], true),
+    ("C", &["H", "L", "N"], true),
+    ("I", &["H", "L", "N"], true),
+    ("A", &["H", "L", "N"], true),
+    ("E", &["X", "U", "P", "F", "H"], false),
+    ("RL", &["X", "O", "T", "W", "U"], false),
+    ("RC", &["X", "U", "R", "C"], false),
+    ("CR", &["X", "L", "M", "H"], false),
+    ("IR", &["X", "L", "M", "H"], false),
+    ("AR", &["X", "L", "M", "H"], false),
+    ("MAV", &["X", "N", "A", "L", "P"], false),
+    ("MAC", &["X", "L", "H"], false),
+    ("MPR", &["X", "N", "L", "H"], false),
+    ("MUI", &["X", "N", "R"], false),
+    ("MS", &["X", "U", "C"], false),
+    ("MC", &["X", "N", "L", "H"], false),
+    ("MI", &["X", "N", "L", "H"], false),
+    ("MA", &["X", "N", "L", "H"], false)
+];
+
+const CVSS_V4_METRICS: [MetricDefinition; 32] = [
+    ("AV", &["N", "A", "L", "P"], true),
+    ("AC", &["L", "H"], true),
+    ("AT", &["N", "P"], true),
+    ("PR", &["N", "L", "H"], true),
+    ("UI", &["N", "P", "A"], true),
+    ("VC", &["H", "L", "N"], true),
+    ("VI", &["H", "L", "N"], true),
+    ("VA", &["H", "L", "N"], true),
+    ("SC", &["H", "L", "N"], true),
+    ("SI", &["H", "L", "N"], true),
+    ("SA", &["H", "L", "N"], true),
+    ("E", &["X", "A", "P", "U"], false),
+    ("CR", &["X", "H", "M", "L"], false),
+    ("IR", &["X", "H", "M", "L"], false),
+    ("AR", &["X", "H", "M", "L"], false),
+    ("MAV", &["X", "N", "A", "L", "P"], false),
+    ("MAC", &["X", "L", "H"], false),
+    ("MAT", &["X", "N", "P"], false),
+    ("MPR", &["X", "N", "L", "H"], false),
+    ("MUI", &["X", "N", "P", "A"], false),
+    ("MVC", &["X", "H", "L", "N"], false),
+    ("MVI", &["X", "H", "L", "N"], false),
+    ("MVA", &["X", "H", "L", "N"], false),
+    ("MSC", &["X", "H", "L", "N"], false),
+    ("MSI", &["X", "S", "H", "L", "N"], false),
+    ("MSA", &["X", "S", "H", "L", "N"], false),
+    ("S", &["X", "N", "P"], false),
+    ("AU", &["X", "N", "Y"], false),
+    ("R", &["X", "A", "U", "I"], false),
+    ("V", &["X", "D", "C"], false),
+    ("RE", &["X", "L", "M", "H"], false),
+    ("U", &["X", "Clear", "Green", "Amber", "Red"], false)
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CvssVector {
+    pub version: CvssVersion,
+    metrics: HashMap<String, String>
+}
+
+impl CvssVector {
+    // Accepts "CVSS:3.0/", "CVSS:3.1/" and "CVSS:4.0/" vectors. Every base
+    // metric has to be present, and no metric may appear twice.
+    pub fn parse(vector: &str) -> Result<CvssVector, String> {
+        let mut parts = vector.trim().split('/');
+
+        let (version, definitions): (CvssVersion, &[MetricDefinition]) = match parts.next() {
+            Some("CVSS:3.0") => (CvssVersion::V3_0, &CVSS_V3_METRICS),
+            Some("CVSS:3.1") => (CvssVersion::V3_1, &CVSS_V3_METRICS),
+            Some("CVSS:4.0") => (CvssVersion::V4_0, &CVSS_V4_METRICS),
+            _ => return Err("CVSS vectors must start with CVSS:3.0, CVSS:3.1 or CVSS:4.0".to_owned())
+        };
+
+        let mut metrics = HashMap::new();
+        for part in parts {
+            let (metric, value) = match part.find(':') {
+                Some(index) => (&part[..index], &part[index + 1..]),
+                None => return Err(format!("\"{}\" is not a metric:value pair", part))
+            };
+
+            let allowed_values = match definitions.iter().find(|(name, _, _)| *name == metric) {
+                Some((_, allowed_values, _)) => allowed_values,
+                None => return Err(format!("{} is not a CVSS metric", metric))
+            };
+
+            if !allowed_values.contains(&value) {
+                return Err(format!("{} is not a valid value for {}", value, metric));
+            }
+
+            if metrics.insert(metric.to_owned(), value.to_owned()).is_some() {
+                return Err(format!("{} is given more than once", metric));
+            }
+        }
+
+        for (metric, _, required) in definitions.iter() {
+            if *required && !metrics.contains_key(*metric) {
+                return Err(format!("Missing base metric {}", metric));
+            }
+        }
+
+        Ok(CvssVector {
+            version: version,
+            metrics: metrics
+        })
+    }
+
+    fn metric(&self, metric: &str) -> &str {
+        self.metrics.get(metric).map_or("", |value| value.as_str())
+    }
+
+    // The 0-10 score. For v4.0 that takes in any threat and environmental
+    // metrics given alongside the base metrics.
+    pub fn base_score(&self) -> f64 {
+        match self.version {
+            CvssVersion::V3_0 | CvssVersion::V3_1 => self.v3_base_score(),
+            CvssVersion::V4_0 => cvss_v4::score(|metric| self.v4_metric(metric))
+        }
+    }
+
+    // A v4.0 metric's effective value. Modified metrics replace the base ones,
+    // and unset threat and requirement metrics count as the worst case.
+    fn v4_metric(&self, metric: &str) -> &str {
+        match (metric, self.metric(metric)) {
+            ("E", "") | ("E", "X") => return "A",
+            ("CR", "") | ("CR", "X") | ("IR", "") | ("IR", "X") | ("AR", "") | ("AR", "X") => return "H",
+            _ => {}
+        }
+
+        match self.metrics.get(&format!("M{}", metric)) {
+            Some(modified) if modified != "X" => modified,
+            _ => self.metric(metric)
+        }
+    }
+
+    fn v3_base_score(&self) -> f64 {
+        let scope_changed = self.metric("S") == "C";
+
+        let attack_vector: f64 = match self.metric("AV") {
+            "N" => 0.85,
+            "A" => 0.62,
+            "L" => 0.55,
+            _ => 0.2
+        };
+        let attack_complexity = if self.metric("AC") == "L" { 0.77 } else { 0.44 };
+        let privileges_required = match (self.metric("PR"), scope_changed) {
+            ("N", _) => 0.85,
+            ("L", false) => 0.62,
+            ("L", true) => 0.68,
+            (_, false) => 0.27,
+            (_, true) => 0.5
+        };
+        let user_interaction = if self.metric("UI") == "N" { 0.85 } else { 0.62 };
+        let cia = |metric: &str| -> f64 {
+            match self.metric(metric) {
+                "H" => 0.56,
+                "L" => 0.22,
+                _ => 0.0
+            }
+        };
+
+        let impact_sub_score = 1.0 - (1.0 - cia("C")) * (1.0 - cia("I")) * (1.0 - cia("A"));
+        let impact = if scope_changed {
+            7.52 * (impact_sub_score - 0.029) - 3.25 * (impact_sub_score - 0.02).powi(15)
+        } else {
+            6.42 * impact_sub_score
+        };
+        let exploitability = 8.22 * attack_vector * attack_complexity * privileges_required * user_interaction;
+
+        if impact <= 0.0 {
+            0.0
+        } else if scope_changed {
+            self.round_up((1.08 * (impact + exploitability)).min(10.0))
+        } else {
+            self.round_up((impact + exploitability).min(10.0))
+        }
+    }
+
+    // v3.1 redefined Roundup to avoid floating point surprises like 4.000001
+    // rounding to 4.1
+    fn round_up(&self, value: f64) -> f64 {
+        match self.version {
+            CvssVersion::V3_0 => (value * 10.0).ceil() / 10.0,
+            _ => {
+                let int_input = (value * 100000.0).round() as i64;
+                if int_input % 10000 == 0 {
+                    int_input as f64 / 100000.0
+                } else {
+                    ((int_input / 10000) + 1) as f64 / 10.0
+                }
+            }
+        }
+    }
+}
+
+// The score of the node's vector, if it has a valid one
+pub fn base_score(attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+    attributes.get(CVSS_ATTRIBUTE)
+        .and_then(|attribute| attribute.value_string.as_ref())
+        .and_then(|vector| CvssVector::parse(vector).ok())
+        .map(|vector| vector.base_score())
+}
+
+// Adds cvssBaseScore to the computedAttributes of every node with a valid
+// vector
+pub fn annotate_base_scores(nodes: &mut [ApiFullComputedNodeData]) {
+    for node in nodes {
+        if let Some(score) = base_score(&node.modelAttributes) {
+            node.computedAttributes.insert(CVSS_BASE_SCORE_ATTRIBUTE.to_owned(), ModelAttribute::from_float(score));
+        }
+    }
+}
+
+// Drops a cvssBaseScore from stored attributes. It's derived rather than
+// entered, and trees saved before it moved to computedAttributes still carry
+// one.
+pub fn strip_base_score(attributes: &mut HashMap<String, ModelAttribute>) {
+    attributes.remove(CVSS_BASE_SCORE_ATTRIBUTE);
+}
+
+// Checks every node's CVSS vector before a tree is saved, returning the
+// invalid ones
+pub fn validate_tree_vectors(nodes: &[ApiFullNodeData]) -> Vec<ApiNodeIssue> {
+    let mut errors = Vec::new();
+
+    for node in nodes {
+        let attribute = match node.modelAttributes.get(CVSS_ATTRIBUTE) {
+            Some(attribute) => attribute,
+            None => continue
+        };
+
+        let issue = |message: String| ApiNodeIssue {
+            nodeId: node.id.clone(),
+            field: format!("modelAttributes.{}", CVSS_ATTRIBUTE),
+            message: message
+        };
+
+        match attribute.value_string.as_ref().map(|vector| CvssVector::parse(vector)) {
+            Some(Ok(_)) => {},
+            Some(Err(err)) => errors.push(issue(err)),
+            None => errors.push(issue("Expected a CVSS vector string".to_owned()))
+        }
+    }
+
+    errors
+}
@@ -0,0 +1,247 @@
+// CVSS v4.0 scoring. v4.0 has no closed formula: the metrics are sorted into
+// six equivalence classes (EQ1-EQ6), the resulting macro vector's score is
+// looked up in FIRST's table, and that score is lowered by how far the vector
+// sits below the most severe vectors of its macro vector. This follows FIRST's
+// reference calculator, including its choice of neighbouring macro vectors.
+
+// The score of every macro vector, keyed by its EQ1 to EQ6 levels
+const MACRO_VECTOR_SCORES: [(&str, f64); 270] = [
+    ("000000", 10.0), ("000001", 9.9), ("000010", 9.8), ("000011", 9.5), ("000020", 9.5), ("000021", 9.2),
+    ("000100", 10.0), ("000101", 9.6), ("000110", 9.3), ("000111", 8.7), ("000120", 9.1), ("000121", 8.1),
+    ("000200", 9.3), ("000201", 9.0), ("000210", 8.9), ("000211", 8.0), ("000220", 8.1), ("000221", 6.8),
+    ("001000", 9.8), ("001001", 9.5), ("001010", 9.5), ("001011", 9.2), ("001020", 9.0), ("001021", 8.4),
+    ("001100", 9.3), ("001101", 9.2), ("001110", 8.9), ("001111", 8.1), ("001120", 8.1), ("001121", 6.5),
+    ("001200", 8.8), ("001201", 8.0), ("001210", 7.8), ("001211", 7.0), ("001220", 6.9), ("001221", 4.8),
+    ("002001", 9.2), ("002011", 8.2), ("002021", 7.2), ("002101", 7.9), ("002111", 6.9), ("002121", 5.0),
+    ("002201", 6.9), ("002211", 5.5), ("002221", 2.7),
+    ("010000", 9.9), ("010001", 9.7), ("010010", 9.5), ("010011", 9.2), ("010020", 9.2), ("010021", 8.5),
+    ("010100", 9.5), ("010101", 9.1), ("010110", 9.0), ("010111", 8.3), ("010120", 8.4), ("010121", 7.1),
+    ("010200", 9.2), ("010201", 8.1), ("010210", 8.2), ("010211", 7.1), ("010220", 7.2), ("010221", 5.3),
+    ("011000", 9.5), ("011001", 9.3), ("011010", 9.2), ("011011", 8.5), ("011020", 8.5), ("011021", 7.3),
+    ("011100", 9.2), ("011101", 8.2), ("011110", 8.0), ("011111", 7.2), ("011120", 7.0), ("011121", 5.9),
+    ("011200", 8.4), ("011201", 7.0), ("011210", 7.1), ("011211", 5.2), ("011220", 5.0), ("011221", 3.0),
+    ("012001", 8.6), ("012011", 7.5), ("012021", 5.2), ("012101", 7.1), ("012111", 5.2), ("012121", 2.9),
+    ("012201", 6.3), ("012211", 2.9), ("012221", 1.7),
+    ("100000", 9.8), ("100001", 9.5), ("100010", 9.4), ("100011", 8.7), ("100020", 9.1), ("100021", 8.1),
+    ("100100", 9.4), ("100101", 8.9), ("100110", 8.6), ("100111", 7.4), ("100120", 7.7), ("100121", 6.4),
+    ("100200", 8.7), ("100201", 7.5), ("100210", 7.4), ("100211", 6.3), ("100220", 6.3), ("100221", 4.9),
+    ("101000", 9.4), ("101001", 8.9), ("101010", 8.8), ("101011", 7.7), ("101020", 7.6), ("101021", 6.7),
+    ("101100", 8.6), ("101101", 7.6), ("101110", 7.4), ("101111", 5.8), ("101120", 5.9), ("101121", 5.0),
+    ("101200", 7.2), ("101201", 5.7), ("101210", 5.7), ("101211", 5.2), ("101220", 5.2), ("101221", 2.5),
+    ("102001", 8.3), ("102011", 7.0), ("102021", 5.4), ("102101", 6.5), ("102111", 5.8), ("102121", 2.6),
+    ("102201", 5.3), ("102211", 2.1), ("102221", 1.3),
+    ("110000", 9.5), ("110001", 9.0), ("110010", 8.8), ("110011", 7.6), ("110020", 7.6), ("110021", 7.0),
+    ("110100", 9.0), ("110101", 7.7), ("110110", 7.5), ("110111", 6.2), ("110120", 6.1), ("110121", 5.3),
+    ("110200", 7.7), ("110201", 6.6), ("110210", 6.8), ("110211", 5.9), ("110220", 5.2), ("110221", 3.0),
+    ("111000", 8.9), ("111001", 7.8), ("111010", 7.6), ("111011", 6.7), ("111020", 6.2), ("111021", 5.8),
+    ("111100", 7.4), ("111101", 5.9), ("111110", 5.7), ("111111", 5.7), ("111120", 4.7), ("111121", 2.3),
+    ("111200", 6.1), ("111201", 5.2), ("111210", 5.7), ("111211", 2.9), ("111220", 2.4), ("111221", 1.6),
+    ("112001", 7.1), ("112011", 5.9), ("112021", 3.0), ("112101", 5.8), ("112111", 2.6), ("112121", 1.5),
+    ("112201", 2.3), ("112211", 1.3), ("112221", 0.6),
+    ("200000", 9.3), ("200001", 8.7), ("200010", 8.6), ("200011", 7.2), ("200020", 7.5), ("200021", 5.8),
+    ("200100", 8.6), ("200101", 7.4), ("200110", 7.4), ("200111", 6.1), ("200120", 5.6), ("200121", 3.4),
+    ("200200", 7.0), ("200201", 5.4), ("200210", 5.2), ("200211", 4.0), ("200220", 4.0), ("200221", 2.2),
+    ("201000", 8.5), ("201001", 7.5), ("201010", 7.4), ("201011", 5.5), ("201020", 6.2), ("201021", 5.1),
+    ("201100", 7.2), ("201101", 5.7), ("201110", 5.5), ("201111", 4.1), ("201120", 4.6), ("201121", 1.9),
+    ("201200", 5.3), ("201201", 3.6), ("201210", 3.4), ("201211", 1.9), ("201220", 1.9), ("201221", 0.8),
+    ("202001", 6.4), ("202011", 5.1), ("202021", 2.0), ("202101", 4.7), ("202111", 2.1), ("202121", 1.1),
+    ("202201", 2.4), ("202211", 0.9), ("202221", 0.4),
+    ("210000", 8.8), ("210001", 7.5), ("210010", 7.3), ("210011", 5.3), ("210020", 6.0), ("210021", 5.0),
+    ("210100", 7.3), ("210101", 5.5), ("210110", 5.9), ("210111", 4.0), ("210120", 4.1), ("210121", 2.0),
+    ("210200", 5.4), ("210201", 4.3), ("210210", 4.5), ("210211", 2.2), ("210220", 2.0), ("210221", 1.1),
+    ("211000", 7.5), ("211001", 5.5), ("211010", 5.8), ("211011", 4.5), ("211020", 4.0), ("211021", 2.1),
+    ("211100", 6.1), ("211101", 5.1), ("211110", 4.8), ("211111", 1.8), ("211120", 2.0), ("211121", 0.9),
+    ("211200", 4.6), ("211201", 1.8), ("211210", 1.7), ("211211", 0.7), ("211220", 0.8), ("211221", 0.2),
+    ("212001", 5.3), ("212011", 2.4), ("212021", 1.4), ("212101", 2.4), ("212111", 1.2), ("212121", 0.5),
+    ("212201", 1.0), ("212211", 0.3), ("212221", 0.1)
+];
+
+// The most severe vectors of each equivalence class level. EQ3 and EQ6 are
+// scored together, so their entries are keyed by both levels.
+const EQ1_MAXES: [&[&str]; 3] = [
+    &["AV:N/PR:N/UI:N"],
+    &["AV:A/PR:N/UI:N", "AV:N/PR:L/UI:N", "AV:N/PR:N/UI:P"],
+    &["AV:P/PR:N/UI:N", "AV:A/PR:L/UI:P"]
+];
+const EQ2_MAXES: [&[&str]; 2] = [
+    &["AC:L/AT:N"],
+    &["AC:H/AT:N", "AC:L/AT:P"]
+];
+const EQ3_EQ6_MAXES: [((usize, usize), &[&str]); 5] = [
+    ((0, 0), &["VC:H/VI:H/VA:H/CR:H/IR:H/AR:H"]),
+    ((0, 1), &["VC:H/VI:H/VA:L/CR:M/IR:M/AR:H", "VC:H/VI:H/VA:H/CR:M/IR:M/AR:M"]),
+    ((1, 0), &["VC:L/VI:H/VA:H/CR:H/IR:H/AR:H", "VC:H/VI:L/VA:H/CR:H/IR:H/AR:H"]),
+    ((1, 1), &[
+        "VC:L/VI:H/VA:L/CR:H/IR:M/AR:H",
+        "VC:L/VI:H/VA:H/CR:H/IR:M/AR:M",
+        "VC:H/VI:L/VA:H/CR:M/IR:H/AR:M",
+        "VC:H/VI:L/VA:L/CR:M/IR:H/AR:H",
+        "VC:L/VI:L/VA:H/CR:H/IR:H/AR:M"
+    ]),
+    ((2, 1), &["VC:L/VI:L/VA:L/CR:H/IR:H/AR:H"])
+];
+const EQ4_MAXES: [&str; 3] = ["SC:H/SI:S/SA:S", "SC:H/SI:H/SA:H", "SC:L/SI:L/SA:L"];
+
+// How many 0.1 severity steps separate a level's most and least severe vectors
+const EQ1_DEPTHS: [f64; 3] = [1.0, 4.0, 5.0];
+const EQ2_DEPTHS: [f64; 2] = [1.0, 2.0];
+const EQ4_DEPTHS: [f64; 3] = [6.0, 5.0, 4.0];
+
+const DISTANCE_METRICS: [&str; 14] = ["AV", "PR", "UI", "AC", "AT", "VC", "VI", "VA", "SC", "SI", "SA", "CR", "IR", "AR"];
+
+// Scores a vector given its effective value for each metric: modified
+// metrics already applied, and unset threat and requirement metrics at their
+// worst case
+pub fn score<'a, M>(metric: M) -> f64
+where
+    M: Fn(&str) -> &'a str
+{
+    if ["VC", "VI", "VA", "SC", "SI", "SA"].iter().all(|name| metric(name) == "N") {
+        return 0.0;
+    }
+
+    let is = |name: &str, value: &str| metric(name) == value;
+
+    let eq1 = if is("AV", "N") && is("PR", "N") && is("UI", "N") {
+        0
+    } else if (is("AV", "N") || is("PR", "N") || is("UI", "N")) && !is("AV", "P") {
+        1
+    } else {
+        2
+    };
+    let eq2 = if is("AC", "L") && is("AT", "N") { 0 } else { 1 };
+    let eq3 = if is("VC", "H") && is("VI", "H") {
+        0
+    } else if is("VC", "H") || is("VI", "H") || is("VA", "H") {
+        1
+    } else {
+        2
+    };
+    let eq4 = if is("SI", "S") || is("SA", "S") {
+        0
+    } else if is("SC", "H") || is("SI", "H") || is("SA", "H") {
+        1
+    } else {
+        2
+    };
+    let eq5 = match metric("E") {
+        "A" => 0,
+        "P" => 1,
+        _ => 2
+    };
+    let eq6 = if (is("CR", "H") && is("VC", "H")) || (is("IR", "H") && is("VI", "H")) || (is("AR", "H") && is("VA", "H")) { 0 } else { 1 };
+
+    let value = match lookup([eq1, eq2, eq3, eq4, eq5, eq6]) {
+        Some(value) => value,
+        None => return 0.0
+    };
+
+    // The scores one step less severe in each class, where that macro vector
+    // exists. EQ3 and EQ6 step together; from 00 either may step, and the
+    // higher of the two is used.
+    let eq3_eq6_lower = match (eq3, eq6) {
+        (0, 0) => match (lookup([eq1, eq2, eq3, eq4, eq5, eq6 + 1]), lookup([eq1, eq2, eq3 + 1, eq4, eq5, eq6])) {
+            (Some(left), Some(right)) if left > right => Some(left),
+            (_, right) => right
+        },
+        (0, 1) | (1, 1) => lookup([eq1, eq2, eq3 + 1, eq4, eq5, eq6]),
+        (1, 0) => lookup([eq1, eq2, eq3, eq4, eq5, eq6 + 1]),
+        _ => None
+    };
+    let lower = [
+        lookup([eq1 + 1, eq2, eq3, eq4, eq5, eq6]),
+        lookup([eq1, eq2 + 1, eq3, eq4, eq5, eq6]),
+        eq3_eq6_lower,
+        lookup([eq1, eq2, eq3, eq4 + 1, eq5, eq6]),
+        lookup([eq1, eq2, eq3, eq4, eq5 + 1, eq6])
+    ];
+
+    // How far the vector sits below the first of its macro vector's most
+    // severe vectors that it doesn't exceed anywhere
+    let eq3_eq6_maxes = EQ3_EQ6_MAXES.iter()
+        .find(|(levels, _)| *levels == (eq3, eq6))
+        .map_or(&[][..], |(_, maxes)| *maxes);
+    let mut distances = None;
+    'search: for eq1_max in EQ1_MAXES[eq1] {
+        for eq2_max in EQ2_MAXES[eq2] {
+            for eq3_eq6_max in eq3_eq6_maxes {
+                let max_vector = [*eq1_max, *eq2_max, *eq3_eq6_max, EQ4_MAXES[eq4]].join("/");
+                let candidate: Vec<f64> = DISTANCE_METRICS.iter()
+                    .map(|name| severity(name, metric(name)) - severity(name, max_value(&max_vector, name)))
+                    .collect();
+
+                if candidate.iter().all(|distance| *distance >= 0.0) {
+                    distances = Some(candidate);
+                    break 'search;
+                }
+            }
+        }
+    }
+    let distances = distances.unwrap_or_else(|| vec![0.0; DISTANCE_METRICS.len()]);
+    let distance = |names: &[&str]| -> f64 {
+        names.iter()
+            .filter_map(|name| DISTANCE_METRICS.iter().position(|metric| metric == name))
+            .map(|index| distances[index])
+            .sum()
+    };
+
+    let eq3_eq6_depth = match (eq3, eq6) {
+        (0, 0) => 7.0,
+        (0, 1) => 6.0,
+        (2, _) => 10.0,
+        _ => 8.0
+    };
+    // EQ5 has a single vector per level, so never contributes a distance
+    let proportions = [
+        distance(&["AV", "PR", "UI"]) / (EQ1_DEPTHS[eq1] * 0.1),
+        distance(&["AC", "AT"]) / (EQ2_DEPTHS[eq2] * 0.1),
+        distance(&["VC", "VI", "VA", "CR", "IR", "AR"]) / (eq3_eq6_depth * 0.1),
+        distance(&["SC", "SI", "SA"]) / (EQ4_DEPTHS[eq4] * 0.1),
+        0.0
+    ];
+
+    let adjustments: Vec<f64> = lower.iter()
+        .zip(proportions.iter())
+        .filter_map(|(lower, proportion)| lower.map(|lower| (value - lower) * proportion))
+        .collect();
+    let mean_adjustment = if adjustments.is_empty() {
+        0.0
+    } else {
+        adjustments.iter().sum::<f64>() / adjustments.len() as f64
+    };
+
+    ((value - mean_adjustment).clamp(0.0, 10.0) * 10.0).round() / 10.0
+}
+
+fn lookup(levels: [usize; 6]) -> Option<f64> {
+    let key: String = levels.iter().map(|level| level.to_string()).collect();
+
+    MACRO_VECTOR_SCORES.iter()
+        .find(|(macro_vector, _)| *macro_vector == key)
+        .map(|(_, score)| *score)
+}
+
+fn max_value<'a>(vector: &'a str, name: &str) -> &'a str {
+    vector.split('/')
+        .find_map(|part| part.strip_prefix(name).and_then(|rest| rest.strip_prefix(':')))
+        .unwrap_or("")
+}
+
+// Each value's severity in 0.1 steps, most severe first
+fn severity(name: &str, value: &str) -> f64 {
+    let levels: &[&str] = match name {
+        "AV" => &["N", "A", "L", "P"],
+        "PR" => &["N", "L", "H"],
+        "UI" => &["N", "P", "A"],
+        "AC" => &["L", "H"],
+        "AT" => &["N", "P"],
+        "VC" | "VI" | "VA" => &["H", "L", "N"],
+        // Subsequent system impacts start a step down, leaving room for
+        // Safety, which only SI and SA can take
+        "SC" => &["", "H", "L", "N"],
+        "SI" | "SA" => &["S", "H", "L", "N"],
+        _ => &["H", "M", "L"]
+    };
+
+    levels.iter().position(|level| *level == value).unwrap_or(0) as f64 * 0.1
+}
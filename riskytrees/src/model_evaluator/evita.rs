@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use crate::constants;
+use crate::models::{ApiFullComputedNodeData, GateType, ModelAttribute, ModelAttributeSchema};
+use crate::model_evaluator::{AnalysisModel, ModelContext};
+
+pub const EVITA_RISK_LEVEL_ATTRIBUTE: &str = "riskLevel";
+
+pub const EVITA_ATTACK_POTENTIAL_FACTORS: [&str; 5] = ["elapsedTime", "expertise", "knowledgeOfTarget", "windowOfOpportunity", "equipment"];
+pub const EVITA_SEVERITY_ATTRIBUTES: [&str; 4] = ["safetySeverity", "privacySeverity", "financialSeverity", "operationalSeverity"];
+
+// ISO/SAE 21434 attack potential categories and their points, per factor
+const EVITA_FACTOR_CATEGORIES: [(&str, &str, i32); 21] = [
+    ("elapsedTime", "one day", 0),
+    ("elapsedTime", "one week", 1),
+    ("elapsedTime", "one month", 4),
+    ("elapsedTime", "six months", 17),
+    ("elapsedTime", "more than six months", 19),
+    ("expertise", "layman", 0),
+    ("expertise", "proficient", 3),
+    ("expertise", "expert", 6),
+    ("expertise", "multiple experts", 8),
+    ("knowledgeOfTarget", "public", 0),
+    ("knowledgeOfTarget", "restricted", 3),
+    ("knowledgeOfTarget", "confidential", 7),
+    ("knowledgeOfTarget", "strictly confidential", 11),
+    ("windowOfOpportunity", "unlimited", 0),
+    ("windowOfOpportunity", "easy", 1),
+    ("windowOfOpportunity", "moderate", 4),
+    ("windowOfOpportunity", "difficult", 10),
+    ("equipment", "standard", 0),
+    ("equipment", "specialized", 4),
+    ("equipment", "bespoke", 7),
+    ("equipment", "multiple bespoke", 9)
+];
+
+// EVITA (as adopted by ISO/SAE 21434): leaves rate the five attack potential
+// factors, which sum to the attack potential. The easiest child wins an OR gate
+// and the hardest child bounds an AND/SAND gate. Each node's attack potential
+// maps to an attack probability (1-5) which is combined with the severity
+// ratings (0-4) to give a risk level (0-6) per node. Nodes without their own
+// severity ratings use the root's, since that is the attacker's goal.
+pub struct Evita;
+
+impl AnalysisModel for Evita {
+    fn id(&self) -> &str {
+        constants::EVITA_MODEL_ID
+    }
+
+    fn title(&self) -> &str {
+        "EVITA"
+    }
+
+    fn attributes(&self) -> Vec<ModelAttributeSchema> {
+        let mut attributes = Vec::new();
+
+        for factor in EVITA_ATTACK_POTENTIAL_FACTORS.iter() {
+            let mut attribute = ModelAttributeSchema::number(factor, Some(0.0), None);
+            attribute.allowedValues = Some(EVITA_FACTOR_CATEGORIES.iter()
+                .filter(|(category_factor, _, _)| category_factor == factor)
+                .map(|(_, category, _)| category.to_string())
+                .collect());
+            attributes.push(attribute);
+        }
+
+        for severity in EVITA_SEVERITY_ATTRIBUTES.iter() {
+            attributes.push(ModelAttributeSchema::number(severity, Some(0.0), Some(4.0)));
+        }
+
+        attributes
+    }
+
+    fn leaf_value(&self, attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+        leaf_attack_potential(attributes)
+    }
+
+    fn combine(&self, gate: GateType, child_values: Vec<f64>) -> Option<f64> {
+        match gate {
+            GateType::Or => Some(child_values.into_iter().fold(f64::INFINITY, f64::min)),
+            GateType::And | GateType::SequentialAnd => Some(child_values.into_iter().fold(0.0, f64::max))
+        }
+    }
+
+    fn annotate(&self, node: &ApiFullComputedNodeData, value: f64, root: Option<&ApiFullComputedNodeData>, _context: &ModelContext) -> HashMap<String, ModelAttribute> {
+        let mut computed = HashMap::new();
+        let attack_potential = value as i32;
+        let attack_probability = evita_attack_probability(attack_potential);
+
+        computed.insert("attackPotential".to_owned(), ModelAttribute::from_int(attack_potential));
+        computed.insert("attackProbability".to_owned(), ModelAttribute::from_int(attack_probability));
+
+        if let Some(severities) = evita_severities(node).or(root.and_then(evita_severities)) {
+            let risk_level = severities.iter()
+                .map(|severity| evita_risk_level(*severity, attack_probability))
+                .max()
+                .unwrap_or(0);
+
+            computed.insert(EVITA_RISK_LEVEL_ATTRIBUTE.to_owned(), ModelAttribute::from_int(risk_level));
+        }
+
+        computed
+    }
+
+    fn score_attribute(&self) -> &str {
+        EVITA_RISK_LEVEL_ATTRIBUTE
+    }
+
+    // Countermeasures make an attack harder rather than less likely, so they
+    // raise the required attack potential towards "beyond high"
+    fn mitigate(&self, value: f64, effectiveness: f64) -> f64 {
+        value + effectiveness * (EVITA_BEYOND_HIGH_POTENTIAL - value).max(0.0)
+    }
+}
+
+// Points for a single attack potential factor. Numbers are taken as points
+// directly, otherwise the ISO/SAE 21434 category names are accepted.
+pub fn evita_factor_points(factor: &str, attribute: &ModelAttribute) -> Option<i32> {
+    if let Some(points) = attribute.as_f64() {
+        return Some(points.max(0.0) as i32);
+    }
+
+    let category = attribute.value_string.as_ref()?.trim().to_lowercase();
+
+    EVITA_FACTOR_CATEGORIES.iter()
+        .find(|(category_factor, category_name, _)| *category_factor == factor && *category_name == category)
+        .map(|(_, _, points)| *points)
+}
+
+const EVITA_BEYOND_HIGH_POTENTIAL: f64 = 25.0;
+
+// Required attack potential -> attack probability, from basic (5) to beyond
+// high (1).
+pub fn evita_attack_probability(attack_potential: i32) -> i32 {
+    match attack_potential {
+        i32::MIN..=9 => 5,
+        10..=13 => 4,
+        14..=19 => 3,
+        20..=24 => 2,
+        _ => 1
+    }
+}
+
+// EVITA risk graph: R0 for no severity, otherwise severity + probability - 3
+// kept within R0..R6.
+pub fn evita_risk_level(severity: i32, attack_probability: i32) -> i32 {
+    if severity <= 0 {
+        0
+    } else {
+        (severity.min(4) + attack_probability - 3).clamp(0, 6)
+    }
+}
+
+fn evita_severities(node: &ApiFullComputedNodeData) -> Option<[i32; 4]> {
+    let mut severities = [0; 4];
+    let mut found = false;
+
+    for (index, key) in EVITA_SEVERITY_ATTRIBUTES.iter().enumerate() {
+        if let Some(severity) = node.modelAttributes.get(*key).and_then(|attribute| attribute.as_f64()) {
+            severities[index] = severity.clamp(0.0, 4.0) as i32;
+            found = true;
+        }
+    }
+
+    if found {
+        Some(severities)
+    } else {
+        None
+    }
+}
+
+// Leaves with none of the factors rated are left out; unrated factors on a
+// partially rated leaf count as zero.
+fn leaf_attack_potential(attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+    let mut total = 0;
+    let mut found = false;
+
+    for factor in EVITA_ATTACK_POTENTIAL_FACTORS.iter() {
+        if let Some(points) = attributes.get(*factor).and_then(|attribute| evita_factor_points(factor, attribute)) {
+            total += points;
+            found = true;
+        }
+    }
+
+    if found {
+        Some(f64::from(total))
+    } else {
+        None
+    }
+}
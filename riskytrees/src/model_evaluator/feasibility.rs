@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{self, ApiFullComputedNodeData, ApiFullComputedTreeData, AttackerProfile, GateType};
+use crate::model_evaluator::{self, countermeasure, timed, LinkedTree};
+use crate::tree_analysis::paths::COST_ATTRIBUTE;
+
+// Leaf modelAttributes describing what a step demands of the attacker. Cost
+// and time are the same estimates attack paths use.
+pub const SKILL_ATTRIBUTE: &str = "skill";
+pub const ACCESS_ATTRIBUTE: &str = "access";
+
+// Marks every node the profile can't carry out as infeasible. Leaves are
+// infeasible when any requirement exceeds the profile's limits; above them an
+// OR gate needs one feasible child and an AND/SAND gate needs all of them.
+// Unresolved nodes, countermeasures and children outside of the given trees
+// are ignored, as they are in model rollups, which then skip infeasible nodes.
+pub fn apply_profile(tree: &mut ApiFullComputedTreeData, linked: &mut [LinkedTree], profile: &AttackerProfile) {
+    let nodes = model_evaluator::combined_nodes(tree, linked);
+    let node_indices = model_evaluator::index_nodes(&nodes);
+    let mut results = HashMap::new();
+
+    for node in &nodes {
+        feasible_node(&nodes, &node_indices, &node.id, profile, &mut results, &mut HashSet::new());
+    }
+
+    let all_nodes = tree.nodes.iter_mut()
+        .chain(linked.iter_mut().flat_map(|linked_tree| linked_tree.nodes.iter_mut()));
+    for node in all_nodes {
+        node.infeasible = results.get(&node.id) == Some(&Some(false));
+    }
+}
+
+fn feasible_node(
+    nodes: &[ApiFullComputedNodeData],
+    node_indices: &HashMap<String, usize>,
+    node_id: &String,
+    profile: &AttackerProfile,
+    results: &mut HashMap<String, Option<bool>>,
+    visiting: &mut HashSet<String>
+) -> Option<bool> {
+    if let Some(result) = results.get(node_id) {
+        return *result;
+    }
+
+    let node = &nodes[*node_indices.get(node_id)?];
+
+    if !node.conditionResolved || countermeasure::is_countermeasure(node) || visiting.contains(node_id) {
+        return None;
+    }
+
+    visiting.insert(node_id.clone());
+    let mut child_results = Vec::new();
+    for child in &node.children {
+        if let Some(feasible) = feasible_node(nodes, node_indices, child, profile, results, visiting) {
+            child_results.push(feasible);
+        }
+    }
+    visiting.remove(node_id);
+
+    let feasible = if child_results.is_empty() {
+        within_limits(node, profile)
+    } else {
+        match node.gateType {
+            GateType::Or => child_results.into_iter().any(|feasible| feasible),
+            GateType::And | GateType::SequentialAnd => child_results.into_iter().all(|feasible| feasible)
+        }
+    };
+
+    results.insert(node_id.clone(), Some(feasible));
+    Some(feasible)
+}
+
+// Requirements a leaf doesn't state, and limits the profile doesn't set, never
+// rule a leaf out
+pub fn within_limits(node: &ApiFullComputedNodeData, profile: &AttackerProfile) -> bool {
+    let required = |key: &str| node.modelAttributes.get(key).and_then(|attribute| attribute.as_f64());
+    let exceeds = |required: Option<f64>, limit: Option<f64>| match (required, limit) {
+        (Some(required), Some(limit)) => required > limit,
+        _ => false
+    };
+
+    let required_access = node.modelAttributes.get(ACCESS_ATTRIBUTE)
+        .and_then(|attribute| attribute.value_string.as_ref())
+        .and_then(|access| models::access_level(access));
+    let profile_access = profile.access.as_ref().and_then(|access| models::access_level(access));
+    let exceeds_access = match (required_access, profile_access) {
+        (Some(required), Some(limit)) => required > limit,
+        _ => false
+    };
+
+    // A leaf with a time range only has to fit at its quickest
+    let required_time = timed::leaf_time_range(node).map(|(min, _)| min);
+
+    !(exceeds(required(SKILL_ATTRIBUTE), profile.skill)
+        || exceeds(required(COST_ATTRIBUTE), profile.budget)
+        || exceeds(required_time, profile.time)
+        || exceeds_access)
+}
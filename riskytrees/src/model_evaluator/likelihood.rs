@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::constants;
+use crate::models::{ApiFullComputedNodeData, GateType, ModelAttribute, ModelAttributeSchema};
+use crate::model_evaluator::{AnalysisModel, ModelContext};
+use crate::model_evaluator::cvss;
+
+pub const LIKELIHOOD_ATTRIBUTE: &str = "likelihood";
+
+// Attacker Likelihood: leaves carry a "likelihood" between 0 and 1. OR gates
+// take the most likely child while AND/SAND gates need every child to succeed,
+// so they multiply. Leaves without an estimate don't contribute.
+pub struct AttackerLikelihood;
+
+impl AnalysisModel for AttackerLikelihood {
+    fn id(&self) -> &str {
+        constants::ATTACKER_LIKELIHOOD_MODEL_ID
+    }
+
+    fn title(&self) -> &str {
+        "Attacker Likelihood"
+    }
+
+    fn attributes(&self) -> Vec<ModelAttributeSchema> {
+        vec![ModelAttributeSchema::number(LIKELIHOOD_ATTRIBUTE, Some(0.0), Some(1.0))]
+    }
+
+    fn leaf_value(&self, attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+        leaf_likelihood(attributes)
+    }
+
+    fn combine(&self, gate: GateType, child_values: Vec<f64>) -> Option<f64> {
+        Some(combine_likelihoods(gate, child_values))
+    }
+
+    fn annotate(&self, _node: &ApiFullComputedNodeData, value: f64, _root: Option<&ApiFullComputedNodeData>, _context: &ModelContext) -> HashMap<String, ModelAttribute> {
+        let mut computed = HashMap::new();
+        computed.insert(LIKELIHOOD_ATTRIBUTE.to_owned(), ModelAttribute::from_float(value));
+        computed
+    }
+
+    fn score_attribute(&self) -> &str {
+        LIKELIHOOD_ATTRIBUTE
+    }
+}
+
+// Leaves without their own likelihood fall back to their CVSS base score, read
+// as a 0-1 likelihood
+pub fn leaf_likelihood(attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+    attributes.get(LIKELIHOOD_ATTRIBUTE)
+        .and_then(|attribute| attribute.as_f64())
+        .or_else(|| cvss::base_score(attributes).map(|score| score / 10.0))
+        .map(|likelihood| likelihood.clamp(0.0, 1.0))
+}
+
+pub fn combine_likelihoods(gate: GateType, child_likelihoods: Vec<f64>) -> f64 {
+    match gate {
+        GateType::Or => child_likelihoods.into_iter().fold(0.0, f64::max),
+        GateType::And | GateType::SequentialAnd => child_likelihoods.into_iter().product()
+    }
+}
@@ -0,0 +1,543 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::models::{ApiFullComputedNodeData, ApiFullComputedTreeData, ApiFullNodeData, ApiNodeIssue, ApiTreeReference, GateType, ListModelResponseItem, ModelAttribute, ModelAttributeSchema, NodeType, RiskMatrix, ACCESS_LEVELS};
+use crate::tree_analysis::paths::{COST_ATTRIBUTE, TIME_ATTRIBUTE};
+use crate::tree_analysis::simulation::{LIKELIHOOD_MAX_ATTRIBUTE, LIKELIHOOD_MIN_ATTRIBUTE};
+
+pub mod likelihood;
+pub mod risk_of_attack;
+pub mod evita;
+pub mod custom;
+pub mod countermeasure;
+pub mod feasibility;
+pub mod timed;
+pub mod cvss;
+mod cvss_v4;
+
+// An analysis model turns per-node modelAttributes into computed values rolled
+// up through the tree's gates. Adding a model means implementing this trait
+// and registering it in ModelRegistry::with_builtin_models.
+pub trait AnalysisModel: Send + Sync {
+    fn id(&self) -> &str;
+
+    fn title(&self) -> &str;
+
+    // The modelAttributes keys this model reads, including any leaf defaults
+    fn attributes(&self) -> Vec<ModelAttributeSchema>;
+
+    // The value a leaf feeds into the rollup, or None to leave it out. Schema
+    // defaults have already been filled in.
+    fn leaf_value(&self, attributes: &HashMap<String, ModelAttribute>) -> Option<f64>;
+
+    // Gate aggregation rule for a node whose children produced values, or None
+    // if they can't be combined
+    fn combine(&self, gate: GateType, child_values: Vec<f64>) -> Option<f64>;
+
+    // Converts a node's rolled up value into the attributes returned to clients
+    fn annotate(&self, node: &ApiFullComputedNodeData, value: f64, root: Option<&ApiFullComputedNodeData>, context: &ModelContext) -> HashMap<String, ModelAttribute>;
+
+    // Which computed attribute on the root becomes the tree's rootScore
+    fn score_attribute(&self) -> &str;
+
+    // The risk matrix bucket a node's rolled up value falls in, for models
+    // that rate risk
+    fn risk_bucket(&self, _node: &ApiFullComputedNodeData, _value: f64, _root: Option<&ApiFullComputedNodeData>, _context: &ModelContext) -> Option<String> {
+        None
+    }
+
+    // A node's value once countermeasures with the given combined effectiveness
+    // are in place. By default that's the share of attacks still getting
+    // through, which suits models whose values are likelihoods.
+    fn mitigate(&self, value: f64, effectiveness: f64) -> f64 {
+        value * (1.0 - effectiveness)
+    }
+
+    // Whether attackers are after higher values, going by which child an OR
+    // gate keeps. Analyses that rank or minimise scores go by this so they
+    // agree with the rollup.
+    fn attacker_prefers_higher(&self) -> bool {
+        self.combine(GateType::Or, vec![0.0, 1.0]) == Some(1.0)
+    }
+
+    fn leaf_defaults(&self) -> HashMap<String, ModelAttribute> {
+        let mut defaults = HashMap::new();
+
+        for attribute in self.attributes() {
+            if let Some(default) = attribute.default {
+                defaults.insert(attribute.key, default);
+            }
+        }
+
+        defaults
+    }
+}
+
+// Per-project settings some models need while computing, along with the
+// custom models defined by the project's org
+pub struct ModelContext {
+    pub risk_matrix: RiskMatrix,
+    pub custom_models: Vec<custom::FormulaModel>
+}
+
+impl Default for ModelContext {
+    fn default() -> ModelContext {
+        ModelContext {
+            risk_matrix: RiskMatrix::default(),
+            custom_models: Vec::new()
+        }
+    }
+}
+
+impl ModelContext {
+    // Built-in models take precedence over custom ones
+    pub fn find_model(&self, model_id: &str) -> Option<&dyn AnalysisModel> {
+        registry().get(model_id).or_else(|| {
+            self.custom_models.iter()
+                .find(|model| model.id() == model_id)
+                .map(|model| model as &dyn AnalysisModel)
+        })
+    }
+}
+
+pub struct ModelRegistry {
+    models: Vec<Box<dyn AnalysisModel>>
+}
+
+impl ModelRegistry {
+    pub fn new() -> ModelRegistry {
+        ModelRegistry {
+            models: Vec::new()
+        }
+    }
+
+    pub fn with_builtin_models() -> ModelRegistry {
+        let mut registry = ModelRegistry::new();
+
+        registry.register(Box::new(likelihood::AttackerLikelihood));
+        registry.register(Box::new(risk_of_attack::RiskOfAttack));
+        registry.register(Box::new(evita::Evita));
+
+        registry
+    }
+
+    pub fn register(&mut self, model: Box<dyn AnalysisModel>) {
+        self.models.push(model);
+    }
+
+    pub fn get(&self, model_id: &str) -> Option<&dyn AnalysisModel> {
+        self.models.iter()
+            .find(|model| model.id() == model_id)
+            .map(|model| model.as_ref())
+    }
+
+    pub fn list(&self) -> Vec<ListModelResponseItem> {
+        self.models.iter()
+            .map(|model| ListModelResponseItem {
+                id: model.id().to_owned(),
+                title: model.title().to_owned(),
+                attributes: model.attributes()
+            })
+            .collect()
+    }
+}
+
+static REGISTRY: OnceLock<ModelRegistry> = OnceLock::new();
+
+pub fn registry() -> &'static ModelRegistry {
+    REGISTRY.get_or_init(ModelRegistry::with_builtin_models)
+}
+
+// Nodes of another tree reached through a child link, already condition
+// resolved with the same config as the tree being computed
+#[derive(Clone)]
+pub struct LinkedTree {
+    pub id: String,
+    pub title: String,
+    pub nodes: Vec<ApiFullComputedNodeData>
+}
+
+// Runs the project's selected model over an already condition-resolved tree and
+// stores the results in each node's computedAttributes, with the root's headline
+// number copied to rootScore. Projects without a model (or with one we don't
+// know) are left untouched.
+pub fn compute(tree: &mut ApiFullComputedTreeData, selected_model: Option<&String>, context: &ModelContext) {
+    compute_with_links(tree, &[], selected_model, context);
+}
+
+// Like compute, but children pointing into the linked trees are followed so
+// their subtrees count towards this tree's scores. Only this tree's nodes are
+// annotated; the linked trees that fed a value into them are listed in
+// contributingTrees.
+pub fn compute_with_links(tree: &mut ApiFullComputedTreeData, linked: &[LinkedTree], selected_model: Option<&String>, context: &ModelContext) {
+    tree.mitigationCost = countermeasure::mitigation_cost(&tree.nodes);
+    timed::compute_times(tree, linked);
+    cvss::annotate_base_scores(&mut tree.nodes);
+
+    match selected_model.and_then(|model_id| context.find_model(model_id)) {
+        Some(model) => compute_with_model_and_links(tree, linked, model, context),
+        None => {
+            tree.rootScore = None;
+            tree.mitigatedRootScore = None;
+        }
+    }
+}
+
+fn compute_with_model_and_links(tree: &mut ApiFullComputedTreeData, linked: &[LinkedTree], model: &dyn AnalysisModel, context: &ModelContext) {
+    let nodes = combined_nodes(tree, linked);
+    let results = rollup_with_model(&nodes, model);
+    let mitigated_results = rollup_with_model_mitigated(&nodes, model);
+    let root = tree.nodes.iter().find(|node| node.id == tree.rootNodeId).cloned();
+
+    for node in tree.nodes.iter_mut() {
+        if let Some(Some(value)) = results.get(&node.id) {
+            let computed = model.annotate(node, *value, root.as_ref(), context);
+            node.computedAttributes.extend(computed);
+            node.riskBucket = model.risk_bucket(node, *value, root.as_ref(), context);
+        }
+
+        if let Some(Some(value)) = mitigated_results.get(&node.id) {
+            let mitigated = model.annotate(node, *value, root.as_ref(), context);
+            node.mitigatedAttributes.extend(mitigated);
+        }
+    }
+
+    tree.rootScore = root_value(tree, model.score_attribute());
+    tree.mitigatedRootScore = tree.nodes.iter()
+        .find(|node| node.id == tree.rootNodeId)
+        .and_then(|root| root.mitigatedAttributes.get(model.score_attribute()))
+        .and_then(|value| value.as_f64());
+    tree.contributingTrees = contributing_trees(&nodes, &tree.rootNodeId, &results, linked);
+}
+
+// The tree's own nodes followed by those of every linked tree
+pub fn combined_nodes(tree: &ApiFullComputedTreeData, linked: &[LinkedTree]) -> Vec<ApiFullComputedNodeData> {
+    let mut nodes = tree.nodes.clone();
+    for linked_tree in linked {
+        nodes.extend(linked_tree.nodes.iter().cloned());
+    }
+
+    nodes
+}
+
+// Walks down from the root and collects the linked trees owning a node that
+// produced a value along the way
+fn contributing_trees(nodes: &[ApiFullComputedNodeData], root_node_id: &String, results: &HashMap<String, Option<f64>>, linked: &[LinkedTree]) -> Vec<ApiTreeReference> {
+    let node_indices = index_nodes(nodes);
+    let mut contributing = Vec::new();
+    let mut seen = HashSet::new();
+    let mut pending = vec![root_node_id.clone()];
+
+    while let Some(node_id) = pending.pop() {
+        if !seen.insert(node_id.clone()) {
+            continue;
+        }
+
+        let node = match node_indices.get(&node_id) {
+            Some(index) => &nodes[*index],
+            None => continue
+        };
+
+        if !node.conditionResolved || node.infeasible {
+            continue;
+        }
+
+        if let Some(Some(_)) = results.get(&node_id) {
+            for linked_tree in linked {
+                let owns_node = linked_tree.nodes.iter().any(|linked_node| linked_node.id == node_id);
+                let reference = ApiTreeReference {
+                    id: linked_tree.id.clone(),
+                    title: linked_tree.title.clone()
+                };
+
+                if owns_node && !contributing.contains(&reference) {
+                    contributing.push(reference);
+                }
+            }
+        }
+
+        pending.extend(node.children.iter().cloned());
+    }
+
+    contributing
+}
+
+pub fn rollup_with_model(nodes: &[ApiFullComputedNodeData], model: &dyn AnalysisModel) -> HashMap<String, Option<f64>> {
+    let defaults = model.leaf_defaults();
+
+    rollup(nodes, |node| {
+        let mut attributes = defaults.clone();
+        attributes.extend(node.modelAttributes.clone());
+        model.leaf_value(&attributes)
+    }, |gate, child_values| model.combine(gate, child_values))
+}
+
+// Like rollup_with_model, but every attack node's value is passed through the
+// model's mitigate with the effectiveness of its countermeasures before its
+// parent sees it
+pub fn rollup_with_model_mitigated(nodes: &[ApiFullComputedNodeData], model: &dyn AnalysisModel) -> HashMap<String, Option<f64>> {
+    let defaults = model.leaf_defaults();
+    let mitigations = countermeasure::mitigations(nodes);
+
+    rollup_adjusted(nodes, |node| {
+        let mut attributes = defaults.clone();
+        attributes.extend(node.modelAttributes.clone());
+        model.leaf_value(&attributes)
+    }, |gate, child_values| model.combine(gate, child_values), |node, value| match mitigations.get(&node.id) {
+        Some(effectiveness) => model.mitigate(value, *effectiveness),
+        None => value
+    })
+}
+
+pub fn root_value(tree: &ApiFullComputedTreeData, attribute: &str) -> Option<f64> {
+    tree.nodes.iter()
+        .find(|node| node.id == tree.rootNodeId)
+        .and_then(|root| root.computedAttributes.get(attribute))
+        .and_then(|value| value.as_f64())
+}
+
+// Checks one node's modelAttributes against a model's schema. Returns a
+// (key, problem) pair for every attribute that doesn't fit; keys the model
+// doesn't know about are reported too.
+pub fn validate_attributes(model: &dyn AnalysisModel, attributes: &HashMap<String, ModelAttribute>) -> Vec<(String, String)> {
+    let mut schema = model.attributes();
+    let extra: Vec<ModelAttributeSchema> = analysis_attributes().into_iter()
+        .filter(|analysis_field| !schema.iter().any(|field| field.key == analysis_field.key))
+        .collect();
+    schema.extend(extra);
+
+    validate_against_schema(&schema, model.title(), attributes)
+}
+
+// Attributes the tree analyses (attack paths, simulation, timing and attacker
+// profiles) and CVSS scoring read from nodes. They're allowed whatever model is
+// selected, though a model defining the same key takes precedence.
+pub fn analysis_attributes() -> Vec<ModelAttributeSchema> {
+    let mut access = ModelAttributeSchema::number(feasibility::ACCESS_ATTRIBUTE, None, None);
+    access.valueType = "string".to_owned();
+    access.allowedValues = Some(ACCESS_LEVELS.iter().map(|level| level.to_string()).collect());
+
+    let mut cvss_vector = ModelAttributeSchema::number(cvss::CVSS_ATTRIBUTE, None, None);
+    cvss_vector.valueType = "string".to_owned();
+
+    vec![
+        ModelAttributeSchema::number(COST_ATTRIBUTE, Some(0.0), None),
+        ModelAttributeSchema::number(TIME_ATTRIBUTE, Some(0.0), None),
+        ModelAttributeSchema::number(timed::TIME_MIN_ATTRIBUTE, Some(0.0), None),
+        ModelAttributeSchema::number(timed::TIME_MAX_ATTRIBUTE, Some(0.0), None),
+        ModelAttributeSchema::number(LIKELIHOOD_MIN_ATTRIBUTE, Some(0.0), Some(1.0)),
+        ModelAttributeSchema::number(LIKELIHOOD_MAX_ATTRIBUTE, Some(0.0), Some(1.0)),
+        ModelAttributeSchema::number(feasibility::SKILL_ATTRIBUTE, Some(0.0), None),
+        access,
+        cvss_vector
+    ]
+}
+
+fn validate_against_schema(schema: &[ModelAttributeSchema], owner: &str, attributes: &HashMap<String, ModelAttribute>) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+
+    for (key, attribute) in attributes {
+        match schema.iter().find(|field| field.key == *key) {
+            Some(field) => {
+                if let Some(problem) = validate_attribute(field, attribute) {
+                    problems.push((key.clone(), problem));
+                }
+            },
+            None => problems.push((key.clone(), format!("{} is not an attribute of {}", key, owner)))
+        }
+    }
+
+    problems.sort();
+    problems
+}
+
+// Runs validate_attributes over every node of a tree being saved. Countermeasure
+// nodes are checked against their own attributes rather than the model's.
+pub fn validate_tree_attributes(model: &dyn AnalysisModel, nodes: &[ApiFullNodeData]) -> Vec<ApiNodeIssue> {
+    let mut issues = Vec::new();
+
+    for node in nodes {
+        let problems = match node.nodeType {
+            NodeType::Countermeasure => validate_against_schema(&countermeasure::countermeasure_attributes(), "a countermeasure", &node.modelAttributes),
+            NodeType::Attack => validate_attributes(model, &node.modelAttributes)
+        };
+
+        for (key, message) in problems {
+            issues.push(ApiNodeIssue {
+                nodeId: node.id.clone(),
+                field: format!("modelAttributes.{}", key),
+                message: message
+            });
+        }
+    }
+
+    issues
+}
+
+fn validate_attribute(field: &ModelAttributeSchema, attribute: &ModelAttribute) -> Option<String> {
+    let allowed = |value: &String| match field.allowedValues {
+        Some(ref allowed_values) => allowed_values.iter().any(|allowed_value| allowed_value.eq_ignore_ascii_case(value.trim())),
+        None => false
+    };
+
+    if field.valueType == "string" {
+        return match attribute.value_string {
+            Some(ref value) => {
+                if field.allowedValues.is_none() || allowed(value) {
+                    None
+                } else {
+                    Some(format!("{} is not one of the allowed values", value))
+                }
+            },
+            None => Some("Expected a string".to_owned())
+        };
+    }
+
+    let number = if attribute.value_float.is_some() || attribute.value_int.is_some() {
+        attribute.as_f64()
+    } else {
+        match attribute.value_string {
+            Some(ref value) if allowed(value) => return None,
+            Some(ref value) => return Some(format!("Expected a number but got \"{}\"", value)),
+            None => return Some("Expected a number".to_owned())
+        }
+    };
+
+    match number {
+        Some(number) => {
+            if field.min.map_or(false, |min| number < min) || field.max.map_or(false, |max| number > max) {
+                Some(format!("{} is outside of {} to {}", number, field.min.map_or("-inf".to_owned(), |min| min.to_string()), field.max.map_or("inf".to_owned(), |max| max.to_string())))
+            } else {
+                None
+            }
+        },
+        None => Some("Expected a number".to_owned())
+    }
+}
+
+pub fn index_nodes(nodes: &[ApiFullComputedNodeData]) -> HashMap<String, usize> {
+    let mut indices = HashMap::new();
+
+    for (index, node) in nodes.iter().enumerate() {
+        indices.insert(node.id.clone(), index);
+    }
+
+    indices
+}
+
+// Whether a child counts towards its parent's gate. Countermeasures mitigate
+// rather than contribute, and children living in other trees aren't part of
+// the computation.
+pub fn contributes(nodes: &[ApiFullComputedNodeData], node_indices: &HashMap<String, usize>, child_id: &String) -> bool {
+    node_indices.get(child_id).map_or(false, |index| !countermeasure::is_countermeasure(&nodes[*index]))
+}
+
+// Computes a value for every node in the tree, bottom up. Leaves use
+// leaf_value, everything else combines its children's values according to its
+// gate. Unresolved or infeasible nodes have no value; an OR skips such children
+// while an AND or SAND with one becomes unreachable itself, as does any node
+// whose children all dropped out. Countermeasures and children that live in
+// other trees are left out.
+pub fn rollup<L, C>(nodes: &[ApiFullComputedNodeData], leaf_value: L, combine: C) -> HashMap<String, Option<f64>>
+where
+    L: Fn(&ApiFullComputedNodeData) -> Option<f64>,
+    C: Fn(GateType, Vec<f64>) -> Option<f64>
+{
+    rollup_adjusted(nodes, leaf_value, combine, |_, value| value)
+}
+
+// rollup over nodes that have already been indexed, for callers that roll the
+// same tree up many times
+pub fn rollup_indexed<L, C>(nodes: &[ApiFullComputedNodeData], node_indices: &HashMap<String, usize>, leaf_value: L, combine: C) -> HashMap<String, Option<f64>>
+where
+    L: Fn(&ApiFullComputedNodeData) -> Option<f64>,
+    C: Fn(GateType, Vec<f64>) -> Option<f64>
+{
+    let mut results: HashMap<String, Option<f64>> = HashMap::new();
+
+    for node in nodes {
+        rollup_node(nodes, node_indices, &node.id, &leaf_value, &combine, &|_, value| value, &mut results, &mut HashSet::new());
+    }
+
+    results
+}
+
+// rollup with a final adjustment applied to each node's value, which is what
+// its parent then combines
+pub fn rollup_adjusted<L, C, A>(nodes: &[ApiFullComputedNodeData], leaf_value: L, combine: C, adjust: A) -> HashMap<String, Option<f64>>
+where
+    L: Fn(&ApiFullComputedNodeData) -> Option<f64>,
+    C: Fn(GateType, Vec<f64>) -> Option<f64>,
+    A: Fn(&ApiFullComputedNodeData, f64) -> f64
+{
+    let node_indices = index_nodes(nodes);
+    let mut results: HashMap<String, Option<f64>> = HashMap::new();
+
+    for node in nodes {
+        rollup_node(nodes, &node_indices, &node.id, &leaf_value, &combine, &adjust, &mut results, &mut HashSet::new());
+    }
+
+    results
+}
+
+fn rollup_node<L, C, A>(
+    nodes: &[ApiFullComputedNodeData],
+    node_indices: &HashMap<String, usize>,
+    node_id: &String,
+    leaf_value: &L,
+    combine: &C,
+    adjust: &A,
+    results: &mut HashMap<String, Option<f64>>,
+    visiting: &mut HashSet<String>
+) -> Option<f64>
+where
+    L: Fn(&ApiFullComputedNodeData) -> Option<f64>,
+    C: Fn(GateType, Vec<f64>) -> Option<f64>,
+    A: Fn(&ApiFullComputedNodeData, f64) -> f64
+{
+    if let Some(result) = results.get(node_id) {
+        return *result;
+    }
+
+    // Children living in other trees aren't part of this computation
+    let node = match node_indices.get(node_id) {
+        Some(index) => &nodes[*index],
+        None => return None
+    };
+
+    if !node.conditionResolved || node.infeasible || countermeasure::is_countermeasure(node) || visiting.contains(node_id) {
+        return None;
+    }
+
+    visiting.insert(node_id.clone());
+    let mut child_values = Vec::new();
+    let mut contributing_children = 0;
+    for child in &node.children {
+        if !contributes(nodes, node_indices, child) {
+            continue;
+        }
+
+        contributing_children += 1;
+        if let Some(value) = rollup_node(nodes, node_indices, child, leaf_value, combine, adjust, results, visiting) {
+            child_values.push(value);
+        }
+    }
+    visiting.remove(node_id);
+
+    // An OR only needs one reachable child, AND and SAND need every one of them
+    let unreachable = match node.gateType {
+        GateType::Or => child_values.is_empty(),
+        GateType::And | GateType::SequentialAnd => child_values.len() < contributing_children
+    };
+
+    let value = if contributing_children == 0 {
+        leaf_value(node)
+    } else if unreachable {
+        None
+    } else {
+        combine(node.gateType, child_values)
+    }.map(|value| adjust(node, value));
+
+    results.insert(node_id.clone(), value);
+    value
+}
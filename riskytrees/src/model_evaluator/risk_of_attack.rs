@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::constants;
+use crate::models::{ApiFullComputedNodeData, GateType, ModelAttribute, ModelAttributeSchema};
+use crate::model_evaluator::{AnalysisModel, ModelContext};
+use crate::model_evaluator::likelihood::{combine_likelihoods, leaf_likelihood, LIKELIHOOD_ATTRIBUTE};
+
+pub const IMPACT_ATTRIBUTE: &str = "impact";
+pub const RISK_ATTRIBUTE: &str = "risk";
+
+// Risk of Attack: likelihood rolls up exactly as in Attacker Likelihood and is
+// multiplied by the impact of reaching the node. Nodes without their own
+// "impact" use the root's. The project's risk matrix turns each pair into a
+// Low/Medium/High/Critical bucket, returned as the node's riskBucket.
+pub struct RiskOfAttack;
+
+impl AnalysisModel for RiskOfAttack {
+    fn id(&self) -> &str {
+        constants::RISK_OF_ATTACK_MODEL_ID
+    }
+
+    fn title(&self) -> &str {
+        "Risk of Attack"
+    }
+
+    fn attributes(&self) -> Vec<ModelAttributeSchema> {
+        vec![
+            ModelAttributeSchema::number(LIKELIHOOD_ATTRIBUTE, Some(0.0), Some(1.0)),
+            ModelAttributeSchema::number(IMPACT_ATTRIBUTE, Some(0.0), None)
+        ]
+    }
+
+    fn leaf_value(&self, attributes: &HashMap<String, ModelAttribute>) -> Option<f64> {
+        leaf_likelihood(attributes)
+    }
+
+    fn combine(&self, gate: GateType, child_values: Vec<f64>) -> Option<f64> {
+        Some(combine_likelihoods(gate, child_values))
+    }
+
+    fn annotate(&self, node: &ApiFullComputedNodeData, value: f64, root: Option<&ApiFullComputedNodeData>, _context: &ModelContext) -> HashMap<String, ModelAttribute> {
+        let mut computed = HashMap::new();
+        computed.insert(LIKELIHOOD_ATTRIBUTE.to_owned(), ModelAttribute::from_float(value));
+
+        if let Some(impact) = node_impact(node).or(root.and_then(node_impact)) {
+            computed.insert(IMPACT_ATTRIBUTE.to_owned(), ModelAttribute::from_float(impact));
+            computed.insert(RISK_ATTRIBUTE.to_owned(), ModelAttribute::from_float(value * impact));
+        }
+
+        computed
+    }
+
+    fn score_attribute(&self) -> &str {
+        RISK_ATTRIBUTE
+    }
+
+    fn risk_bucket(&self, node: &ApiFullComputedNodeData, value: f64, root: Option<&ApiFullComputedNodeData>, context: &ModelContext) -> Option<String> {
+        node_impact(node).or(root.and_then(node_impact))
+            .and_then(|impact| context.risk_matrix.bucket(value, impact))
+    }
+}
+
+fn node_impact(node: &ApiFullComputedNodeData) -> Option<f64> {
+    node.modelAttributes.get(IMPACT_ATTRIBUTE)
+        .and_then(|attribute| attribute.as_f64())
+        .map(|impact| impact.max(0.0))
+}
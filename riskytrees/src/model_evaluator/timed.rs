@@ -0,0 +1,51 @@
+use crate::models::{ApiFullComputedNodeData, ApiFullComputedTreeData, ApiTimeRange, GateType};
+use crate::model_evaluator::{self, LinkedTree};
+use crate::tree_analysis::paths::TIME_ATTRIBUTE;
+
+pub const TIME_MIN_ATTRIBUTE: &str = "timeMin";
+pub const TIME_MAX_ATTRIBUTE: &str = "timeMax";
+
+// How long a leaf takes, as (lowest, highest) estimate. A "timeMin"/"timeMax"
+// range wins over a fixed "time", which counts as both.
+pub fn leaf_time_range(node: &ApiFullComputedNodeData) -> Option<(f64, f64)> {
+    let value = |key: &str| node.modelAttributes.get(key)
+        .and_then(|attribute| attribute.as_f64())
+        .map(|value| value.max(0.0));
+
+    match (value(TIME_ATTRIBUTE), value(TIME_MIN_ATTRIBUTE), value(TIME_MAX_ATTRIBUTE)) {
+        (_, Some(min), Some(max)) if min <= max => Some((min, max)),
+        (Some(time), _, _) => Some((time, time)),
+        _ => None
+    }
+}
+
+// Children of an AND run in parallel so the slowest decides, an OR is reached
+// by its fastest child and a SAND has to wait for each child in turn
+pub fn combine_times(gate: GateType, child_times: Vec<f64>) -> f64 {
+    match gate {
+        GateType::Or => child_times.into_iter().fold(f64::INFINITY, f64::min),
+        GateType::And => child_times.into_iter().fold(0.0, f64::max),
+        GateType::SequentialAnd => child_times.into_iter().sum()
+    }
+}
+
+// Fills in timeToCompromise for every node of the tree that can be reached
+// through leaves with a time estimate. Works the same way under every model,
+// and follows linked trees when they're given.
+pub fn compute_times(tree: &mut ApiFullComputedTreeData, linked: &[LinkedTree]) {
+    let nodes = model_evaluator::combined_nodes(tree, linked);
+    let combine = |gate, child_times| Some(combine_times(gate, child_times));
+
+    let earliest = model_evaluator::rollup(&nodes, |node| leaf_time_range(node).map(|(min, _)| min), combine);
+    let latest = model_evaluator::rollup(&nodes, |node| leaf_time_range(node).map(|(_, max)| max), combine);
+
+    for node in tree.nodes.iter_mut() {
+        node.timeToCompromise = match (earliest.get(&node.id), latest.get(&node.id)) {
+            (Some(Some(min)), Some(Some(max))) => Some(ApiTimeRange {
+                min: *min,
+                max: *max
+            }),
+            _ => None
+        };
+    }
+}
@@ -15,6 +15,45 @@ pub struct ModelAttribute {
 }
 
 impl ModelAttribute {
+    pub fn from_float(value: f64) -> ModelAttribute {
+        ModelAttribute {
+            value_string: None,
+            value_int: None,
+            value_float: Some(value)
+        }
+    }
+
+    pub fn from_int(value: i32) -> ModelAttribute {
+        ModelAttribute {
+            value_string: None,
+            value_int: Some(value),
+            value_float: None
+        }
+    }
+
+    pub fn from_string(value: &str) -> ModelAttribute {
+        ModelAttribute {
+            value_string: Some(value.to_owned()),
+            value_int: None,
+            value_float: None
+        }
+    }
+
+    // Numeric view of the attribute. Strings are parsed so values typed into
+    // free-text fields still count.
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.value_float.is_some() {
+            self.value_float
+        } else if self.value_int.is_some() {
+            Some(f64::from(self.value_int.expect("Checked")))
+        } else {
+            match self.value_string {
+                Some(ref val) => val.trim().parse::<f64>().ok(),
+                None => None
+            }
+        }
+    }
+
     fn to_bson_doc(self) -> Document {
         if self.value_string.is_some() {
             doc! {
@@ -48,6 +87,78 @@ impl Clone for ModelAttribute {
     }
 }
 
+// How a node's children combine. An attacker needs any one child for OR, every
+// child for AND, and every child in the listed order for SAND (sequential AND).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GateType {
+    #[serde(rename = "or")]
+    Or,
+    #[serde(rename = "and")]
+    And,
+    #[serde(rename = "sand")]
+    SequentialAnd
+}
+
+impl Default for GateType {
+    fn default() -> GateType {
+        GateType::Or
+    }
+}
+
+impl GateType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GateType::Or => "or",
+            GateType::And => "and",
+            GateType::SequentialAnd => "sand"
+        }
+    }
+
+    // Anything unrecognised (including documents stored before gates existed)
+    // is treated as OR.
+    pub fn parse(value: &str) -> GateType {
+        match value {
+            "and" => GateType::And,
+            "sand" => GateType::SequentialAnd,
+            _ => GateType::Or
+        }
+    }
+}
+
+// What a node stands for. Attack nodes are steps towards the root; a
+// countermeasure is a child of the attack node it mitigates and carries an
+// effectiveness (0-1) and a cost instead of taking part in the gates.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NodeType {
+    #[serde(rename = "attack")]
+    Attack,
+    #[serde(rename = "countermeasure")]
+    Countermeasure
+}
+
+impl Default for NodeType {
+    fn default() -> NodeType {
+        NodeType::Attack
+    }
+}
+
+impl NodeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeType::Attack => "attack",
+            NodeType::Countermeasure => "countermeasure"
+        }
+    }
+
+    // Documents stored before countermeasures existed are attack nodes
+    pub fn parse(value: &str) -> NodeType {
+        match value {
+            "countermeasure" => NodeType::Countermeasure,
+            _ => NodeType::Attack
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
     pub email: String,
@@ -156,7 +267,144 @@ pub struct ListModelResult {
 
 pub struct ListModelResponseItem {
     pub id: String,
-    pub title: String
+    pub title: String,
+    pub attributes: Vec<ModelAttributeSchema>
+}
+
+// Describes one modelAttributes key a model reads. Number attributes take
+// value_int or value_float, and may also accept one of allowedValues as a
+// value_string (e.g. EVITA category names). String attributes must be one of
+// allowedValues when it is set. default fills in the attribute on leaves that
+// don't set it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelAttributeSchema {
+    pub key: String,
+    pub valueType: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub allowedValues: Option<Vec<String>>,
+    pub default: Option<ModelAttribute>
+}
+
+impl ModelAttributeSchema {
+    pub fn number(key: &str, min: Option<f64>, max: Option<f64>) -> ModelAttributeSchema {
+        ModelAttributeSchema {
+            key: key.to_owned(),
+            valueType: "number".to_owned(),
+            min: min,
+            max: max,
+            allowedValues: None,
+            default: None
+        }
+    }
+}
+
+// An org-defined analysis model. leafFormula is evaluated for every leaf with
+// the leaf's modelAttributes as variables; aggregationFormula combines child
+// values at every other node, with the children in `values` and the node's gate
+// ("or", "and" or "sand") in `gate`. Both are evalexpr expressions rather
+// than conditions, so config lookups and node references aren't available.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustomModelPayload {
+    pub title: String,
+    pub attributes: Vec<ModelAttributeSchema>,
+    pub leafFormula: String,
+    pub aggregationFormula: String,
+    #[serde(default)]
+    pub scoreAttribute: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustomModelDefinition {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub orgId: String,
+    pub title: String,
+    pub attributes: Vec<ModelAttributeSchema>,
+    pub leafFormula: String,
+    pub aggregationFormula: String,
+    pub scoreAttribute: String
+}
+
+impl CustomModelDefinition {
+    pub fn from_payload(id: String, org_id: String, payload: &CustomModelPayload) -> CustomModelDefinition {
+        CustomModelDefinition {
+            id: id,
+            orgId: org_id,
+            title: payload.title.clone(),
+            attributes: payload.attributes.clone(),
+            leafFormula: payload.leafFormula.clone(),
+            aggregationFormula: payload.aggregationFormula.clone(),
+            scoreAttribute: payload.scoreAttribute.clone().unwrap_or("score".to_owned())
+        }
+    }
+}
+
+// Ways in, from furthest away to closest, as in CVSS's attack vector. An
+// attacker with a given access can also use every level before it.
+pub const ACCESS_LEVELS: [&str; 4] = ["network", "adjacent", "local", "physical"];
+
+// What an attacker is capable of. Leaves needing more skill, money, access or
+// time than this are infeasible for them; limits left out don't apply.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttackerProfilePayload {
+    pub name: String,
+    pub skill: Option<f64>,
+    pub budget: Option<f64>,
+    pub access: Option<String>,
+    pub time: Option<f64>
+}
+
+impl AttackerProfilePayload {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Profile name must not be empty".to_owned());
+        }
+
+        for (limit, value) in [("skill", self.skill), ("budget", self.budget), ("time", self.time)].iter() {
+            if value.map_or(false, |value| !(value >= 0.0)) {
+                return Err(format!("{} must not be negative", limit));
+            }
+        }
+
+        match self.access {
+            Some(ref access) if access_level(access).is_none() => Err(format!("access must be one of {}", ACCESS_LEVELS.join(", "))),
+            _ => Ok(())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttackerProfile {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub projectId: String,
+    pub name: String,
+    pub skill: Option<f64>,
+    pub budget: Option<f64>,
+    pub access: Option<String>,
+    pub time: Option<f64>
+}
+
+impl AttackerProfile {
+    pub fn from_payload(id: String, project_id: String, payload: &AttackerProfilePayload) -> AttackerProfile {
+        AttackerProfile {
+            id: id,
+            projectId: project_id,
+            name: payload.name.clone(),
+            skill: payload.skill,
+            budget: payload.budget,
+            access: payload.access.as_ref().map(|access| access.trim().to_lowercase()),
+            time: payload.time
+        }
+    }
+}
+
+// Position of an access level in ACCESS_LEVELS, ignoring case
+pub fn access_level(access: &str) -> Option<usize> {
+    ACCESS_LEVELS.iter().position(|level| level.eq_ignore_ascii_case(access.trim()))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -165,6 +413,75 @@ pub struct SelectedModelResult {
     pub modelId: String
 }
 
+// Maps a (likelihood, impact) pair onto a risk bucket for the Risk of Attack
+// model. Each threshold list splits its axis into bands (a value at or above
+// the nth threshold lands in band n + 1) and cells[likelihood band][impact band]
+// names the bucket.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RiskMatrix {
+    pub likelihoodThresholds: Vec<f64>,
+    pub impactThresholds: Vec<f64>,
+    pub cells: Vec<Vec<String>>
+}
+
+pub const RISK_BUCKETS: [&str; 4] = ["Low", "Medium", "High", "Critical"];
+
+impl Default for RiskMatrix {
+    // 5x5 matrix over likelihoods of 0-1 and impacts of 1-5
+    fn default() -> RiskMatrix {
+        let rows = vec![
+            vec!["Low", "Low", "Low", "Medium", "Medium"],
+            vec!["Low", "Low", "Medium", "Medium", "High"],
+            vec!["Low", "Medium", "Medium", "High", "High"],
+            vec!["Medium", "Medium", "High", "High", "Critical"],
+            vec!["Medium", "High", "High", "Critical", "Critical"]
+        ];
+
+        RiskMatrix {
+            likelihoodThresholds: vec![0.2, 0.4, 0.6, 0.8],
+            impactThresholds: vec![2.0, 3.0, 4.0, 5.0],
+            cells: rows.into_iter().map(|row| row.into_iter().map(|cell| cell.to_owned()).collect()).collect()
+        }
+    }
+}
+
+impl RiskMatrix {
+    pub fn validate(&self) -> Result<(), String> {
+        let ascending = |thresholds: &Vec<f64>| thresholds.windows(2).all(|pair| pair[0] < pair[1]);
+
+        if !ascending(&self.likelihoodThresholds) || !ascending(&self.impactThresholds) {
+            return Err("Thresholds must be in ascending order".to_owned());
+        }
+
+        if self.cells.len() != self.likelihoodThresholds.len() + 1 {
+            return Err("Expected one row per likelihood band".to_owned());
+        }
+
+        for row in &self.cells {
+            if row.len() != self.impactThresholds.len() + 1 {
+                return Err("Expected one column per impact band".to_owned());
+            }
+
+            for cell in row {
+                if !RISK_BUCKETS.contains(&cell.as_str()) {
+                    return Err(format!("Unknown risk bucket {}", cell));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn bucket(&self, likelihood: f64, impact: f64) -> Option<String> {
+        let likelihood_band = self.likelihoodThresholds.iter().filter(|threshold| likelihood >= **threshold).count();
+        let impact_band = self.impactThresholds.iter().filter(|threshold| impact >= **threshold).count();
+
+        self.cells.get(likelihood_band)
+            .and_then(|row| row.get(impact_band))
+            .map(|cell| cell.to_owned())
+    }
+}
+
 // Everything below is an OpenAPI structure or part of one
 
 #[derive(Serialize, Deserialize)]
@@ -213,6 +530,10 @@ pub struct ApiFullNodeData {
     pub modelAttributes: HashMap<String, ModelAttribute>,
     pub conditionAttribute: String,
     pub children: Vec<String>,
+    #[serde(default)]
+    pub gateType: GateType,
+    #[serde(default)]
+    pub nodeType: NodeType
 }
 
 impl Clone for ApiFullNodeData {
@@ -223,7 +544,9 @@ impl Clone for ApiFullNodeData {
             description: self.description.to_owned(),
             modelAttributes: self.modelAttributes.clone(),
             conditionAttribute: self.conditionAttribute.to_owned(),
-            children: self.children.clone()
+            children: self.children.clone(),
+            gateType: self.gateType,
+            nodeType: self.nodeType
         }
     }
 }
@@ -242,7 +565,9 @@ impl ApiFullNodeData {
             "description": self.description,
             "modelAttributes": model_attributes,
             "conditionAttribute": self.conditionAttribute,
-            "children": self.children
+            "children": self.children,
+            "gateType": self.gateType.as_str(),
+            "nodeType": self.nodeType.as_str()
         }
     }
 }
@@ -255,7 +580,36 @@ pub struct ApiFullComputedNodeData {
     pub modelAttributes: HashMap<String, ModelAttribute>,
     pub conditionAttribute: String,
     pub children: Vec<String>,
-    pub conditionResolved: bool
+    #[serde(default)]
+    pub gateType: GateType,
+    #[serde(default)]
+    pub nodeType: NodeType,
+    pub conditionResolved: bool,
+    // Set when the requested attacker profile can't carry out this node
+    #[serde(default)]
+    pub infeasible: bool,
+    #[serde(default)]
+    pub computedAttributes: HashMap<String, ModelAttribute>,
+    // computedAttributes with the tree's countermeasures applied
+    #[serde(default)]
+    pub mitigatedAttributes: HashMap<String, ModelAttribute>,
+    #[serde(default)]
+    pub timeToCompromise: Option<ApiTimeRange>,
+    // Only filled in when tree GET is asked to explain conditions
+    #[serde(default)]
+    pub explanation: Option<ApiConditionExplanation>,
+    // Where the node lands in the project's risk matrix, for models that rate
+    // risk
+    #[serde(default)]
+    pub riskBucket: Option<String>
+}
+
+// Earliest time an attacker can reach a node, using every leaf's lowest (min)
+// and highest (max) time estimates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiTimeRange {
+    pub min: f64,
+    pub max: f64
 }
 
 impl Clone for ApiFullComputedNodeData {
@@ -267,7 +621,15 @@ impl Clone for ApiFullComputedNodeData {
             modelAttributes: self.modelAttributes.clone(),
             conditionAttribute: self.conditionAttribute.to_owned(),
             conditionResolved: self.conditionResolved.to_owned(),
-            children: self.children.clone()
+            infeasible: self.infeasible,
+            children: self.children.clone(),
+            gateType: self.gateType,
+            nodeType: self.nodeType,
+            computedAttributes: self.computedAttributes.clone(),
+            mitigatedAttributes: self.mitigatedAttributes.clone(),
+            timeToCompromise: self.timeToCompromise.clone(),
+            explanation: self.explanation.clone(),
+            riskBucket: self.riskBucket.clone()
         }
     }
 }
@@ -280,6 +642,18 @@ impl ApiFullComputedNodeData {
             model_attributes.insert(key, val.to_bson_doc());
         }
 
+        let mut computed_attributes = doc! {};
+
+        for (key, val) in self.computedAttributes.into_iter() {
+            computed_attributes.insert(key, val.to_bson_doc());
+        }
+
+        let mut mitigated_attributes = doc! {};
+
+        for (key, val) in self.mitigatedAttributes.into_iter() {
+            mitigated_attributes.insert(key, val.to_bson_doc());
+        }
+
         doc! {
             "id": self.id,
             "title": self.title,
@@ -287,7 +661,15 @@ impl ApiFullComputedNodeData {
             "modelAttributes": model_attributes,
             "conditionAttribute": self.conditionAttribute,
             "conditionResolved": self.conditionResolved,
-            "children": self.children
+            "infeasible": self.infeasible,
+            "children": self.children,
+            "gateType": self.gateType.as_str(),
+            "nodeType": self.nodeType.as_str(),
+            "computedAttributes": computed_attributes,
+            "mitigatedAttributes": mitigated_attributes,
+            "timeToCompromise": mongodb::bson::to_bson(&self.timeToCompromise).unwrap_or(mongodb::bson::Bson::Null),
+            "explanation": mongodb::bson::to_bson(&self.explanation).unwrap_or(mongodb::bson::Bson::Null),
+            "riskBucket": self.riskBucket
         }
     }
 }
@@ -315,11 +697,21 @@ impl ApiFullTreeData {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ApiFullComputedTreeData {
     pub title: String,
     pub rootNodeId: String,
-    pub nodes: Vec<ApiFullComputedNodeData>
+    pub nodes: Vec<ApiFullComputedNodeData>,
+    pub rootScore: Option<f64>,
+    // rootScore once every active countermeasure is applied, and what those
+    // countermeasures cost together
+    #[serde(default)]
+    pub mitigatedRootScore: Option<f64>,
+    #[serde(default)]
+    pub mitigationCost: f64,
+    // Other trees whose nodes fed into the scores, when subtrees are included
+    #[serde(default)]
+    pub contributingTrees: Vec<ApiTreeReference>
 }
 
 impl ApiFullComputedTreeData {
@@ -333,7 +725,10 @@ impl ApiFullComputedTreeData {
         doc! {
             "title": self.title,
             "rootNodeId": self.rootNodeId,
-            "nodes": nodes_as_docs
+            "nodes": nodes_as_docs,
+            "rootScore": self.rootScore,
+            "mitigatedRootScore": self.mitigatedRootScore,
+            "mitigationCost": self.mitigationCost
         }
     }
 }
@@ -367,6 +762,25 @@ pub struct ApiTreeResponse {
     pub result: Option<ApiFullTreeData>
 }
 
+// A problem with one field of one node, found while validating a tree
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiNodeIssue {
+    pub nodeId: String,
+    pub field: String,
+    pub message: String
+}
+
+// Tree PUTs report validation problems as errors when the update was rejected
+// and as warnings when it was saved anyway in lenient mode
+#[derive(Serialize, Deserialize)]
+pub struct ApiTreeUpdateResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiFullComputedTreeData>,
+    pub errors: Vec<ApiNodeIssue>,
+    pub warnings: Vec<ApiNodeIssue>
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ApiTreeComputedResponse {
     pub ok: bool,
@@ -423,6 +837,13 @@ pub struct ApiSelectedModelResponse {
     pub result: Option<SelectedModelResult>
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiRiskMatrixResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<RiskMatrix>
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiGetNodeResponse {
     pub ok: bool,
@@ -447,6 +868,320 @@ pub struct ApiTreeDagResponseResult {
     pub root: ApiTreeDagItem
 }
 
+// One minimal way of achieving a tree's root: every listed step is needed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiCutSet {
+    pub nodeIds: Vec<String>,
+    pub score: Option<f64>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiCutSetsResult {
+    pub modelId: Option<String>,
+    pub cutSets: Vec<ApiCutSet>,
+    pub truncated: bool
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiCutSetsResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiCutSetsResult>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiAttackPath {
+    pub objective: String,
+    pub nodeIds: Vec<String>,
+    pub value: f64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiAttackPathResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiAttackPath>
+}
+
+// Leaving out the seed picks a random one, which is returned so the run can be
+// repeated. nodeIds defaults to just the root.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSimulationPayload {
+    pub iterations: Option<u32>,
+    pub seed: Option<u64>,
+    pub nodeIds: Option<Vec<String>>,
+    pub bins: Option<usize>,
+    pub configId: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiPercentiles {
+    pub p5: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiHistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: u32
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSimulatedNode {
+    pub nodeId: String,
+    pub samples: u32,
+    pub mean: Option<f64>,
+    pub percentiles: Option<ApiPercentiles>,
+    pub histogram: Vec<ApiHistogramBin>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSimulationResult {
+    pub iterations: u32,
+    pub seed: u64,
+    pub nodes: Vec<ApiSimulatedNode>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSimulationResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiSimulationResult>
+}
+
+// How the root score moves when one leaf attribute is nudged down (low) and up
+// (high). swing is the larger of the two moves.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSensitivityEntry {
+    pub nodeId: String,
+    pub title: String,
+    pub attribute: String,
+    pub lowScore: Option<f64>,
+    pub highScore: Option<f64>,
+    pub swing: f64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSensitivityResult {
+    pub modelId: String,
+    pub perturbation: f64,
+    pub baselineScore: Option<f64>,
+    pub ranking: Vec<ApiSensitivityEntry>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiSensitivityResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiSensitivityResult>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiMitigationPayload {
+    pub budget: f64,
+    pub configId: Option<String>,
+    pub includeSubtrees: Option<bool>
+}
+
+// A set of countermeasures to put in place, what they cost together and the
+// root score left once they are
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiMitigationPortfolio {
+    pub countermeasureIds: Vec<String>,
+    pub cost: f64,
+    pub residualScore: Option<f64>
+}
+
+// exact is false when there were too many countermeasures to try every
+// combination and portfolios were built up greedily instead
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiMitigationPlan {
+    pub modelId: String,
+    pub budget: f64,
+    pub baselineScore: Option<f64>,
+    pub exact: bool,
+    pub best: ApiMitigationPortfolio,
+    pub frontier: Vec<ApiMitigationPortfolio>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiMitigationPlanResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiMitigationPlan>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConfigMatrixColumn {
+    pub configId: String,
+    pub name: Option<String>,
+    pub rootScore: Option<f64>,
+    pub mitigatedRootScore: Option<f64>,
+    // Set when the tree couldn't be computed for this config
+    pub error: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConfigMatrixRow {
+    pub nodeId: String,
+    pub title: String,
+    // One entry per column, in the same order as ApiConfigMatrix::configs, and
+    // null for columns with an error
+    pub conditionResolved: Vec<Option<bool>>,
+    pub varies: bool
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConfigMatrix {
+    pub configs: Vec<ApiConfigMatrixColumn>,
+    pub nodes: Vec<ApiConfigMatrixRow>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConfigMatrixResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiConfigMatrix>
+}
+
+// The condition to check and, optionally, the config to check its keys
+// against: ad-hoc attributes, a stored config of the project, or else the
+// project's selected config
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConditionValidatePayload {
+    pub condition: String,
+    #[serde(default)]
+    pub projectId: Option<String>,
+    #[serde(default)]
+    pub configId: Option<String>,
+    #[serde(default)]
+    pub attributes: Option<serde_json::Value>
+}
+
+// start and end are character positions in the condition, end exclusive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionDiagnostic {
+    pub message: String,
+    pub start: usize,
+    pub end: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionKey {
+    // As written in conditions, e.g. config["network"]["zone"]
+    pub key: String,
+    // Object keys as strings and array positions as numbers
+    pub path: Vec<serde_json::Value>,
+    // Whether the config has a value at the path, when a config was given
+    pub exists: Option<bool>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConditionValidation {
+    pub valid: bool,
+    pub normalized: Option<String>,
+    pub errors: Vec<ApiConditionDiagnostic>,
+    pub keys: Vec<ApiConditionKey>,
+    // What the condition evaluates to under the given config, or why it
+    // couldn't be evaluated
+    pub resolved: Option<bool>,
+    pub evaluationError: Option<ApiConditionDiagnostic>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConditionValidateResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiConditionValidation>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionNodeRef {
+    pub treeId: String,
+    pub nodeId: String,
+    pub title: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionKeyUsage {
+    // As written in conditions, e.g. config["network"]["zone"]
+    pub key: String,
+    pub path: Vec<serde_json::Value>,
+    pub nodes: Vec<ApiConditionNodeRef>,
+    // Stored configs with no value at the path
+    pub missingFrom: Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConfigKeyUsage {
+    pub configId: String,
+    pub name: Option<String>,
+    // Keys no condition reads, down to the innermost object keys
+    pub unusedKeys: Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionKeyReport {
+    pub keys: Vec<ApiConditionKeyUsage>,
+    pub configs: Vec<ApiConfigKeyUsage>,
+    // Conditions that don't parse, so any keys they read aren't counted
+    pub invalidConditions: Vec<ApiConditionNodeRef>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiConditionKeyReportResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<ApiConditionKeyReport>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionValue {
+    pub key: String,
+    pub value: serde_json::Value
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PruneReason {
+    // The node's own condition didn't hold
+    #[serde(rename = "condition")]
+    Condition,
+    // Every way down from the root passes through a node whose condition
+    // didn't hold
+    #[serde(rename = "ancestor")]
+    Ancestor
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiPruning {
+    pub reason: PruneReason,
+    // The node whose condition did the pruning
+    pub nodeId: String
+}
+
+// How a node's condition was evaluated against the config tree GET used
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiConditionExplanation {
+    // None when the condition doesn't parse
+    pub normalized: Option<String>,
+    // Config values the condition refers to, for the keys that are set
+    pub values: Vec<ApiConditionValue>,
+    pub missingKeys: Vec<String>,
+    // The parse or evaluation error that left the condition unresolved
+    pub error: Option<ApiConditionDiagnostic>,
+    pub prunedBy: Option<ApiPruning>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiTreeReference {
+    pub id: String,
+    pub title: String
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiTreeDagItem {
     pub id: String,
@@ -523,6 +1258,44 @@ pub struct ApiGetOrgsResponse {
     pub result: Option<OrgMetadataList> 
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiCustomModelResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<CustomModelDefinition>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CustomModelList {
+    pub models: Vec<CustomModelDefinition>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiCustomModelListResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<CustomModelList>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiAttackerProfileResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<AttackerProfile>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AttackerProfileList {
+    pub profiles: Vec<AttackerProfile>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiAttackerProfileListResponse {
+    pub ok: bool,
+    pub message: String,
+    pub result: Option<AttackerProfileList>
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiAddMemberPayload {
     pub email: String
@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+
+use crate::constants;
 use crate::models;
 use crate::expression_evaluator;
+use crate::model_evaluator;
+use crate::tree_analysis;
 use crate::recommendations::convert_recommendations_to_list;
 use crate::recommendations::recommend_steps_for_path;
 
@@ -9,12 +14,38 @@ fn test_test_runner() {
 }
 
 #[test]
-fn test_de_json_condition() {
-    let res = expression_evaluator::de_json_condition("config[\"hello\"] == \"world\"");
-    assert_eq!(res, "config_hello == \"world\"");
+fn test_condition_parser() {
+    let normalized = |condition: &str| expression_evaluator::parse(condition).expect("Valid condition").to_string();
+
+    assert_eq!(normalized("config['hello']=='world'"), "config[\"hello\"] == \"world\"");
+    assert_eq!(normalized("config[\"a\"][\"b\"][0] >= (1 + 2) * 3"), "config[\"a\"][\"b\"][0] >= (1 + 2) * 3");
+    assert_eq!(normalized("!(config[\"x\"] || false) && true"), "!(config[\"x\"] || false) && true");
+    assert_eq!(normalized("'it\\'s' == \"say \\\"hi\\\"\""), "\"it's\" == \"say \\\"hi\\\"\"");
 
-    let res2 = expression_evaluator::de_json_condition("config[\"hello\"] == config[\"hello\"]");
-    assert_eq!(res2, "config_hello == config_hello");
+    let error = |condition: &str| expression_evaluator::parse(condition).expect_err("Invalid condition");
+    assert_eq!(error("config[\"a\"] = 1").span.start, 12);
+    assert_eq!(error("config[\"a\" == 1").message, "Expected ']' to close the lookup but found '=='");
+    assert_eq!(error("(1 == 1").span.start, 7);
+    assert_eq!(error("config[\"a] == 1").message, "Unterminated string");
+    assert_eq!(normalized("'\\u0041' == 'A'"), "\"A\" == \"A\"");
+    assert_eq!(error("'\\u+041' == 'A'").message, "Invalid unicode escape, expected \\u followed by 4 hex digits");
+    assert_eq!(error("settings[\"a\"] == 1").span, expression_evaluator::Span::new(0, 8));
+    assert_eq!(error("1 == 1 2").span.start, 7);
+    assert_eq!(error("config_a_b == 1").message, "'config_a_b' is the old flattened form of a config lookup, write it as config[\"a\"][\"b\"] (keys that contain '_' stay whole inside one [])");
+
+    // Deep nesting is refused rather than overflowing the stack
+    let too_deep = format!("Condition nests more than {} levels deep", expression_evaluator::parser::MAX_NESTING);
+    assert_eq!(error(&format!("{}true{}", "(".repeat(50000), ")".repeat(50000))).message, too_deep);
+    assert_eq!(error(&format!("{}true", "!".repeat(50000))).message, too_deep);
+    assert_eq!(error(&format!("1{} == 1", " + 1".repeat(50000))).message, too_deep);
+    assert_eq!(error(&format!("2{}", " ^ 2".repeat(50000))).message, too_deep);
+    assert_eq!(normalized(&format!("{}true{}", "(".repeat(100), ")".repeat(100))), "true");
+    let config = models::ApiProjectConfigResponseResult {
+        id: "test".to_owned(),
+        name: None,
+        attributes: serde_json::json!({})
+    };
+    assert_eq!(evaluate_condition(&format!("1{} == 100", " + 1".repeat(99)), &config), Ok(true));
 }
 
 #[test]
@@ -28,12 +59,441 @@ fn test_expression_evaluation() {
         })
     };
 
-    assert_eq!(expression_evaluator::evaluate("1 == 1", &config), true);
-    assert_eq!(expression_evaluator::evaluate("\"test\" == \"test\"", &config), true);
-    assert_eq!(expression_evaluator::evaluate("config[\"hello\"] == \"world\"", &config), true);
-    assert_eq!(expression_evaluator::evaluate("config[\"hello\"] == \"test\"", &config), false);
-    assert_eq!(expression_evaluator::evaluate("config[\"other\"] == false", &config), true);
+    assert_eq!(resolves("1 == 1", &config), true);
+    assert_eq!(resolves("\"test\" == \"test\"", &config), true);
+    assert_eq!(resolves("config[\"hello\"] == \"world\"", &config), true);
+    assert_eq!(resolves("config[\"hello\"] == \"test\"", &config), false);
+    assert_eq!(resolves("config[\"other\"] == false", &config), true);
+
+    let config = models::ApiProjectConfigResponseResult {
+        id: "test".to_owned(),
+        name: None,
+        attributes: serde_json::json!({
+            "a_b": 1,
+            "a": { "b": 2 },
+            "port": 443.0,
+            "zones": ["dmz", "lan"],
+            "with space": "yes"
+        })
+    };
+
+    // Keys are kept whole, so ones that used to collide when flattened stay apart
+    assert_eq!(resolves("config[\"a_b\"] == 1 && config[\"a\"][\"b\"] == 2", &config), true);
+    assert_eq!(resolves("config[\"port\"] == 443", &config), true);
+    assert_eq!(resolves("config[\"zones\"][1] == \"lan\"", &config), true);
+    assert_eq!(resolves("config[\"with space\"] == 'yes'", &config), true);
+    assert_eq!(resolves("config[\"port\"] / 2 > 200", &config), true);
+    assert_eq!(resolves("false && config[\"missing\"]", &config), false);
 
+    let error = |condition: &str| evaluate_condition(condition, &config).expect_err("Fails to evaluate").message;
+    assert_eq!(error("config[\"missing\"] == 1"), "config[\"missing\"] is not set");
+    assert_eq!(error("config[\"a\"][\"b\"][\"c\"] == 1"), "config[\"a\"][\"b\"] is a number, not an object");
+    assert_eq!(error("config[\"zones\"][5] == 1"), "config[\"zones\"][5] is out of range, the array has 2 items");
+    assert_eq!(error("config[\"port\"] + \"s\" == 1"), "Cannot apply '+' to a number and a string");
+    assert_eq!(error("config[\"port\"]"), "Conditions must be true or false, but this one is a number");
+}
+
+fn computed_node(id: &str, children: Vec<&str>, attributes: Vec<(&str, f64)>, resolved: bool) -> models::ApiFullComputedNodeData {
+    let mut model_attributes = HashMap::new();
+    for (key, val) in attributes {
+        model_attributes.insert(key.to_owned(), models::ModelAttribute::from_float(val));
+    }
+
+    models::ApiFullComputedNodeData {
+        id: id.to_owned(),
+        title: id.to_owned(),
+        description: "".to_owned(),
+        modelAttributes: model_attributes,
+        conditionAttribute: "".to_owned(),
+        children: children.into_iter().map(|c| c.to_owned()).collect(),
+        gateType: models::GateType::Or,
+        nodeType: models::NodeType::Attack,
+        conditionResolved: resolved,
+        infeasible: false,
+        computedAttributes: HashMap::new(),
+        mitigatedAttributes: HashMap::new(),
+        timeToCompromise: None,
+        explanation: None,
+        riskBucket: None
+    }
+}
+
+// Resolves a condition the way tree GET does, on a lone node
+fn resolves(condition: &str, config: &models::ApiProjectConfigResponseResult) -> bool {
+    let mut node = computed_node("n", vec![], vec![], true);
+    node.conditionAttribute = condition.to_owned();
+    let mut nodes = vec![node];
+    expression_evaluator::resolve_tree_conditions(&mut nodes, "n", &config.attributes);
+    nodes[0].conditionResolved
+}
+
+fn evaluate_condition(condition: &str, config: &models::ApiProjectConfigResponseResult) -> Result<bool, expression_evaluator::ConditionError> {
+    expression_evaluator::parse(condition)?.resolve(&expression_evaluator::Scope::config(&config.attributes))
+}
+
+fn computed_tree(nodes: Vec<models::ApiFullComputedNodeData>) -> models::ApiFullComputedTreeData {
+    models::ApiFullComputedTreeData {
+        title: "Test".to_owned(),
+        rootNodeId: nodes[0].id.clone(),
+        nodes,
+        rootScore: None,
+        mitigatedRootScore: None,
+        mitigationCost: 0.0,
+        contributingTrees: vec![]
+    }
+}
+
+fn computed_value(tree: &models::ApiFullComputedTreeData, node_id: &str, key: &str) -> Option<f64> {
+    tree.nodes.iter()
+        .find(|node| node.id == node_id)
+        .and_then(|node| node.computedAttributes.get(key))
+        .and_then(|attribute| attribute.as_f64())
+}
+
+#[test]
+fn test_attacker_likelihood() {
+    let mut tree = computed_tree(vec![
+        computed_node("root", vec!["a", "b", "c", "other-tree-node"], vec![], true),
+        computed_node("a", vec![], vec![("likelihood", 0.2)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.6)], true),
+        computed_node("c", vec![], vec![("likelihood", 0.9)], false)
+    ]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    // c is unresolved so the root takes b
+    assert_eq!(computed_value(&tree, "root", "likelihood"), Some(0.6));
+    assert_eq!(computed_value(&tree, "a", "likelihood"), Some(0.2));
+    assert_eq!(computed_value(&tree, "c", "likelihood"), None);
+}
+
+#[test]
+fn test_attacker_likelihood_and_gate() {
+    let mut root = computed_node("root", vec!["a", "b"], vec![], true);
+    root.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        computed_node("a", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.4)], true)
+    ]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    assert_eq!(computed_value(&tree, "root", "likelihood"), Some(0.2));
+
+    // Pruning a required step makes the AND unreachable rather than more likely
+    let mut root = computed_node("root", vec!["and", "other"], vec![], true);
+    root.gateType = models::GateType::Or;
+    let mut and = computed_node("and", vec!["a", "b", "c"], vec![], true);
+    and.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        and,
+        computed_node("a", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.8)], true),
+        computed_node("c", vec![], vec![("likelihood", 0.9)], false),
+        computed_node("other", vec!["d"], vec![("likelihood", 0.7)], true),
+        computed_node("d", vec![], vec![("likelihood", 0.3)], false)
+    ]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    assert_eq!(computed_value(&tree, "and", "likelihood"), None);
+    // Its only child is pruned, so other isn't scored as a leaf either
+    assert_eq!(computed_value(&tree, "other", "likelihood"), None);
+    assert_eq!(tree.rootScore, None);
+}
+
+#[test]
+fn test_gate_type_defaults_to_or() {
+    let node: models::ApiFullNodeData = serde_json::from_value(serde_json::json!({
+        "id": "0",
+        "title": "Old node",
+        "description": "",
+        "modelAttributes": {},
+        "conditionAttribute": "",
+        "children": []
+    })).expect("Should deserialize");
+
+    assert_eq!(node.gateType, models::GateType::Or);
+    assert_eq!(models::GateType::parse("sand"), models::GateType::SequentialAnd);
+}
+
+#[test]
+fn test_evita() {
+    let mut root = computed_node("root", vec!["a", "b"], vec![("safetySeverity", 3.0), ("financialSeverity", 2.0)], true);
+    root.gateType = models::GateType::Or;
+    let mut easy = computed_node("a", vec![], vec![("elapsedTime", 1.0), ("expertise", 3.0)], true);
+    easy.modelAttributes.insert("equipment".to_owned(), models::ModelAttribute {
+        value_string: Some("Specialized".to_owned()),
+        value_int: None,
+        value_float: None
+    });
+    let hard = computed_node("b", vec![], vec![("elapsedTime", 19.0), ("expertise", 8.0)], true);
+    let mut tree = computed_tree(vec![root, easy, hard]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::EVITA_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    // Easiest path is 1 + 3 + 4 = 8 points, a basic attack potential
+    assert_eq!(computed_value(&tree, "root", "attackPotential"), Some(8.0));
+    assert_eq!(computed_value(&tree, "root", "attackProbability"), Some(5.0));
+    assert_eq!(computed_value(&tree, "root", "riskLevel"), Some(5.0));
+    assert_eq!(computed_value(&tree, "b", "attackProbability"), Some(1.0));
+    // b inherits the root's severities
+    assert_eq!(computed_value(&tree, "b", "riskLevel"), Some(1.0));
+
+    assert_eq!(model_evaluator::evita::evita_risk_level(0, 5), 0);
+    assert_eq!(model_evaluator::evita::evita_risk_level(1, 1), 0);
+    assert_eq!(model_evaluator::evita::evita_risk_level(4, 5), 6);
+}
+
+#[test]
+fn test_risk_of_attack() {
+    let mut tree = computed_tree(vec![
+        computed_node("root", vec!["a", "b"], vec![("impact", 5.0)], true),
+        computed_node("a", vec![], vec![("likelihood", 0.3)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.9), ("impact", 1.0)], true)
+    ]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::RISK_OF_ATTACK_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    assert_eq!(tree.rootScore, Some(4.5));
+    let bucket = |node_id: &str| tree.nodes.iter()
+        .find(|node| node.id == node_id)
+        .and_then(|node| node.riskBucket.clone());
+    assert_eq!(bucket("root"), Some("Critical".to_owned()));
+    assert_eq!(bucket("a"), Some("High".to_owned()));
+    assert_eq!(bucket("b"), Some("Medium".to_owned()));
+    assert!(tree.nodes.iter().all(|node| !node.computedAttributes.contains_key("riskBucket")));
+}
+
+#[test]
+fn test_model_registry() {
+    let registry = model_evaluator::registry();
+
+    let listed: Vec<String> = registry.list().into_iter().map(|model| model.id).collect();
+    assert!(listed.contains(&constants::EVITA_MODEL_ID.to_owned()));
+    assert!(registry.get("not-a-model").is_none());
+
+    let evita = registry.get(constants::EVITA_MODEL_ID).expect("EVITA should be registered");
+    let mut attributes = HashMap::new();
+    attributes.insert("expertise".to_owned(), models::ModelAttribute {
+        value_string: Some("Expert".to_owned()),
+        value_int: None,
+        value_float: None
+    });
+    attributes.insert("safetySeverity".to_owned(), models::ModelAttribute::from_int(7));
+    attributes.insert("colour".to_owned(), models::ModelAttribute::from_int(1));
+
+    let problems = model_evaluator::validate_attributes(evita, &attributes);
+    let keys: Vec<&str> = problems.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["colour", "safetySeverity"]);
+}
+
+#[test]
+fn test_rollup_across_linked_trees() {
+    let mut tree = computed_tree(vec![
+        computed_node("root", vec!["a", "linked-root"], vec![], true),
+        computed_node("a", vec![], vec![("likelihood", 0.2)], true)
+    ]);
+    // The linked tree points back at this tree's root, which must not loop
+    let linked = vec![model_evaluator::LinkedTree {
+        id: "other-tree".to_owned(),
+        title: "Other".to_owned(),
+        nodes: vec![
+            computed_node("linked-root", vec!["linked-leaf", "root"], vec![], true),
+            computed_node("linked-leaf", vec![], vec![("likelihood", 0.8)], true)
+        ]
+    }];
+
+    model_evaluator::compute_with_links(&mut tree, &linked, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    assert_eq!(tree.rootScore, Some(0.8));
+    assert_eq!(tree.nodes.len(), 2);
+    assert_eq!(tree.contributingTrees, vec![models::ApiTreeReference {
+        id: "other-tree".to_owned(),
+        title: "Other".to_owned()
+    }]);
+
+    // Without the links the root only sees a
+    let mut tree = computed_tree(vec![
+        computed_node("root", vec!["a", "linked-root"], vec![], true),
+        computed_node("a", vec![], vec![("likelihood", 0.2)], true)
+    ]);
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+    assert_eq!(tree.rootScore, Some(0.2));
+    assert!(tree.contributingTrees.is_empty());
+}
+
+#[test]
+fn test_minimal_cut_sets() {
+    let mut root = computed_node("root", vec!["a", "gate"], vec![], true);
+    root.gateType = models::GateType::Or;
+    let mut gate = computed_node("gate", vec!["b", "c", "d"], vec![], true);
+    gate.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        gate,
+        computed_node("a", vec![], vec![("likelihood", 0.1)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("c", vec![], vec![("likelihood", 0.8)], true),
+        computed_node("d", vec![], vec![("likelihood", 0.9)], false)
+    ]);
+
+    // d is unresolved, so the AND can't be achieved at all
+    let cut_sets = tree_analysis::cutsets::minimal_cut_sets(&tree.nodes, &tree.rootNodeId);
+    assert!(!cut_sets.truncated);
+    assert_eq!(cut_sets.sets, vec![vec!["a".to_owned()]]);
+
+    tree.nodes[5].conditionResolved = true;
+    let cut_sets = tree_analysis::cutsets::minimal_cut_sets(&tree.nodes, &tree.rootNodeId);
+    assert_eq!(cut_sets.sets, vec![vec!["a".to_owned()], vec!["b".to_owned(), "c".to_owned(), "d".to_owned()]]);
+
+    let model = model_evaluator::registry().get(constants::ATTACKER_LIKELIHOOD_MODEL_ID);
+    let scored = tree_analysis::cutsets::score_cut_sets(&tree.nodes, &cut_sets, model);
+    assert_eq!(scored[0].nodeIds, vec!["a".to_owned()]);
+    assert!((scored[1].score.expect("Scored") - 0.36).abs() < 1e-9);
+
+    // Sets containing a smaller set aren't minimal
+    tree.nodes[1].children.push("a".to_owned());
+    let cut_sets = tree_analysis::cutsets::minimal_cut_sets(&tree.nodes, &tree.rootNodeId);
+    assert_eq!(cut_sets.sets, vec![vec!["a".to_owned()]]);
+}
+
+#[test]
+fn test_best_attack_path() {
+    let mut gate = computed_node("gate", vec!["b", "c"], vec![], true);
+    gate.gateType = models::GateType::SequentialAnd;
+    let tree = computed_tree(vec![
+        computed_node("root", vec!["a", "gate", "d"], vec![], true),
+        gate,
+        computed_node("a", vec![], vec![("cost", 500.0), ("likelihood", 0.3), ("time", 4.0)], true),
+        computed_node("b", vec![], vec![("cost", 100.0), ("likelihood", 0.9), ("time", 2.0)], true),
+        computed_node("c", vec![], vec![("cost", 150.0), ("likelihood", 0.5), ("time", 3.0)], true),
+        computed_node("d", vec![], vec![("cost", 1.0), ("likelihood", 1.0), ("time", 0.0)], false)
+    ]);
+
+    let cheapest = tree_analysis::paths::best_attack_path(&tree.nodes, &tree.rootNodeId, tree_analysis::paths::PathObjective::Cost).expect("Should find a path");
+    assert_eq!(cheapest.node_ids, vec!["root", "gate", "b", "c"]);
+    assert_eq!(cheapest.value, 250.0);
+
+    let likeliest = tree_analysis::paths::best_attack_path(&tree.nodes, &tree.rootNodeId, tree_analysis::paths::PathObjective::Likelihood).expect("Should find a path");
+    assert_eq!(likeliest.value, 0.45);
+
+    // SAND steps happen one after another
+    let fastest = tree_analysis::paths::best_attack_path(&tree.nodes, &tree.rootNodeId, tree_analysis::paths::PathObjective::Time).expect("Should find a path");
+    assert_eq!(fastest.node_ids, vec!["root", "a"]);
+    assert_eq!(fastest.value, 4.0);
+
+    // Both halves of the AND need the same foothold, which is only paid for once
+    let mut root = computed_node("root", vec!["left", "right"], vec![], true);
+    root.gateType = models::GateType::And;
+    let mut left = computed_node("left", vec!["foothold", "b"], vec![], true);
+    left.gateType = models::GateType::And;
+    let mut right = computed_node("right", vec!["foothold", "c"], vec![], true);
+    right.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        left,
+        right,
+        computed_node("foothold", vec![], vec![("cost", 1000.0)], true),
+        computed_node("b", vec![], vec![("cost", 100.0)], true),
+        computed_node("c", vec![], vec![("cost", 10.0)], true)
+    ]);
+
+    let cheapest = tree_analysis::paths::best_attack_path(&tree.nodes, &tree.rootNodeId, tree_analysis::paths::PathObjective::Cost).expect("Should find a path");
+    assert_eq!(cheapest.node_ids, vec!["root", "left", "foothold", "b", "right", "c"]);
+    assert_eq!(cheapest.value, 1110.0);
+
+    // A required step that's pruned leaves no path at all
+    tree.nodes[5].conditionResolved = false;
+    assert!(tree_analysis::paths::best_attack_path(&tree.nodes, &tree.rootNodeId, tree_analysis::paths::PathObjective::Cost).is_none());
+}
+
+#[test]
+fn test_simulation_is_reproducible() {
+    let mut uncertain = computed_node("b", vec![], vec![("likelihoodMin", 0.2), ("likelihoodMax", 0.6)], true);
+    uncertain.modelAttributes.insert("likelihood".to_owned(), models::ModelAttribute::from_float(0.3));
+    let tree = computed_tree(vec![
+        computed_node("root", vec!["a", "b"], vec![], true),
+        computed_node("a", vec![], vec![("likelihood", 0.5)], true),
+        uncertain
+    ]);
+    let node_ids = vec!["root".to_owned(), "a".to_owned()];
+
+    let first = tree_analysis::simulation::simulate(&tree.nodes, &node_ids, 500, 42, 10);
+    let second = tree_analysis::simulation::simulate(&tree.nodes, &node_ids, 500, 42, 10);
+    assert_eq!(first[0].mean, second[0].mean);
+    assert_eq!(first[0].samples, 500);
+    assert_eq!(first[0].histogram.iter().map(|bin| bin.count).sum::<u32>(), 500);
+
+    // A fixed leaf never varies, and an OR of 0.5 with 0.2..0.6 takes the
+    // likelier of the two, as the likelihood model does
+    let leaf = first[1].percentiles.as_ref().expect("Should have samples");
+    assert_eq!((leaf.p5, leaf.p95), (0.5, 0.5));
+    let root = first[0].percentiles.as_ref().expect("Should have samples");
+    assert!(root.p5 == 0.5 && root.p95 <= 0.6 && root.p95 > 0.5);
+
+    // With only fixed leaves the simulation matches the tree's score
+    let fixed = computed_tree(vec![
+        computed_node("root", vec!["a", "b"], vec![], true),
+        computed_node("a", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.3)], true)
+    ]);
+    let simulated = tree_analysis::simulation::simulate(&fixed.nodes, &["root".to_owned()], 10, 42, 10);
+    assert_eq!(simulated[0].mean, Some(0.5));
+
+    assert_eq!(tree_analysis::simulation::LeafDistribution::from_node(&tree.nodes[2]), Some(tree_analysis::simulation::LeafDistribution::Triangular(0.2, 0.3, 0.6)));
+}
+
+#[test]
+fn test_custom_formula_model() {
+    let payload: models::CustomModelPayload = serde_json::from_value(serde_json::json!({
+        "title": "Cost to attack",
+        "attributes": [
+            {"key": "cost", "valueType": "number", "min": 0.0},
+            {"key": "difficulty", "valueType": "number", "default": {"value_string": null, "value_int": 1, "value_float": null}}
+        ],
+        "leafFormula": "cost * difficulty",
+        "aggregationFormula": "if(gate == \"or\", min(values), sum(values))"
+    })).expect("Should deserialize");
+    let definition = models::CustomModelDefinition::from_payload("custom".to_owned(), "org".to_owned(), &payload);
+    assert!(model_evaluator::custom::validate_definition(&definition).is_ok());
+
+    let mut root = computed_node("root", vec!["a", "b"], vec![], true);
+    root.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        computed_node("a", vec![], vec![("cost", 100.0), ("difficulty", 3.0)], true),
+        computed_node("b", vec![], vec![("cost", 50.0)], true)
+    ]);
+
+    let context = model_evaluator::ModelContext {
+        risk_matrix: models::RiskMatrix::default(),
+        custom_models: vec![model_evaluator::custom::FormulaModel::new(definition.clone())]
+    };
+    model_evaluator::compute(&mut tree, Some(&"custom".to_owned()), &context);
+
+    // b falls back to the default difficulty of 1
+    assert_eq!(computed_value(&tree, "b", "score"), Some(50.0));
+    assert_eq!(tree.rootScore, Some(350.0));
+
+    let mut broken = definition;
+    broken.aggregationFormula = "min(values".to_owned();
+    assert!(model_evaluator::custom::validate_definition(&broken).is_err());
+}
+
+#[test]
+fn test_risk_matrix_validation() {
+    assert!(models::RiskMatrix::default().validate().is_ok());
+
+    let mut matrix = models::RiskMatrix::default();
+    matrix.cells[0][0] = "Severe".to_owned();
+    assert!(matrix.validate().is_err());
+
+    let mut matrix = models::RiskMatrix::default();
+    matrix.likelihoodThresholds = vec![0.5];
+    assert!(matrix.validate().is_err());
 }
 
 #[tokio::test]
@@ -49,3 +509,557 @@ async fn test_recommendations() {
     assert!(final_list.len() > 1);
 }
 
+
+#[test]
+fn test_countermeasures_mitigate_scores() {
+    let mut countermeasure = computed_node("c", vec![], vec![("effectiveness", 0.5), ("cost", 100.0)], true);
+    countermeasure.nodeType = models::NodeType::Countermeasure;
+    let mut unused = computed_node("d", vec![], vec![("effectiveness", 0.9), ("cost", 50.0)], false);
+    unused.nodeType = models::NodeType::Countermeasure;
+    let mut tree = computed_tree(vec![
+        computed_node("root", vec!["a", "b"], vec![], true),
+        computed_node("a", vec!["c", "d"], vec![("likelihood", 0.5)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.4)], true),
+        countermeasure,
+        unused
+    ]);
+
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+
+    // The countermeasure isn't an attack step, so a still counts as a leaf
+    assert_eq!(computed_value(&tree, "a", "likelihood"), Some(0.5));
+    assert_eq!(computed_value(&tree, "c", "likelihood"), None);
+    assert_eq!(tree.rootScore, Some(0.5));
+
+    let mitigated = tree.nodes.iter().find(|node| node.id == "a").and_then(|node| node.mitigatedAttributes.get("likelihood")).and_then(|attribute| attribute.as_f64());
+    assert_eq!(mitigated, Some(0.25));
+    // b is now the easier way in
+    assert_eq!(tree.mitigatedRootScore, Some(0.4));
+    // d's condition doesn't hold so it neither applies nor costs anything
+    assert_eq!(tree.mitigationCost, 100.0);
+
+    assert_eq!(model_evaluator::AnalysisModel::mitigate(&model_evaluator::evita::Evita, 5.0, 0.5), 15.0);
+}
+
+#[test]
+fn test_sensitivity_ranks_leaves() {
+    let mut root = computed_node("root", vec!["a", "b"], vec![], true);
+    root.gateType = models::GateType::And;
+    let tree = computed_tree(vec![
+        root,
+        computed_node("a", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("b", vec![], vec![("likelihood", 0.9)], true)
+    ]);
+
+    let context = model_evaluator::ModelContext::default();
+    let model = context.find_model(constants::ATTACKER_LIKELIHOOD_MODEL_ID).expect("Built in");
+    let result = tree_analysis::sensitivity::sensitivity(&tree, &[], model, &context, 0.1);
+
+    assert!((result.baselineScore.expect("Scored") - 0.45).abs() < 1e-9);
+    assert_eq!(result.ranking.iter().map(|entry| entry.nodeId.as_str()).collect::<Vec<&str>>(), vec!["a", "b"]);
+    assert!((result.ranking[0].swing - 0.09).abs() < 1e-9);
+    assert!((result.ranking[1].swing - 0.05).abs() < 1e-9);
+    assert!((result.ranking[1].highScore.expect("Scored") - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn test_mitigation_optimizer() {
+    let countermeasure = |id: &str, effectiveness: f64, cost: f64| {
+        let mut node = computed_node(id, vec![], vec![("effectiveness", effectiveness), ("cost", cost)], true);
+        node.nodeType = models::NodeType::Countermeasure;
+        node
+    };
+    let tree = computed_tree(vec![
+        computed_node("root", vec!["a", "b"], vec![], true),
+        computed_node("a", vec!["c1", "c2"], vec![("likelihood", 0.8)], true),
+        computed_node("b", vec!["c3"], vec![("likelihood", 0.3)], true),
+        countermeasure("c1", 0.5, 100.0),
+        countermeasure("c2", 0.75, 300.0),
+        countermeasure("c3", 0.5, 50.0)
+    ]);
+
+    let context = model_evaluator::ModelContext::default();
+    let model = context.find_model(constants::ATTACKER_LIKELIHOOD_MODEL_ID).expect("Built in");
+
+    let plan = tree_analysis::mitigation::optimize(&tree, &[], model, &context, 350.0);
+    assert!(plan.exact);
+    assert_eq!(plan.baselineScore, Some(0.8));
+    assert_eq!(plan.best.countermeasureIds, vec!["c2", "c3"]);
+    assert!((plan.best.residualScore.expect("Scored") - 0.2).abs() < 1e-9);
+
+    // Spending another 50 on c3 doesn't help until a is mitigated below b
+    let plan = tree_analysis::mitigation::optimize(&tree, &[], model, &context, 200.0);
+    assert_eq!(plan.best.countermeasureIds, vec!["c1"]);
+
+    let frontier: Vec<f64> = plan.frontier.iter().map(|portfolio| portfolio.cost).collect();
+    assert_eq!(frontier, vec![0.0, 100.0, 300.0, 350.0, 450.0]);
+
+    // Under a cost to attack model a lower residual helps the attacker, and
+    // the default mitigation only ever lowers it
+    let payload: models::CustomModelPayload = serde_json::from_value(serde_json::json!({
+        "title": "Cost to attack",
+        "attributes": [{"key": "likelihood", "valueType": "number"}],
+        "leafFormula": "likelihood",
+        "aggregationFormula": "if(gate == \"or\", min(values), sum(values))"
+    })).expect("Should deserialize");
+    let context = model_evaluator::ModelContext {
+        risk_matrix: models::RiskMatrix::default(),
+        custom_models: vec![model_evaluator::custom::FormulaModel::new(models::CustomModelDefinition::from_payload("custom".to_owned(), "org".to_owned(), &payload))]
+    };
+    let model = context.find_model("custom").expect("Custom model");
+    assert!(!model.attacker_prefers_higher());
+
+    let plan = tree_analysis::mitigation::optimize(&tree, &[], model, &context, 450.0);
+    assert_eq!(plan.baselineScore, Some(0.3));
+    assert!(plan.best.countermeasureIds.is_empty());
+    assert_eq!(plan.frontier.len(), 1);
+}
+
+#[test]
+fn test_attacker_profile_feasibility() {
+    let profile = |skill: f64, budget: f64, access: &str| models::AttackerProfile {
+        id: "".to_owned(),
+        projectId: "".to_owned(),
+        name: "Test".to_owned(),
+        skill: Some(skill),
+        budget: Some(budget),
+        access: Some(access.to_owned()),
+        time: None
+    };
+    let build_tree = || {
+        let mut root = computed_node("root", vec!["a", "b"], vec![], true);
+        root.gateType = models::GateType::And;
+        let mut b = computed_node("b", vec![], vec![("likelihood", 0.5)], true);
+        b.modelAttributes.insert("access".to_owned(), models::ModelAttribute::from_string("Physical"));
+
+        computed_tree(vec![
+            root,
+            computed_node("a", vec!["a1", "a2"], vec![], true),
+            computed_node("a1", vec![], vec![("likelihood", 0.9), ("skill", 8.0)], true),
+            computed_node("a2", vec![], vec![("likelihood", 0.4), ("skill", 3.0), ("cost", 5000.0)], true),
+            b
+        ])
+    };
+    let infeasible = |tree: &models::ApiFullComputedTreeData| tree.nodes.iter()
+        .filter(|node| node.infeasible)
+        .map(|node| node.id.clone())
+        .collect::<Vec<String>>();
+
+    let mut tree = build_tree();
+    model_evaluator::feasibility::apply_profile(&mut tree, &mut [], &profile(4.0, 100.0, "network"));
+    assert_eq!(infeasible(&tree), vec!["root", "a", "a1", "a2", "b"]);
+
+    let mut tree = build_tree();
+    model_evaluator::feasibility::apply_profile(&mut tree, &mut [], &profile(5.0, 10000.0, "physical"));
+    assert_eq!(infeasible(&tree), vec!["a1"]);
+
+    // The pruned leaf no longer counts towards the rollup
+    model_evaluator::compute(&mut tree, Some(&constants::ATTACKER_LIKELIHOOD_MODEL_ID.to_owned()), &model_evaluator::ModelContext::default());
+    assert!((tree.rootScore.expect("Scored") - 0.2).abs() < 1e-9);
+}
+
+#[test]
+fn test_time_to_compromise() {
+    let mut root = computed_node("root", vec!["a", "b"], vec![], true);
+    root.gateType = models::GateType::SequentialAnd;
+    let mut a = computed_node("a", vec!["a1", "a2"], vec![], true);
+    a.gateType = models::GateType::And;
+    let mut tree = computed_tree(vec![
+        root,
+        a,
+        computed_node("a1", vec![], vec![("time", 2.0)], true),
+        computed_node("a2", vec![], vec![("timeMin", 1.0), ("timeMax", 5.0)], true),
+        computed_node("b", vec!["b1", "b2", "b3"], vec![], true),
+        computed_node("b1", vec![], vec![("time", 3.0)], true),
+        computed_node("b2", vec![], vec![("time", 10.0)], true),
+        computed_node("b3", vec![], vec![("time", 0.5)], false)
+    ]);
+
+    // Times don't depend on the selected model
+    model_evaluator::compute(&mut tree, None, &model_evaluator::ModelContext::default());
+
+    let time = |node_id: &str| tree.nodes.iter().find(|node| node.id == node_id).and_then(|node| node.timeToCompromise.clone());
+    assert_eq!(time("a"), Some(models::ApiTimeRange { min: 2.0, max: 5.0 }));
+    assert_eq!(time("b"), Some(models::ApiTimeRange { min: 3.0, max: 3.0 }));
+    assert_eq!(time("root"), Some(models::ApiTimeRange { min: 5.0, max: 8.0 }));
+    assert_eq!(time("b3"), None);
+}
+
+#[test]
+fn test_cvss_vectors() {
+    let score = |vector: &str| model_evaluator::cvss::CvssVector::parse(vector).expect("Valid vector").base_score();
+
+    assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), 9.8);
+    assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), 10.0);
+    assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"), 6.1);
+    assert_eq!(score("CVSS:3.1/AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:H/A:H/E:F"), 7.8);
+    assert_eq!(score("CVSS:3.0/AV:N/AC:H/PR:N/UI:N/S:U/C:H/I:N/A:N"), 5.9);
+    assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), 0.0);
+
+    // v4.0 scores, checked against FIRST's calculator
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"), 9.3);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:H/SI:H/SA:H"), 10.0);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:L/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"), 8.7);
+    assert_eq!(score("CVSS:4.0/AV:L/AC:L/AT:N/PR:L/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"), 8.5);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:L/VI:N/VA:N/SC:N/SI:N/SA:N"), 6.9);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:N/VI:N/VA:H/SC:N/SI:N/SA:N"), 8.7);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:A/VC:N/VI:N/VA:N/SC:L/SI:L/SA:N"), 5.1);
+    assert_eq!(score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:N/VI:N/VA:N/SC:N/SI:N/SA:N"), 0.0);
+    // Modified metrics replace the base ones
+    assert_eq!(score("CVSS:4.0/AV:L/AC:L/AT:N/PR:L/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N/MAV:N"), 8.7);
+
+    assert!(model_evaluator::cvss::CvssVector::parse("AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+    assert!(model_evaluator::cvss::CvssVector::parse("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H").is_err());
+    assert!(model_evaluator::cvss::CvssVector::parse("CVSS:3.1/AV:X/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+    assert!(model_evaluator::cvss::CvssVector::parse("CVSS:3.1/AV:N/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+    assert!(model_evaluator::cvss::CvssVector::parse("CVSS:4.0/AV:N/AC:L/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N").is_err());
+
+    // The base score stands in for a missing likelihood
+    let mut attributes = HashMap::new();
+    attributes.insert("cvss".to_owned(), models::ModelAttribute::from_string("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"));
+    assert_eq!(model_evaluator::cvss::base_score(&attributes), Some(9.8));
+    assert!((model_evaluator::likelihood::leaf_likelihood(&attributes).expect("Has a likelihood") - 0.98).abs() < 1e-9);
+
+    // A stored score never outlives the vector it came from
+    attributes.insert("cvssBaseScore".to_owned(), models::ModelAttribute::from_float(9.8));
+    attributes.remove("cvss");
+    assert_eq!(model_evaluator::likelihood::leaf_likelihood(&attributes), None);
+
+    // The score is only ever returned as a computed attribute
+    let mut leaf = computed_node("leaf", vec![], vec![], true);
+    leaf.modelAttributes.insert("cvss".to_owned(), models::ModelAttribute::from_string("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"));
+    let mut tree = computed_tree(vec![leaf]);
+    model_evaluator::compute(&mut tree, None, &model_evaluator::ModelContext::default());
+    assert_eq!(tree.nodes[0].computedAttributes.get("cvssBaseScore").and_then(|attribute| attribute.as_f64()), Some(6.1));
+    assert!(!tree.nodes[0].modelAttributes.contains_key("cvssBaseScore"));
+}
+
+#[test]
+fn test_config_matrix() {
+    let evaluation = |config_id: &str, b1: bool, b2: bool, score: f64| {
+        let mut tree = computed_tree(vec![
+            computed_node("root", vec!["b1", "b2"], vec![], true),
+            computed_node("b1", vec![], vec![], b1),
+            computed_node("b2", vec![], vec![], b2),
+        ]);
+        tree.rootScore = Some(score);
+
+        tree_analysis::config_matrix::ConfigEvaluation {
+            config_id: config_id.to_owned(),
+            name: Some(config_id.to_uppercase()),
+            tree: Ok(tree)
+        }
+    };
+
+    let broken = tree_analysis::config_matrix::ConfigEvaluation {
+        config_id: "deleted".to_owned(),
+        name: None,
+        tree: Err("Could not load config".to_owned())
+    };
+
+    let matrix = tree_analysis::config_matrix::build(&[
+        broken,
+        evaluation("cloud", true, true, 0.5),
+        evaluation("onprem", true, false, 0.2),
+    ]);
+
+    assert_eq!(matrix.configs.iter().map(|column| column.configId.as_str()).collect::<Vec<_>>(), vec!["deleted", "cloud", "onprem"]);
+    assert_eq!(matrix.configs[2].name, Some("ONPREM".to_owned()));
+    assert_eq!(matrix.configs.iter().map(|column| column.rootScore).collect::<Vec<_>>(), vec![None, Some(0.5), Some(0.2)]);
+    assert_eq!(matrix.configs[0].error, Some("Could not load config".to_owned()));
+    assert_eq!(matrix.configs[1].error, None);
+
+    // The failed column neither resolves nor counts towards varies
+    let row = |node_id: &str| matrix.nodes.iter().find(|row| row.nodeId == node_id).expect("Row exists");
+    assert_eq!(row("b1").conditionResolved, vec![None, Some(true), Some(true)]);
+    assert!(!row("b1").varies);
+    assert_eq!(row("b2").conditionResolved, vec![None, Some(true), Some(false)]);
+    assert!(row("b2").varies);
+
+    assert!(tree_analysis::config_matrix::build(&[]).nodes.is_empty());
+}
+
+#[test]
+fn test_condition_validation() {
+    let attributes = serde_json::json!({
+        "network": { "zone": "dmz" },
+        "ports": [22, 443]
+    });
+
+    let result = expression_evaluator::validate("config['network']['zone']=='dmz' && config[\"ports\"][1] == 443 || config[\"vpn\"]", Some(&attributes));
+    assert!(result.valid);
+    assert_eq!(result.normalized, Some("config[\"network\"][\"zone\"] == \"dmz\" && config[\"ports\"][1] == 443 || config[\"vpn\"]".to_owned()));
+    assert_eq!(result.keys.iter().map(|key| (key.key.as_str(), key.exists)).collect::<Vec<_>>(), vec![
+        ("config[\"network\"][\"zone\"]", Some(true)),
+        ("config[\"ports\"][1]", Some(true)),
+        ("config[\"vpn\"]", Some(false)),
+    ]);
+    assert_eq!(result.keys[1].path, vec![serde_json::json!("ports"), serde_json::json!(1)]);
+    // The left side already holds, so the missing key is never read
+    assert_eq!(result.resolved, Some(true));
+
+    let result = expression_evaluator::validate("config[\"vpn\"] == true", Some(&attributes));
+    assert_eq!(result.resolved, None);
+    assert_eq!(result.evaluationError.map(|err| (err.start, err.end)), Some((0, 13)));
+
+    let result = expression_evaluator::validate("config[\"vpn\"] == true", None);
+    assert_eq!(result.keys[0].exists, None);
+    assert_eq!(result.resolved, None);
+
+    let result = expression_evaluator::validate("config[\"vpn\"] == (true", None);
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].start, 22);
+
+    let mut node = models::ApiFullNodeData {
+        id: "n1".to_owned(),
+        title: "n1".to_owned(),
+        description: "".to_owned(),
+        modelAttributes: HashMap::new(),
+        conditionAttribute: "config[\"a\"] = 1".to_owned(),
+        children: vec![],
+        gateType: models::GateType::Or,
+        nodeType: models::NodeType::Attack
+    };
+    let issues = expression_evaluator::validate_tree_conditions(&[node.clone()]);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].field, "conditionAttribute");
+    assert_eq!(issues[0].message, "Unexpected '=', use '==' to compare at position 12");
+
+    node.conditionAttribute = "".to_owned();
+    assert!(expression_evaluator::validate_tree_conditions(&[node]).is_empty());
+}
+
+#[test]
+fn test_explain_conditions() {
+    let attributes = serde_json::json!({ "internet": false, "zone": "dmz" });
+
+    let mut nodes = vec![
+        computed_node("root", vec!["remote", "local"], vec![], true),
+        computed_node("remote", vec!["exploit"], vec![], false),
+        computed_node("exploit", vec!["payload"], vec![], true),
+        computed_node("payload", vec![], vec![], true),
+        computed_node("local", vec!["shared"], vec![], false),
+        computed_node("shared", vec![], vec![], true),
+    ];
+    nodes[1].conditionAttribute = "config['internet'] && config[\"zone\"] == 'dmz'".to_owned();
+    nodes[4].conditionAttribute = "config[\"vpn\"] == true".to_owned();
+    nodes[2].children.push("shared".to_owned());
+    let mut tree = computed_tree(nodes);
+
+    expression_evaluator::explain_tree(&mut tree, Some(&attributes));
+    let explanation = |tree: &models::ApiFullComputedTreeData, node_id: &str| tree.nodes.iter().find(|node| node.id == node_id).and_then(|node| node.explanation.clone()).expect("Explained");
+
+    let remote = explanation(&tree, "remote");
+    assert_eq!(remote.normalized, Some("config[\"internet\"] && config[\"zone\"] == \"dmz\"".to_owned()));
+    assert_eq!(remote.values, vec![
+        models::ApiConditionValue { key: "config[\"internet\"]".to_owned(), value: serde_json::json!(false) },
+        models::ApiConditionValue { key: "config[\"zone\"]".to_owned(), value: serde_json::json!("dmz") },
+    ]);
+    assert!(remote.missingKeys.is_empty());
+    assert_eq!(remote.error, None);
+    assert_eq!(remote.prunedBy, Some(models::ApiPruning { reason: models::PruneReason::Condition, nodeId: "remote".to_owned() }));
+
+    let local = explanation(&tree, "local");
+    assert_eq!(local.missingKeys, vec!["config[\"vpn\"]".to_owned()]);
+    assert_eq!(local.error.map(|err| err.message), Some("config[\"vpn\"] is not set".to_owned()));
+
+    // Pruned two levels up
+    assert_eq!(explanation(&tree, "payload").prunedBy, Some(models::ApiPruning { reason: models::PruneReason::Ancestor, nodeId: "remote".to_owned() }));
+    // Every parent of shared is pruned, the closest one is reported
+    assert_eq!(explanation(&tree, "shared").prunedBy.map(|pruning| pruning.reason), Some(models::PruneReason::Ancestor));
+    assert_eq!(explanation(&tree, "root").prunedBy, None);
+    assert_eq!(explanation(&tree, "root").normalized, Some("".to_owned()));
+
+    // Without a config every condition fails to evaluate
+    expression_evaluator::explain_tree(&mut tree, None);
+    assert_eq!(explanation(&tree, "remote").error.map(|err| err.message), Some("No config could be loaded to evaluate against".to_owned()));
+    assert_eq!(explanation(&tree, "root").error, None);
+}
+
+#[test]
+fn test_condition_collections() {
+    let config = models::ApiProjectConfigResponseResult {
+        id: "test".to_owned(),
+        name: None,
+        attributes: serde_json::json!({
+            "regions": ["eu", "us"],
+            "ports": [22, 80, 443],
+            "owner": null,
+            "limits": { "sessions": 5.0 }
+        })
+    };
+    let evaluate = |condition: &str| evaluate_condition(condition, &config);
+
+    assert_eq!(evaluate("\"eu\" in config[\"regions\"]"), Ok(true));
+    assert_eq!(evaluate("\"apac\" in config[\"regions\"]"), Ok(false));
+    assert_eq!(evaluate("443.0 in config[\"ports\"] && !(8080 in config[\"ports\"])"), Ok(true));
+    assert_eq!(evaluate("len(config[\"ports\"]) > 2 && len(\"héllo\") == 5"), Ok(true));
+    assert_eq!(evaluate("config[\"regions\"] == [\"eu\", \"us\"] && config[\"limits\"] == default(config[\"other\"], config[\"limits\"])"), Ok(true));
+    assert_eq!(evaluate("\"sessions\" in config[\"limits\"] && \"ur\" in \"europe\""), Ok(true));
+    assert_eq!(evaluate("config[\"owner\"] == null && config[\"regions\"] != null"), Ok(true));
+    assert_eq!(evaluate("len([]) == 0"), Ok(true));
+
+    // Missing keys are errors unless a default is given
+    assert_eq!(evaluate("config[\"vpn\"] == true").map_err(|err| err.missing_key), Err(true));
+    assert_eq!(evaluate("default(config[\"vpn\"], false) == false"), Ok(true));
+    assert_eq!(evaluate("default(config[\"ports\"][7], 0) == 0"), Ok(true));
+    assert_eq!(evaluate("default(config[\"owner\"], \"nobody\") == \"nobody\""), Ok(true));
+    // Type errors aren't papered over
+    assert!(evaluate("default(len(config[\"limits\"][\"sessions\"]), 0) == 0").is_err());
+    assert_eq!(evaluate("5 in 5").map_err(|err| err.message), Err("Cannot apply 'in' to a number and a number".to_owned()));
+
+    let normalized = |condition: &str| expression_evaluator::parse(condition).expect("Valid condition").to_string();
+    assert_eq!(normalized("'eu' in config['regions']"), "\"eu\" in config[\"regions\"]");
+    assert_eq!(normalized("default( config['a'],[1,2] )==null"), "default(config[\"a\"], [1, 2]) == null");
+
+    let error = |condition: &str| expression_evaluator::parse(condition).expect_err("Invalid condition").message;
+    assert_eq!(error("len(config[\"a\"], 2) > 1"), "len() takes 1 argument but was given 2");
+    assert_eq!(error("lenn(config[\"a\"]) > 1"), "Unknown name 'lenn', values come from config[\"<key>\"], node[\"<id>\"] or self");
+    assert_eq!(error("[1, 2 == 1"), "Expected ',' or ']' to close the array but the condition ended");
+
+    let paths = expression_evaluator::parse("default(config[\"a\"], 1) in [config[\"b\"]]").expect("Valid condition").config_paths();
+    assert_eq!(paths.len(), 2);
+}
+
+#[test]
+fn test_node_references() {
+    let attributes = serde_json::json!({ "internet": true });
+
+    // Dependents come before the nodes they refer to, so order matters
+    let mut nodes = vec![
+        computed_node("root", vec!["exfil", "phish", "loop_a", "loop_b", "remote"], vec![], true),
+        computed_node("exfil", vec![], vec![("likelihood", 0.5)], true),
+        computed_node("phish", vec![], vec![("likelihood", 0.1)], true),
+        computed_node("foothold", vec![], vec![], true),
+        computed_node("loop_a", vec![], vec![], true),
+        computed_node("loop_b", vec![], vec![], true),
+        computed_node("remote", vec!["foothold"], vec![], true),
+    ];
+    nodes[1].conditionAttribute = "node[\"foothold\"].resolved && self.modelAttributes[\"likelihood\"] > 0.3".to_owned();
+    nodes[2].conditionAttribute = "self.modelAttributes[\"likelihood\"] > 0.3".to_owned();
+    nodes[3].conditionAttribute = "config[\"internet\"]".to_owned();
+    nodes[4].conditionAttribute = "!node[\"loop_b\"].resolved".to_owned();
+    nodes[5].conditionAttribute = "node['loop_a'].resolved || config[\"internet\"]".to_owned();
+
+    expression_evaluator::resolve_tree_conditions(&mut nodes, "root", &attributes);
+    let resolved = |nodes: &[models::ApiFullComputedNodeData], node_id: &str| nodes.iter().find(|node| node.id == node_id).map(|node| node.conditionResolved).expect("Node exists");
+    assert!(resolved(&nodes, "exfil"));
+    assert!(!resolved(&nodes, "phish"));
+    assert!(resolved(&nodes, "foothold"));
+    // Cycles never resolve
+    assert!(!resolved(&nodes, "loop_a"));
+    assert!(!resolved(&nodes, "loop_b"));
+
+    nodes[3].conditionAttribute = "!config[\"internet\"]".to_owned();
+    expression_evaluator::resolve_tree_conditions(&mut nodes, "root", &attributes);
+    assert!(!resolved(&nodes, "exfil"));
+
+    // Pruning the branch above the prerequisite disables it too, even though
+    // its own condition still holds
+    nodes[3].conditionAttribute = "config[\"internet\"]".to_owned();
+    nodes[6].conditionAttribute = "!config[\"internet\"]".to_owned();
+    expression_evaluator::resolve_tree_conditions(&mut nodes, "root", &attributes);
+    assert!(resolved(&nodes, "foothold"));
+    assert!(!resolved(&nodes, "exfil"));
+    nodes[6].conditionAttribute = "".to_owned();
+
+    let mut tree = computed_tree(nodes.clone());
+    expression_evaluator::explain_tree(&mut tree, Some(&attributes));
+    let loop_a = tree.nodes.iter().find(|node| node.id == "loop_a").and_then(|node| node.explanation.clone()).expect("Explained");
+    assert_eq!(loop_a.error.map(|err| err.message), Some("Condition depends on its own resolution through loop_a -> loop_b -> loop_a".to_owned()));
+
+    let full_nodes: Vec<models::ApiFullNodeData> = nodes.iter().map(|node| models::ApiFullNodeData {
+        id: node.id.clone(),
+        title: node.title.clone(),
+        description: "".to_owned(),
+        modelAttributes: node.modelAttributes.clone(),
+        conditionAttribute: node.conditionAttribute.clone(),
+        children: node.children.clone(),
+        gateType: models::GateType::Or,
+        nodeType: models::NodeType::Attack
+    }).collect();
+    let mut issues = expression_evaluator::validate_tree_conditions(&full_nodes);
+    issues.sort_by(|a, b| a.nodeId.cmp(&b.nodeId));
+    let messages: Vec<(&str, &str)> = issues.iter().map(|issue| (issue.nodeId.as_str(), issue.message.as_str())).collect();
+    assert_eq!(messages, vec![
+        ("loop_a", "Condition depends on its own resolution through loop_a -> loop_b -> loop_a"),
+        ("loop_b", "Condition depends on its own resolution through loop_b -> loop_a -> loop_b"),
+    ]);
+
+    // Whether a child is still in the tree depends on its parent, so a parent
+    // can't read its own children
+    let mut reads_child = full_nodes.clone();
+    reads_child[6].conditionAttribute = "node[\"foothold\"].resolved".to_owned();
+    let issues = expression_evaluator::validate_tree_conditions(&reads_child);
+    assert!(issues.iter().any(|issue| issue.nodeId == "remote" && issue.message == "Condition depends on its own resolution through remote -> foothold -> remote"));
+
+    let mut missing = full_nodes[1].clone();
+    missing.conditionAttribute = "node[\"n404\"].resolved".to_owned();
+    let issues = expression_evaluator::validate_tree_conditions(&[missing]);
+    assert_eq!(issues[0].message, "There is no node \"n404\" in this tree");
+
+    assert_eq!(expression_evaluator::parse("node['a'].resolved && self.modelAttributes['x'] > 1").expect("Valid condition").to_string(), "node[\"a\"].resolved && self.modelAttributes[\"x\"] > 1");
+}
+
+#[test]
+fn test_condition_key_report() {
+    let mut web = vec![
+        computed_node("web", vec!["tls", "admin"], vec![], true),
+        computed_node("tls", vec![], vec![], true),
+        computed_node("admin", vec![], vec![], true),
+    ];
+    web[1].conditionAttribute = "config[\"network\"][\"zone\"] == \"dmz\" && config[\"internet\"]".to_owned();
+    web[2].conditionAttribute = "default(config[\"ports\"][0], 0) == 22".to_owned();
+    let mut db = vec![
+        computed_node("db", vec!["dump"], vec![], true),
+        computed_node("dump", vec![], vec![], true),
+    ];
+    db[0].conditionAttribute = "config['internet'] = true".to_owned();
+    db[1].conditionAttribute = "!config[\"internet\"]".to_owned();
+
+    let trees = vec![
+        expression_evaluator::usage::TreeConditions { tree_id: "t1".to_owned(), tree: computed_tree(web) },
+        expression_evaluator::usage::TreeConditions { tree_id: "t2".to_owned(), tree: computed_tree(db) },
+    ];
+    let configs = vec![
+        models::ApiProjectConfigResponseResult {
+            id: "prod".to_owned(),
+            name: Some("Production".to_owned()),
+            attributes: serde_json::json!({
+                "internet": true,
+                "network": { "zone": "dmz", "vlan": 12 },
+                "ports": [22, 443],
+                "owner": "ops",
+                "extras": {}
+            })
+        },
+        models::ApiProjectConfigResponseResult {
+            id: "lab".to_owned(),
+            name: None,
+            attributes: serde_json::json!({ "internet": false })
+        },
+    ];
+
+    let report = expression_evaluator::usage::report(&trees, &configs);
+    let keys: Vec<&str> = report.keys.iter().map(|usage| usage.key.as_str()).collect();
+    assert_eq!(keys, vec!["config[\"internet\"]", "config[\"network\"][\"zone\"]", "config[\"ports\"][0]"]);
+
+    let internet = &report.keys[0];
+    let users: Vec<(&str, &str)> = internet.nodes.iter().map(|node| (node.treeId.as_str(), node.nodeId.as_str())).collect();
+    assert_eq!(users, vec![("t1", "tls"), ("t2", "dump")]);
+    assert!(internet.missingFrom.is_empty());
+    assert_eq!(report.keys[1].path, vec![serde_json::json!("network"), serde_json::json!("zone")]);
+    assert_eq!(report.keys[1].missingFrom, vec!["lab".to_owned()]);
+
+    assert_eq!(report.configs[0].configId, "prod");
+    assert_eq!(report.configs[0].unusedKeys, vec![
+        "config[\"extras\"]".to_owned(),
+        "config[\"network\"][\"vlan\"]".to_owned(),
+        "config[\"owner\"]".to_owned(),
+    ]);
+    assert!(report.configs[1].unusedKeys.is_empty());
+
+    // The unparseable condition's keys can't be counted
+    assert_eq!(report.invalidConditions.len(), 1);
+    assert_eq!(report.invalidConditions[0].nodeId, "db");
+}
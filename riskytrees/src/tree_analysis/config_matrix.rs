@@ -0,0 +1,57 @@
+use crate::models::{ApiConfigMatrix, ApiConfigMatrixColumn, ApiConfigMatrixRow, ApiFullComputedTreeData};
+
+// One fully computed copy of the same tree per config, alongside the config's
+// id and name, or why the tree couldn't be computed for that config
+pub struct ConfigEvaluation {
+    pub config_id: String,
+    pub name: Option<String>,
+    pub tree: Result<ApiFullComputedTreeData, String>
+}
+
+// Lines up the per-config evaluations of a tree into a node × config matrix.
+// Rows follow the node order of the first successful evaluation; a node
+// missing from an evaluation counts as unresolved there, while a failed
+// evaluation leaves its column empty and carries the error. Rows whose
+// resolution differs between configs are flagged so variants can be compared
+// at a glance.
+pub fn build(evaluations: &[ConfigEvaluation]) -> ApiConfigMatrix {
+    let configs = evaluations.iter()
+        .map(|evaluation| ApiConfigMatrixColumn {
+            configId: evaluation.config_id.clone(),
+            name: evaluation.name.clone(),
+            rootScore: evaluation.tree.as_ref().ok().and_then(|tree| tree.rootScore),
+            mitigatedRootScore: evaluation.tree.as_ref().ok().and_then(|tree| tree.mitigatedRootScore),
+            error: evaluation.tree.as_ref().err().cloned()
+        })
+        .collect();
+
+    let nodes = match evaluations.iter().find_map(|evaluation| evaluation.tree.as_ref().ok()) {
+        Some(first) => first.nodes.iter()
+            .map(|node| {
+                let resolved: Vec<Option<bool>> = evaluations.iter()
+                    .map(|evaluation| evaluation.tree.as_ref().ok().map(|tree| tree.nodes.iter()
+                        .find(|other| other.id == node.id)
+                        .map_or(false, |other| other.conditionResolved)))
+                    .collect();
+                let mut known = resolved.iter().flatten();
+                let varies = match known.next() {
+                    Some(first) => known.any(|value| value != first),
+                    None => false
+                };
+
+                ApiConfigMatrixRow {
+                    nodeId: node.id.clone(),
+                    title: node.title.clone(),
+                    conditionResolved: resolved,
+                    varies
+                }
+            })
+            .collect(),
+        None => vec![]
+    };
+
+    ApiConfigMatrix {
+        configs,
+        nodes
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{ApiCutSet, ApiFullComputedNodeData, GateType};
+use crate::model_evaluator::{self, countermeasure, AnalysisModel};
+
+// AND gates multiply out their children's sets, so big trees can explode.
+// Anything past this many sets per node is dropped and the result is flagged as
+// truncated.
+pub const MAX_CUT_SETS: usize = 1000;
+
+pub struct CutSets {
+    pub sets: Vec<Vec<String>>,
+    pub truncated: bool
+}
+
+// Computes the minimal cut sets of the tree rooted at root_node_id: each set is
+// a group of leaf steps that together achieve the root. OR gates offer any one
+// of their children's sets and AND/SAND gates need one set from every child.
+// Unresolved and infeasible nodes can't be used, so an AND/SAND with such a
+// child has no sets, while countermeasures and children outside of nodes are
+// ignored, the same way model rollups treat them. Steps keep the order they're
+// first reached in, which for SAND gates is the order they have to happen in.
+pub fn minimal_cut_sets(nodes: &[ApiFullComputedNodeData], root_node_id: &String) -> CutSets {
+    let node_indices = model_evaluator::index_nodes(nodes);
+    let mut memo = HashMap::new();
+    let mut truncated = false;
+
+    let sets = cut_sets_for_node(nodes, &node_indices, root_node_id, &mut memo, &mut HashSet::new(), &mut truncated);
+
+    CutSets {
+        sets: sets,
+        truncated: truncated
+    }
+}
+
+fn cut_sets_for_node(
+    nodes: &[ApiFullComputedNodeData],
+    node_indices: &HashMap<String, usize>,
+    node_id: &String,
+    memo: &mut HashMap<String, Vec<Vec<String>>>,
+    visiting: &mut HashSet<String>,
+    truncated: &mut bool
+) -> Vec<Vec<String>> {
+    if let Some(sets) = memo.get(node_id) {
+        return sets.clone();
+    }
+
+    let node = match node_indices.get(node_id) {
+        Some(index) => &nodes[*index],
+        None => return vec![]
+    };
+
+    if !node.conditionResolved || node.infeasible || countermeasure::is_countermeasure(node) || visiting.contains(node_id) {
+        return vec![];
+    }
+
+    visiting.insert(node_id.clone());
+    let mut child_sets = Vec::new();
+    let mut contributing_children = 0;
+    for child in &node.children {
+        if !model_evaluator::contributes(nodes, node_indices, child) {
+            continue;
+        }
+
+        contributing_children += 1;
+        let sets = cut_sets_for_node(nodes, node_indices, child, memo, visiting, truncated);
+
+        if !sets.is_empty() {
+            child_sets.push(sets);
+        }
+    }
+    visiting.remove(node_id);
+
+    let sets = if contributing_children == 0 {
+        // Leaves are steps themselves
+        vec![vec![node_id.clone()]]
+    } else {
+        match node.gateType {
+            GateType::Or => child_sets.into_iter().flatten().collect(),
+            // A child that can't be achieved leaves the AND with no sets at all
+            GateType::And | GateType::SequentialAnd if child_sets.len() < contributing_children => vec![],
+            GateType::And | GateType::SequentialAnd => {
+                let mut combined: Vec<Vec<String>> = vec![vec![]];
+
+                for sets in child_sets {
+                    let mut next = Vec::new();
+
+                    for partial in &combined {
+                        for set in &sets {
+                            let mut merged = partial.clone();
+                            for step in set {
+                                if !merged.contains(step) {
+                                    merged.push(step.clone());
+                                }
+                            }
+                            next.push(merged);
+                        }
+                    }
+
+                    combined = minimize(next, truncated);
+                }
+
+                combined
+            }
+        }
+    };
+
+    let sets = minimize(sets, truncated);
+    memo.insert(node_id.clone(), sets.clone());
+    sets
+}
+
+// Drops any set that contains another set, plus duplicates
+fn minimize(mut sets: Vec<Vec<String>>, truncated: &mut bool) -> Vec<Vec<String>> {
+    sets.sort_by_key(|set| set.len());
+
+    let mut minimal: Vec<(Vec<String>, HashSet<String>)> = Vec::new();
+    for set in sets {
+        let members: HashSet<String> = set.iter().cloned().collect();
+
+        if !minimal.iter().any(|(_, kept)| kept.is_subset(&members)) {
+            minimal.push((set, members));
+        }
+    }
+
+    if minimal.len() > MAX_CUT_SETS {
+        minimal.truncate(MAX_CUT_SETS);
+        *truncated = true;
+    }
+
+    minimal.into_iter().map(|(set, _)| set).collect()
+}
+
+// Scores every set as if its steps sat under a single AND gate, using each
+// step's own value from the model. Sets come back smallest first, then best
+// score first.
+pub fn score_cut_sets(nodes: &[ApiFullComputedNodeData], cut_sets: &CutSets, model: Option<&dyn AnalysisModel>) -> Vec<ApiCutSet> {
+    let values = match model {
+        Some(model) => model_evaluator::rollup_with_model(nodes, model),
+        None => HashMap::new()
+    };
+
+    let mut scored: Vec<ApiCutSet> = cut_sets.sets.iter().map(|set| {
+        let step_values: Vec<f64> = set.iter()
+            .filter_map(|step| values.get(step).cloned().flatten())
+            .collect();
+
+        let score = match model {
+            Some(model) if !step_values.is_empty() => model.combine(GateType::And, step_values),
+            _ => None
+        };
+
+        ApiCutSet {
+            nodeIds: set.clone(),
+            score: score
+        }
+    }).collect();
+
+    // Put the sets best for the attacker first
+    let higher_first = model.map_or(false, |model| model.attacker_prefers_higher());
+
+    scored.sort_by(|a, b| {
+        a.nodeIds.len().cmp(&b.nodeIds.len()).then_with(|| match (a.score, b.score) {
+            (Some(a_score), Some(b_score)) => {
+                let order = a_score.partial_cmp(&b_score).unwrap_or(std::cmp::Ordering::Equal);
+                if higher_first { order.reverse() } else { order }
+            },
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal
+        })
+    });
+
+    scored
+}
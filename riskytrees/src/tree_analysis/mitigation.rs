@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+
+use crate::models::{ApiFullComputedTreeData, ApiMitigationPlan, ApiMitigationPortfolio};
+use crate::model_evaluator::{self, countermeasure, AnalysisModel, LinkedTree, ModelContext};
+use crate::tree_analysis::paths::COST_ATTRIBUTE;
+
+// Every combination of up to this many countermeasures is tried. Past that the
+// portfolios are built greedily, which isn't guaranteed to be optimal.
+pub const MAX_EXHAUSTIVE_COUNTERMEASURES: usize = 12;
+
+struct Candidate {
+    id: String,
+    cost: f64
+}
+
+// Picks the countermeasures that leave the root score worst for the attacker
+// without going over budget, along with the Pareto frontier of cost against
+// that residual score. Which way is worse comes from the model, so a
+// likelihood should end up low while a cost to attack should end up high.
+// Only countermeasures whose conditions hold are candidates; the rest of the
+// tree is computed exactly as tree GET computes its mitigated scores.
+pub fn optimize(tree: &ApiFullComputedTreeData, linked: &[LinkedTree], model: &dyn AnalysisModel, context: &ModelContext, budget: f64) -> ApiMitigationPlan {
+    let model_id = model.id().to_owned();
+    let candidates = candidates(tree, linked);
+    let residual = |active: &[&Candidate]| residual_score(tree, linked, active, &model_id, context);
+    let lower_is_better = model.attacker_prefers_higher();
+
+    let exact = candidates.len() <= MAX_EXHAUSTIVE_COUNTERMEASURES;
+    let portfolios = if exact {
+        (0..1usize << candidates.len())
+            .map(|mask| {
+                let active: Vec<&Candidate> = candidates.iter().enumerate()
+                    .filter(|(index, _)| mask & (1 << index) != 0)
+                    .map(|(_, candidate)| candidate)
+                    .collect();
+                portfolio(&active, residual(&active))
+            })
+            .collect()
+    } else {
+        greedy_portfolios(&candidates, residual, lower_is_better)
+    };
+
+    let baseline = residual(&[]);
+    let best = portfolios.iter()
+        .filter(|portfolio| portfolio.cost <= budget && portfolio.residualScore.is_some())
+        .fold(None, |best: Option<&ApiMitigationPortfolio>, portfolio| match best {
+            Some(best) if !improves(portfolio, best, lower_is_better) => Some(best),
+            _ => Some(portfolio)
+        })
+        .cloned()
+        .unwrap_or_else(|| portfolio(&[], baseline));
+
+    ApiMitigationPlan {
+        modelId: model_id,
+        budget: budget,
+        baselineScore: baseline,
+        exact: exact,
+        best: best,
+        frontier: pareto_frontier(portfolios, lower_is_better)
+    }
+}
+
+// Resolved countermeasures across the tree and its linked trees. Ones without
+// a cost are free.
+fn candidates(tree: &ApiFullComputedTreeData, linked: &[LinkedTree]) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+
+    model_evaluator::combined_nodes(tree, linked).into_iter()
+        .filter(|node| countermeasure::is_countermeasure(node) && node.conditionResolved)
+        .filter(|node| seen.insert(node.id.clone()))
+        .map(|node| Candidate {
+            cost: node.modelAttributes.get(COST_ATTRIBUTE).and_then(|attribute| attribute.as_f64()).map_or(0.0, |cost| cost.max(0.0)),
+            id: node.id
+        })
+        .collect()
+}
+
+// The mitigated root score with only the given countermeasures in place
+fn residual_score(tree: &ApiFullComputedTreeData, linked: &[LinkedTree], active: &[&Candidate], model_id: &String, context: &ModelContext) -> Option<f64> {
+    let active_ids: HashSet<&String> = active.iter().map(|candidate| &candidate.id).collect();
+    let mut tree = tree.clone();
+    let mut linked = linked.to_vec();
+
+    let nodes = tree.nodes.iter_mut()
+        .chain(linked.iter_mut().flat_map(|linked_tree| linked_tree.nodes.iter_mut()));
+    for node in nodes.filter(|node| countermeasure::is_countermeasure(node) && !active_ids.contains(&node.id)) {
+        node.conditionResolved = false;
+    }
+
+    model_evaluator::compute_with_links(&mut tree, &linked, Some(model_id), context);
+    tree.mitigatedRootScore
+}
+
+fn portfolio(active: &[&Candidate], residual: Option<f64>) -> ApiMitigationPortfolio {
+    ApiMitigationPortfolio {
+        countermeasureIds: active.iter().map(|candidate| candidate.id.clone()).collect(),
+        cost: active.iter().map(|candidate| candidate.cost).sum(),
+        residualScore: residual
+    }
+}
+
+// How much better one residual score is than another for the defender
+fn gain(residual: f64, other: f64, lower_is_better: bool) -> f64 {
+    if lower_is_better { other - residual } else { residual - other }
+}
+
+// Better residual scores win, then lower costs
+fn improves(portfolio: &ApiMitigationPortfolio, other: &ApiMitigationPortfolio, lower_is_better: bool) -> bool {
+    match (portfolio.residualScore, other.residualScore) {
+        (Some(residual), Some(other_residual)) if residual != other_residual => gain(residual, other_residual, lower_is_better) > 0.0,
+        _ => portfolio.cost < other.cost
+    }
+}
+
+// Starts with nothing in place and keeps adding whichever countermeasure
+// improves the residual score the most per unit of cost, recording each step
+fn greedy_portfolios<R>(candidates: &[Candidate], residual: R, lower_is_better: bool) -> Vec<ApiMitigationPortfolio>
+where
+    R: Fn(&[&Candidate]) -> Option<f64>
+{
+    let mut active: Vec<&Candidate> = Vec::new();
+    let mut current = residual(&active);
+    let mut portfolios = vec![portfolio(&active, current)];
+
+    while active.len() < candidates.len() {
+        let mut best: Option<(&Candidate, Option<f64>, f64)> = None;
+
+        for candidate in candidates.iter().filter(|candidate| !active.iter().any(|chosen| chosen.id == candidate.id)) {
+            let mut trial = active.clone();
+            trial.push(candidate);
+            let trial_residual = residual(&trial);
+
+            let improvement = match (current, trial_residual) {
+                (Some(current), Some(trial_residual)) => gain(trial_residual, current, lower_is_better),
+                _ => 0.0
+            };
+            let value = if candidate.cost > 0.0 { improvement / candidate.cost } else if improvement > 0.0 { f64::INFINITY } else { 0.0 };
+
+            if best.as_ref().map_or(true, |(_, _, best_value)| value > *best_value) {
+                best = Some((candidate, trial_residual, value));
+            }
+        }
+
+        match best {
+            Some((candidate, trial_residual, _)) => {
+                active.push(candidate);
+                current = trial_residual;
+                portfolios.push(portfolio(&active, current));
+            },
+            None => break
+        }
+    }
+
+    portfolios
+}
+
+// Portfolios no other portfolio beats on both cost and residual score, cheapest
+// first
+fn pareto_frontier(mut portfolios: Vec<ApiMitigationPortfolio>, lower_is_better: bool) -> Vec<ApiMitigationPortfolio> {
+    portfolios.retain(|portfolio| portfolio.residualScore.is_some());
+    portfolios.sort_by(|a, b| {
+        let by_residual = a.residualScore.partial_cmp(&b.residualScore).unwrap_or(std::cmp::Ordering::Equal);
+
+        a.cost.partial_cmp(&b.cost).unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| if lower_is_better { by_residual } else { by_residual.reverse() })
+            .then_with(|| a.countermeasureIds.len().cmp(&b.countermeasureIds.len()))
+    });
+
+    let mut frontier: Vec<ApiMitigationPortfolio> = Vec::new();
+    for portfolio in portfolios {
+        let dominated = frontier.last().map_or(false, |cheaper| match (cheaper.residualScore, portfolio.residualScore) {
+            (Some(cheaper_residual), Some(residual)) => gain(residual, cheaper_residual, lower_is_better) <= 0.0,
+            _ => false
+        });
+
+        if !dominated {
+            frontier.push(portfolio);
+        }
+    }
+
+    frontier
+}
@@ -0,0 +1,8 @@
+// Structural analyses over a condition-resolved tree (and any linked subtrees),
+// as opposed to model_evaluator which annotates the tree itself
+pub mod cutsets;
+pub mod paths;
+pub mod simulation;
+pub mod sensitivity;
+pub mod mitigation;
+pub mod config_matrix;
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{ApiFullComputedNodeData, GateType};
+use crate::model_evaluator::{self, countermeasure, timed};
+use crate::model_evaluator::likelihood::LIKELIHOOD_ATTRIBUTE;
+
+pub const COST_ATTRIBUTE: &str = "cost";
+pub const TIME_ATTRIBUTE: &str = "time";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathObjective {
+    Cost,
+    Likelihood,
+    Time
+}
+
+impl PathObjective {
+    pub fn parse(value: &str) -> Option<PathObjective> {
+        match value {
+            "cost" => Some(PathObjective::Cost),
+            "likelihood" => Some(PathObjective::Likelihood),
+            "time" => Some(PathObjective::Time),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathObjective::Cost => "cost",
+            PathObjective::Likelihood => "likelihood",
+            PathObjective::Time => "time"
+        }
+    }
+
+    // The leaf modelAttribute each objective reads
+    pub fn attribute(&self) -> &'static str {
+        match self {
+            PathObjective::Cost => COST_ATTRIBUTE,
+            PathObjective::Likelihood => LIKELIHOOD_ATTRIBUTE,
+            PathObjective::Time => TIME_ATTRIBUTE
+        }
+    }
+
+    fn better(&self, a: f64, b: f64) -> bool {
+        match self {
+            PathObjective::Likelihood => a > b,
+            PathObjective::Cost | PathObjective::Time => a < b
+        }
+    }
+
+    // Combines every child of an AND/SAND gate. Steps of an AND can happen in
+    // parallel so its time is the slowest child, a SAND has to wait for each.
+    fn combine_all(&self, gate: GateType, values: Vec<f64>) -> f64 {
+        match (self, gate) {
+            (PathObjective::Cost, _) => values.into_iter().sum(),
+            (PathObjective::Likelihood, _) => values.into_iter().product(),
+            (PathObjective::Time, GateType::SequentialAnd) => values.into_iter().sum(),
+            (PathObjective::Time, _) => values.into_iter().fold(0.0, f64::max)
+        }
+    }
+
+    // Leaves estimated with a time range take its lower end
+    fn leaf_value(&self, node: &ApiFullComputedNodeData) -> Option<f64> {
+        let value = node.modelAttributes.get(self.attribute()).and_then(|attribute| attribute.as_f64());
+
+        match self {
+            PathObjective::Likelihood => value.map(|value| value.clamp(0.0, 1.0)),
+            PathObjective::Cost => value.map(|value| value.max(0.0)),
+            PathObjective::Time => timed::leaf_time_range(node).map(|(min, _)| min)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttackPath {
+    pub node_ids: Vec<String>,
+    pub value: f64
+}
+
+// Finds the single best way to achieve the root for the objective: OR gates take
+// their best child and AND/SAND gates need all of theirs. The path lists every
+// node used, top down. Unresolved or infeasible nodes and leaves without an
+// estimate can't be used, so an AND/SAND needing one has no path, while
+// countermeasures and children outside of nodes are left out as they are in
+// model rollups.
+pub fn best_attack_path(nodes: &[ApiFullComputedNodeData], root_node_id: &String, objective: PathObjective) -> Option<AttackPath> {
+    let node_indices = model_evaluator::index_nodes(nodes);
+    let mut memo = HashMap::new();
+
+    best_path_for_node(nodes, &node_indices, root_node_id, objective, &mut memo, &mut HashSet::new())
+}
+
+fn best_path_for_node(
+    nodes: &[ApiFullComputedNodeData],
+    node_indices: &HashMap<String, usize>,
+    node_id: &String,
+    objective: PathObjective,
+    memo: &mut HashMap<String, Option<AttackPath>>,
+    visiting: &mut HashSet<String>
+) -> Option<AttackPath> {
+    if let Some(path) = memo.get(node_id) {
+        return path.clone();
+    }
+
+    let node = &nodes[*node_indices.get(node_id)?];
+
+    if !node.conditionResolved || node.infeasible || countermeasure::is_countermeasure(node) || visiting.contains(node_id) {
+        return None;
+    }
+
+    visiting.insert(node_id.clone());
+    let children: Vec<&String> = node.children.iter()
+        .filter(|child| model_evaluator::contributes(nodes, node_indices, child))
+        .collect();
+    let child_paths: Vec<AttackPath> = children.iter()
+        .filter_map(|child| best_path_for_node(nodes, node_indices, child, objective, memo, visiting))
+        .collect();
+    visiting.remove(node_id);
+
+    let path = if children.is_empty() {
+        objective.leaf_value(node).map(|value| AttackPath {
+            node_ids: vec![node_id.clone()],
+            value: value
+        })
+    } else {
+        match node.gateType {
+            GateType::Or => child_paths.into_iter()
+                .fold(None, |best: Option<AttackPath>, path| match best {
+                    Some(best) if !objective.better(path.value, best.value) => Some(best),
+                    _ => Some(path)
+                })
+                .map(|best| {
+                    let mut node_ids = vec![node_id.clone()];
+                    node_ids.extend(best.node_ids);
+
+                    AttackPath {
+                        node_ids: node_ids,
+                        value: best.value
+                    }
+                }),
+            // Every child is required, so one that can't be achieved sinks the gate
+            GateType::And | GateType::SequentialAnd if child_paths.len() < children.len() => None,
+            GateType::And | GateType::SequentialAnd => {
+                let mut node_ids = vec![node_id.clone()];
+                let mut values = Vec::new();
+
+                for path in child_paths {
+                    for step in path.node_ids {
+                        if !node_ids.contains(&step) {
+                            node_ids.push(step);
+                        }
+                    }
+                    values.push(path.value);
+                }
+
+                let value = match objective {
+                    // Time depends on how the steps are arranged, not just which
+                    // ones are taken
+                    PathObjective::Time => objective.combine_all(node.gateType, values),
+                    // A step shared between children is only taken once
+                    PathObjective::Cost | PathObjective::Likelihood => {
+                        let steps = node_ids.iter()
+                            .map(|step| &nodes[node_indices[step]])
+                            .filter(|step| !step.children.iter().any(|child| model_evaluator::contributes(nodes, node_indices, child)))
+                            .filter_map(|step| objective.leaf_value(step))
+                            .collect();
+                        objective.combine_all(node.gateType, steps)
+                    }
+                };
+
+                Some(AttackPath {
+                    node_ids: node_ids,
+                    value: value
+                })
+            }
+        }
+    };
+
+    memo.insert(node_id.clone(), path.clone());
+    path
+}
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+
+use crate::models::{ApiFullComputedNodeData, ApiFullComputedTreeData, ApiSensitivityEntry, ApiSensitivityResult, ModelAttribute, ModelAttributeSchema};
+use crate::model_evaluator::{self, countermeasure, AnalysisModel, LinkedTree, ModelContext};
+
+// Share of an attribute's range (or of its value, when the schema doesn't
+// bound it) each leaf attribute is moved by
+pub const DEFAULT_PERTURBATION: f64 = 0.1;
+
+// Nudges every leaf attribute the model reads down and up in turn and
+// recomputes the tree exactly as tree GET does, recording where the root score
+// lands. Attributes with allowedValues step to the neighbouring value instead,
+// so EVITA's categories move one category at a time. Results are ranked by how
+// far the root score swings.
+pub fn sensitivity(tree: &ApiFullComputedTreeData, linked: &[LinkedTree], model: &dyn AnalysisModel, context: &ModelContext, perturbation: f64) -> ApiSensitivityResult {
+    let model_id = model.id().to_owned();
+    let schema = model.attributes();
+    let baseline = root_score(tree.clone(), linked.to_vec(), &model_id, context);
+
+    let mut ranking = Vec::new();
+    for leaf in leaves(tree, linked) {
+        for field in &schema {
+            let current = match leaf.modelAttributes.get(&field.key).or(field.default.as_ref()) {
+                Some(current) => current,
+                None => continue
+            };
+
+            let (low, high) = variants(field, current, perturbation);
+            if low.is_none() && high.is_none() {
+                continue;
+            }
+
+            let score_with = |value: Option<ModelAttribute>| value.and_then(|value| {
+                let mut perturbed_tree = tree.clone();
+                let mut perturbed_linked = linked.to_vec();
+                let nodes = perturbed_tree.nodes.iter_mut()
+                    .chain(perturbed_linked.iter_mut().flat_map(|linked_tree| linked_tree.nodes.iter_mut()));
+
+                for node in nodes.filter(|node| node.id == leaf.id) {
+                    node.modelAttributes.insert(field.key.clone(), value.clone());
+                }
+
+                root_score(perturbed_tree, perturbed_linked, &model_id, context)
+            });
+
+            let low_score = score_with(low);
+            let high_score = score_with(high);
+            let swing = [low_score, high_score].iter()
+                .filter_map(|score| match (score, baseline) {
+                    (Some(score), Some(baseline)) => Some((score - baseline).abs()),
+                    _ => None
+                })
+                .fold(0.0, f64::max);
+
+            ranking.push(ApiSensitivityEntry {
+                nodeId: leaf.id.clone(),
+                title: leaf.title.clone(),
+                attribute: field.key.clone(),
+                lowScore: low_score,
+                highScore: high_score,
+                swing: swing
+            });
+        }
+    }
+
+    ranking.sort_by(|a, b| b.swing.partial_cmp(&a.swing).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.nodeId.cmp(&b.nodeId)));
+
+    ApiSensitivityResult {
+        modelId: model_id,
+        perturbation: perturbation,
+        baselineScore: baseline,
+        ranking: ranking
+    }
+}
+
+fn root_score(mut tree: ApiFullComputedTreeData, linked: Vec<LinkedTree>, model_id: &String, context: &ModelContext) -> Option<f64> {
+    model_evaluator::compute_with_links(&mut tree, &linked, Some(model_id), context);
+    tree.rootScore
+}
+
+// Resolved attack steps without any resolved attack steps below them, across
+// the tree and its linked trees
+fn leaves(tree: &ApiFullComputedTreeData, linked: &[LinkedTree]) -> Vec<ApiFullComputedNodeData> {
+    let nodes = model_evaluator::combined_nodes(tree, linked);
+    let steps: HashSet<&String> = nodes.iter()
+        .filter(|node| node.conditionResolved && !node.infeasible && !countermeasure::is_countermeasure(node))
+        .map(|node| &node.id)
+        .collect();
+
+    let mut seen = HashSet::new();
+    nodes.iter()
+        .filter(|node| steps.contains(&node.id) && !node.children.iter().any(|child| steps.contains(child)))
+        .filter(|node| seen.insert(node.id.clone()))
+        .cloned()
+        .collect()
+}
+
+// The attribute moved one step down and one step up, where that's possible
+fn variants(field: &ModelAttributeSchema, current: &ModelAttribute, perturbation: f64) -> (Option<ModelAttribute>, Option<ModelAttribute>) {
+    if let (Some(allowed_values), Some(value)) = (field.allowedValues.as_ref(), current.value_string.as_ref()) {
+        if let Some(position) = allowed_values.iter().position(|allowed_value| allowed_value.eq_ignore_ascii_case(value.trim())) {
+            let low = position.checked_sub(1).map(|index| ModelAttribute::from_string(&allowed_values[index]));
+            let high = allowed_values.get(position + 1).map(|value| ModelAttribute::from_string(value));
+            return (low, high);
+        }
+    }
+
+    let value = match current.as_f64() {
+        Some(value) => value,
+        None => return (None, None)
+    };
+
+    let step = match (field.min, field.max) {
+        (Some(min), Some(max)) if max > min => perturbation * (max - min),
+        _ if value != 0.0 => perturbation * value.abs(),
+        _ => perturbation
+    };
+
+    let low = field.min.map_or(value - step, |min| (value - step).max(min));
+    let high = field.max.map_or(value + step, |max| (value + step).min(max));
+
+    (
+        if low < value { Some(ModelAttribute::from_float(low)) } else { None },
+        if high > value { Some(ModelAttribute::from_float(high)) } else { None }
+    )
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::models::{ApiFullComputedNodeData, ApiHistogramBin, ApiPercentiles, ApiSimulatedNode};
+use crate::model_evaluator;
+use crate::model_evaluator::likelihood::{combine_likelihoods, LIKELIHOOD_ATTRIBUTE};
+
+pub const LIKELIHOOD_MIN_ATTRIBUTE: &str = "likelihoodMin";
+pub const LIKELIHOOD_MAX_ATTRIBUTE: &str = "likelihoodMax";
+
+pub const DEFAULT_ITERATIONS: u32 = 10000;
+pub const MAX_ITERATIONS: u32 = 100000;
+pub const DEFAULT_HISTOGRAM_BINS: usize = 10;
+
+// How uncertain a leaf's probability of success is. A bare "likelihood" is
+// taken as exact, "likelihoodMin"/"likelihoodMax" give a uniform range and all
+// three together a triangular distribution peaking at "likelihood".
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeafDistribution {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Triangular(f64, f64, f64)
+}
+
+impl LeafDistribution {
+    pub fn from_node(node: &ApiFullComputedNodeData) -> Option<LeafDistribution> {
+        let value = |key: &str| node.modelAttributes.get(key)
+            .and_then(|attribute| attribute.as_f64())
+            .map(|value| value.clamp(0.0, 1.0));
+
+        match (value(LIKELIHOOD_MIN_ATTRIBUTE), value(LIKELIHOOD_ATTRIBUTE), value(LIKELIHOOD_MAX_ATTRIBUTE)) {
+            (Some(min), Some(mode), Some(max)) if min <= mode && mode <= max => Some(LeafDistribution::Triangular(min, mode, max)),
+            (Some(min), None, Some(max)) if min <= max => Some(LeafDistribution::Uniform(min, max)),
+            (_, Some(likelihood), _) => Some(LeafDistribution::Fixed(likelihood)),
+            _ => None
+        }
+    }
+
+    pub fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            LeafDistribution::Fixed(likelihood) => likelihood,
+            LeafDistribution::Uniform(min, max) => min + rng.gen::<f64>() * (max - min),
+            LeafDistribution::Triangular(min, mode, max) => {
+                if max <= min {
+                    return mode;
+                }
+
+                let u: f64 = rng.gen();
+                let split = (mode - min) / (max - min);
+
+                if u < split {
+                    min + (u * (max - min) * (mode - min)).sqrt()
+                } else {
+                    max - ((1.0 - u) * (max - min) * (max - mode)).sqrt()
+                }
+            }
+        }
+    }
+}
+
+// Samples every leaf's probability `iterations` times, propagates each draw up
+// through the gates and summarises the resulting probabilities for the
+// requested nodes. Gates combine draws the way the Attacker Likelihood model
+// does, so an OR takes its likeliest child rather than treating the children
+// as independent attempts, and a tree of fixed leaves simulates to its own
+// score. The same seed always produces the same results.
+pub fn simulate(nodes: &[ApiFullComputedNodeData], node_ids: &[String], iterations: u32, seed: u64, bins: usize) -> Vec<ApiSimulatedNode> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let node_indices = model_evaluator::index_nodes(nodes);
+
+    // Fixed order so draws line up with the same leaves on every run
+    let distributions: Vec<(usize, LeafDistribution)> = nodes.iter()
+        .enumerate()
+        .filter(|(_, node)| node.conditionResolved && !node.infeasible)
+        .filter_map(|(index, node)| LeafDistribution::from_node(node).map(|distribution| (index, distribution)))
+        .collect();
+
+    let mut samples: HashMap<String, Vec<f64>> = HashMap::new();
+    for node_id in node_ids {
+        samples.insert(node_id.clone(), Vec::with_capacity(iterations as usize));
+    }
+
+    let mut drawn: Vec<Option<f64>> = vec![None; nodes.len()];
+    for _ in 0..iterations {
+        for (index, distribution) in &distributions {
+            drawn[*index] = Some(distribution.sample(&mut rng));
+        }
+
+        let results = model_evaluator::rollup_indexed(
+            nodes,
+            &node_indices,
+            |node| node_indices.get(&node.id).and_then(|index| drawn[*index]),
+            |gate, child_likelihoods| Some(combine_likelihoods(gate, child_likelihoods))
+        );
+
+        for (node_id, node_samples) in samples.iter_mut() {
+            if let Some(Some(probability)) = results.get(node_id) {
+                node_samples.push(*probability);
+            }
+        }
+    }
+
+    node_ids.iter()
+        .map(|node_id| summarise(node_id, samples.remove(node_id).unwrap_or_default(), bins))
+        .collect()
+}
+
+fn summarise(node_id: &str, mut samples: Vec<f64>, bins: usize) -> ApiSimulatedNode {
+    if samples.is_empty() {
+        return ApiSimulatedNode {
+            nodeId: node_id.to_owned(),
+            samples: 0,
+            mean: None,
+            percentiles: None,
+            histogram: vec![]
+        };
+    }
+
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+
+    let percentile = |percent: f64| {
+        let index = ((percent / 100.0) * (samples.len() - 1) as f64).round() as usize;
+        samples[index]
+    };
+
+    // Probabilities always fall within 0..1 so the bins are fixed
+    let mut histogram: Vec<ApiHistogramBin> = (0..bins).map(|bin| ApiHistogramBin {
+        lower: bin as f64 / bins as f64,
+        upper: (bin + 1) as f64 / bins as f64,
+        count: 0
+    }).collect();
+    for sample in &samples {
+        let bin = ((sample * bins as f64) as usize).min(bins - 1);
+        histogram[bin].count += 1;
+    }
+
+    ApiSimulatedNode {
+        nodeId: node_id.to_owned(),
+        samples: samples.len() as u32,
+        mean: Some(mean),
+        percentiles: Some(ApiPercentiles {
+            p5: percentile(5.0),
+            p25: percentile(25.0),
+            p50: percentile(50.0),
+            p75: percentile(75.0),
+            p95: percentile(95.0)
+        }),
+        histogram
+    }
+}
@@ -336,6 +336,12 @@ def test_get_model_list():
     models = res['result']['models']
 
     assert(len(models) > 0)
+    for model in models:
+        assert('attributes' in model)
+
+    likelihood = [model for model in models if model['title'] == 'Attacker Likelihood'][0]
+    assert(likelihood['attributes'][0]['key'] == 'likelihood')
+    assert(likelihood['attributes'][0]['max'] == 1.0)
 
 def test_get_and_update_project_model():
     r = requests.post('http://localhost:8000/projects', json = {'title':'test project'}, headers = TEST_HEADERS)
@@ -371,11 +377,14 @@ def test_get_and_update_project_model():
     # Update model
 
     r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'test'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'f1644cb9-b2a5-4abb-813f-98d0277e42f2'}, headers = TEST_HEADERS)
     assert(r.json()['ok'] == True)
 
     r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/model', headers = TEST_HEADERS)
 
-    assert(r.json()['result']['modelId'] == 'test')
+    assert(r.json()['result']['modelId'] == 'f1644cb9-b2a5-4abb-813f-98d0277e42f2')
 
 
     # Ensure config still exists
@@ -386,6 +395,1340 @@ def test_get_and_update_project_model():
     assert("Got" in res['message'])
     assert(len(res['result']['ids']) == 1)
 
+def test_attacker_likelihood_model():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'likelihood project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {
+      "attributes": {
+        "internet": True
+      }
+    }, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {
+      "desiredConfig": config_id
+    }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Likely'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Likely',
+        'nodes': [{
+            'id': "likely-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["likely-1", "likely-2", "likely-3"],
+        }, {
+            'id': "likely-1",
+            'title': "Phish",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.4}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "likely-2",
+            'title': "Exploit exposed service",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.7}},
+            'conditionAttribute': 'config["internet"] == true',
+            'children': [],
+        }, {
+            'id': "likely-3",
+            'title': "Walk in the front door",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.9}},
+            'conditionAttribute': '1 == 2',
+            'children': [],
+        }],
+        'rootNodeId': 'likely-0'
+        }, headers = TEST_HEADERS)
+
+    res = r.json()
+    assert(res['ok'] == True)
+
+    for node in res['result']['nodes']:
+        if node['id'] == 'likely-0':
+            assert(node['computedAttributes']['likelihood']['value_float'] == 0.7)
+        if node['id'] == 'likely-3':
+            assert('likelihood' not in node['computedAttributes'])
+
+def test_gate_type_persisted():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'gate project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Gates'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Gates',
+        'nodes': [{
+            'id': "gate-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["gate-1"],
+            'gateType': 'sand'
+        }, {
+            'id': "gate-1",
+            'title': "Child without a gate",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'gate-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+
+    for node in res['result']['nodes']:
+        if node['id'] == 'gate-0':
+            assert(node['gateType'] == 'sand')
+        if node['id'] == 'gate-1':
+            assert(node['gateType'] == 'or')
+
+def test_risk_of_attack_model():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'risk project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    # Default matrix is 5x5
+    r = requests.get('http://localhost:8000/projects/' + project_id + '/model/matrix', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(len(res['result']['cells']) == 5)
+
+    # Mismatched rows are rejected
+    r = requests.put('http://localhost:8000/projects/' + project_id + '/model/matrix', json = {
+        'likelihoodThresholds': [0.5],
+        'impactThresholds': [3],
+        'cells': [['Low', 'High']]
+    }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.put('http://localhost:8000/projects/' + project_id + '/model/matrix', json = {
+        'likelihoodThresholds': [0.5],
+        'impactThresholds': [3],
+        'cells': [['Low', 'Medium'], ['High', 'Critical']]
+    }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {
+      "attributes": {}
+    }, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {
+      "desiredConfig": config_id
+    }, headers = TEST_HEADERS)
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'f1644cb9-b2a5-4abb-813f-98d0277e42f2'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Risky'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Risky',
+        'nodes': [{
+            'id': "risky-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {'impact': {'value_int': 4}},
+            'conditionAttribute': '',
+            'children': ["risky-1"],
+        }, {
+            'id': "risky-1",
+            'title': "Leaf",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.5}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'risky-0'
+        }, headers = TEST_HEADERS)
+
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['rootScore'] == 2.0)
+
+    for node in res['result']['nodes']:
+        if node['id'] == 'risky-0':
+            assert(node['riskBucket'] == 'Critical')
+            assert('riskBucket' not in node['computedAttributes'])
+
+def test_tree_put_validates_model_attributes():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'validated project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Validated'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    tree = {
+        'title': 'Validated',
+        'nodes': [{
+            'id': "valid-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {'likelyhood': {'value_float': 0.4}},
+            'conditionAttribute': '',
+            'children': ["valid-1"],
+        }, {
+            'id': "valid-1",
+            'title': "Leaf",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_string': 'very'}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'valid-0'
+    }
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = tree, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == False)
+    assert(res['result'] == None)
+    fields = sorted([(issue['nodeId'], issue['field']) for issue in res['errors']])
+    assert(fields == [('valid-0', 'modelAttributes.likelyhood'), ('valid-1', 'modelAttributes.likelihood')])
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '?lenient=true', json = tree, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(len(res['warnings']) == 2)
+    assert(res['result']['title'] == 'Validated')
+
+def test_rollup_across_subtrees():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'subtree project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Parent'}, headers = TEST_HEADERS)
+    parent_id = r.json()['result']['id']
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Child'}, headers = TEST_HEADERS)
+    child_id = r.json()['result']['id']
+
+    parent_root = 'parent-' + str(uuid.uuid4())
+    child_root = 'child-' + str(uuid.uuid4())
+
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(child_id), json = {
+        'title': 'Child',
+        'nodes': [{
+            'id': child_root,
+            'title': "Child root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            # Links back to the parent to make sure cycles terminate
+            'children': [child_root + '-leaf', parent_root],
+        }, {
+            'id': child_root + '-leaf',
+            'title': "Child leaf",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.9}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': child_root
+        }, headers = TEST_HEADERS)
+
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(parent_id), json = {
+        'title': 'Parent',
+        'nodes': [{
+            'id': parent_root,
+            'title': "Parent root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': [parent_root + '-leaf', child_root],
+        }, {
+            'id': parent_root + '-leaf',
+            'title': "Parent leaf",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.3}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': parent_root
+        }, headers = TEST_HEADERS)
+
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(parent_id), headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['rootScore'] == 0.3)
+    assert(res['result']['contributingTrees'] == [])
+
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(parent_id) + '?include_subtrees=true', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['rootScore'] == 0.9)
+    assert(len(res['result']['nodes']) == 2)
+    assert(res['result']['contributingTrees'] == [{'id': child_id, 'title': 'Child'}])
+
+    # Outsiders viewing the public parent don't get the private child
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(parent_id) + '/public', json = {'isPublic': True}, headers = TEST_HEADERS)
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(parent_id) + '?include_subtrees=true', headers = OTHER_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['rootScore'] == 0.3)
+    assert(res['result']['contributingTrees'] == [])
+
+def test_tree_cutsets():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'cutset project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'wifi': False}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Ways in'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    def node(node_id, children, likelihood = None, gate = 'or', condition = ''):
+        return {
+            'id': node_id,
+            'title': node_id,
+            'description': "",
+            'modelAttributes': {} if likelihood is None else {'likelihood': {'value_float': likelihood}},
+            'conditionAttribute': condition,
+            'gateType': gate,
+            'children': children,
+        }
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Ways in',
+        'nodes': [
+            node('cut-0', ['cut-phish', 'cut-both', 'cut-wifi']),
+            node('cut-phish', [], 0.3),
+            node('cut-both', ['cut-badge', 'cut-door'], gate = 'and'),
+            node('cut-badge', [], 0.5),
+            node('cut-door', [], 0.4),
+            node('cut-wifi', [], 0.9, condition = 'config["wifi"] == true'),
+        ],
+        'rootNodeId': 'cut-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '/cutsets', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['truncated'] == False)
+    assert(res['result']['modelId'] == 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74')
+
+    cut_sets = res['result']['cutSets']
+    assert(len(cut_sets) == 2)
+    assert(cut_sets[0]['nodeIds'] == ['cut-phish'])
+    assert(sorted(cut_sets[1]['nodeIds']) == ['cut-badge', 'cut-door'])
+    assert(abs(cut_sets[1]['score'] - 0.2) < 0.0001)
+
+def test_best_attack_path():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'path project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': False}}, headers = TEST_HEADERS)
+    outsider_config = r.json()['result']['id']
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': True}}, headers = TEST_HEADERS)
+    insider_config = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': outsider_config}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Paths'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Paths',
+        'nodes': [{
+            'id': "path-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["path-1", "path-2"],
+        }, {
+            'id': "path-1",
+            'title': "Buy exploit",
+            'description': "",
+            'modelAttributes': {'cost': {'value_int': 5000}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "path-2",
+            'title': "Ask a friend on the inside",
+            'description': "",
+            'modelAttributes': {'cost': {'value_int': 50}},
+            'conditionAttribute': 'config["insider"] == true',
+            'children': [],
+        }],
+        'rootNodeId': 'path-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '/paths/best'
+
+    r = requests.get(url + '?objective=cost', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['nodeIds'] == ['path-0', 'path-1'])
+    assert(res['result']['value'] == 5000)
+
+    r = requests.get(url + '?objective=cost&config_id=' + insider_config, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['nodeIds'] == ['path-0', 'path-2'])
+    assert(res['result']['value'] == 50)
+
+    r = requests.get(url + '?objective=time', headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.get(url + '?objective=fame', headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+def test_simulate_tree():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'simulation project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Uncertain'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Uncertain',
+        'nodes': [{
+            'id': "sim-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'gateType': 'and',
+            'children': ["sim-1", "sim-2"],
+        }, {
+            'id': "sim-1",
+            'title': "Guess password",
+            'description': "",
+            'modelAttributes': {'likelihoodMin': {'value_float': 0.1}, 'likelihoodMax': {'value_float': 0.5}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "sim-2",
+            'title': "Bypass MFA",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.5}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'sim-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '/simulate'
+
+    r = requests.post(url, json = {'iterations': 2000, 'seed': 7, 'nodeIds': ['sim-0', 'sim-1'], 'bins': 5}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['seed'] == 7)
+
+    root = res['result']['nodes'][0]
+    assert(root['nodeId'] == 'sim-0')
+    assert(root['samples'] == 2000)
+    assert(abs(root['mean'] - 0.15) < 0.01)
+    assert(root['percentiles']['p5'] >= 0.05 and root['percentiles']['p95'] <= 0.25)
+    assert(len(root['histogram']) == 5)
+
+    r = requests.post(url, json = {'iterations': 2000, 'seed': 7, 'nodeIds': ['sim-0', 'sim-1'], 'bins': 5}, headers = TEST_HEADERS)
+    assert(r.json()['result'] == res['result'])
+
+    r = requests.post(url, json = {'iterations': 0}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+def test_countermeasures():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'countermeasure project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Defended'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    tree = {
+        'title': 'Defended',
+        'nodes': [{
+            'id': "cm-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["cm-1"],
+        }, {
+            'id': "cm-1",
+            'title': "Phish an admin",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.8}},
+            'conditionAttribute': '',
+            'children': ["cm-2"],
+        }, {
+            'id': "cm-2",
+            'title': "Hardware security keys",
+            'description': "",
+            'modelAttributes': {'effectiveness': {'value_float': 0.75}, 'cost': {'value_int': 2000}},
+            'conditionAttribute': '',
+            'children': [],
+            'nodeType': 'countermeasure'
+        }],
+        'rootNodeId': 'cm-0'
+    }
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['rootScore'] == 0.8)
+    assert(abs(res['result']['mitigatedRootScore'] - 0.2) < 0.0001)
+    assert(res['result']['mitigationCost'] == 2000)
+    for node in res['result']['nodes']:
+        if node['id'] == 'cm-2':
+            assert(node['nodeType'] == 'countermeasure')
+            assert(node['computedAttributes'] == {})
+
+    # Countermeasures are validated against their own attributes
+    tree['nodes'][2]['modelAttributes']['effectiveness'] = {'value_float': 1.5}
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = tree, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == False)
+    assert(res['errors'][0]['field'] == 'modelAttributes.effectiveness')
+
+def test_tree_sensitivity():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'sensitivity project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': False}}, headers = TEST_HEADERS)
+    outsider_config = r.json()['result']['id']
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': True}}, headers = TEST_HEADERS)
+    insider_config = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': outsider_config}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Sensitive'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '/sensitivity'
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Sensitive',
+        'nodes': [{
+            'id': "sens-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["sens-1", "sens-2"],
+            'gateType': 'or'
+        }, {
+            'id': "sens-1",
+            'title': "Get on the network",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.5}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "sens-2",
+            'title': "Borrow a badge",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.9}},
+            'conditionAttribute': 'config["insider"] == true',
+            'children': [],
+        }],
+        'rootNodeId': 'sens-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    # Needs a model to score the root with
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['baselineScore'] == 0.5)
+    assert([entry['nodeId'] for entry in res['result']['ranking']] == ['sens-1'])
+
+    r = requests.get(url + '?config_id=' + insider_config, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['baselineScore'] == 0.9)
+    # The badge dominates the OR, so nudging the network step doesn't move the root
+    assert([entry['nodeId'] for entry in res['result']['ranking']] == ['sens-2', 'sens-1'])
+    assert(res['result']['ranking'][0]['swing'] > res['result']['ranking'][1]['swing'])
+
+    r = requests.get(url + '?perturbation=0', headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+def test_optimize_mitigations():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'mitigation project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Roadmap'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Roadmap',
+        'nodes': [{
+            'id': "opt-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["opt-1", "opt-2"],
+        }, {
+            'id': "opt-1",
+            'title': "Phish an admin",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.8}},
+            'conditionAttribute': '',
+            'children': ["opt-3"],
+        }, {
+            'id': "opt-2",
+            'title': "Exploit the VPN",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.3}},
+            'conditionAttribute': '',
+            'children': ["opt-4"],
+        }, {
+            'id': "opt-3",
+            'title': "Security keys",
+            'description': "",
+            'modelAttributes': {'effectiveness': {'value_float': 0.75}, 'cost': {'value_int': 300}},
+            'conditionAttribute': '',
+            'children': [],
+            'nodeType': 'countermeasure'
+        }, {
+            'id': "opt-4",
+            'title': "Patch the VPN",
+            'description': "",
+            'modelAttributes': {'effectiveness': {'value_float': 0.5}, 'cost': {'value_int': 50}},
+            'conditionAttribute': '',
+            'children': [],
+            'nodeType': 'countermeasure'
+        }],
+        'rootNodeId': 'opt-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id) + '/mitigations/optimize'
+
+    r = requests.post(url, json = {'budget': 400}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['exact'] == True)
+    assert(res['result']['baselineScore'] == 0.8)
+    assert(res['result']['best']['countermeasureIds'] == ['opt-3', 'opt-4'])
+    assert(res['result']['best']['cost'] == 350)
+    assert([portfolio['cost'] for portfolio in res['result']['frontier']] == [0, 300, 350])
+
+    r = requests.post(url, json = {'budget': 100}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['best']['countermeasureIds'] == [])
+
+    r = requests.post(url, json = {'budget': -1}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+def test_attacker_profiles():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'profile project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    url = 'http://localhost:8000/projects/' + project_id + '/profiles'
+
+    r = requests.post(url, json = {'name': 'Script kiddie', 'skill': 2, 'budget': 100, 'access': 'network'}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    kiddie_id = res['result']['id']
+
+    r = requests.post(url, json = {'name': 'Insider', 'skill': 5, 'access': 'Physical'}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['access'] == 'physical')
+    insider_id = res['result']['id']
+
+    r = requests.post(url, json = {'name': 'Ghost', 'access': 'telepathy'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert(len(r.json()['result']['profiles']) == 2)
+
+    r = requests.put(url + '/' + kiddie_id, json = {'name': 'Script kiddie', 'skill': 3, 'budget': 100, 'access': 'network'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+    r = requests.get(url + '/' + kiddie_id, headers = TEST_HEADERS)
+    assert(r.json()['result']['skill'] == 3)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Capabilities'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Capabilities',
+        'nodes': [{
+            'id': "prof-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["prof-1", "prof-2"],
+            'gateType': 'and'
+        }, {
+            'id': "prof-1",
+            'title': "Walk into the server room",
+            'description': "",
+            'modelAttributes': {'access': {'value_string': 'physical'}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "prof-2",
+            'title': "Reset the BMC password",
+            'description': "",
+            'modelAttributes': {'skill': {'value_int': 4}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'prof-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    tree_url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    r = requests.get(tree_url, headers = TEST_HEADERS)
+    assert(all(node['infeasible'] == False for node in r.json()['result']['nodes']))
+
+    r = requests.get(tree_url + '?profile_id=' + kiddie_id, headers = TEST_HEADERS)
+    infeasible = sorted(node['id'] for node in r.json()['result']['nodes'] if node['infeasible'])
+    assert(infeasible == ['prof-0', 'prof-1', 'prof-2'])
+
+    r = requests.get(tree_url + '?profile_id=' + insider_id, headers = TEST_HEADERS)
+    assert(all(node['infeasible'] == False for node in r.json()['result']['nodes']))
+
+    r = requests.get(tree_url + '?profile_id=not-a-profile', headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.delete(url + '/' + kiddie_id, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert(len(r.json()['result']['profiles']) == 1)
+
+def test_time_to_compromise():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'timed project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': False}}, headers = TEST_HEADERS)
+    outsider_config = r.json()['result']['id']
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'insider': True}}, headers = TEST_HEADERS)
+    insider_config = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': outsider_config}, headers = TEST_HEADERS)
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Crown jewels'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Crown jewels',
+        'nodes': [{
+            'id': "time-0",
+            'title': "Exfiltrate the database",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["time-1", "time-2"],
+            'gateType': 'sand'
+        }, {
+            'id': "time-1",
+            'title': "Get in",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["time-3", "time-4"],
+        }, {
+            'id': "time-2",
+            'title': "Dump the tables",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.9}, 'time': {'value_int': 2}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "time-3",
+            'title': "Crack the VPN",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.2}, 'timeMin': {'value_int': 24}, 'timeMax': {'value_int': 72}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "time-4",
+            'title': "Use own badge",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.9}, 'time': {'value_int': 1}},
+            'conditionAttribute': 'config["insider"] == true',
+            'children': [],
+        }],
+        'rootNodeId': 'time-0'
+        }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    def root_time(res):
+        return [node['timeToCompromise'] for node in res['result']['nodes'] if node['id'] == 'time-0'][0]
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert(root_time(r.json()) == {'min': 26, 'max': 74})
+
+    r = requests.get(url + '?config_id=' + insider_config, headers = TEST_HEADERS)
+    assert(root_time(r.json()) == {'min': 3, 'max': 3})
+
+def test_cvss_vectors():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'cvss project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'CVEs'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    tree = {
+        'title': 'CVEs',
+        'nodes': [{
+            'id': "cvss-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["cvss-1", "cvss-2"],
+        }, {
+            'id': "cvss-1",
+            'title': "Unauthenticated RCE",
+            'description': "",
+            'modelAttributes': {'cvss': {'value_string': 'CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H'}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "cvss-2",
+            'title': "Reflected XSS",
+            'description': "",
+            'modelAttributes': {'cvss': {'value_string': 'CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N'}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'cvss-0'
+    }
+
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    res = r.json()
+    for node in res['result']['nodes']:
+        if node['id'] == 'cvss-2':
+            assert(node['computedAttributes']['cvssBaseScore']['value_float'] == 6.1)
+            assert('cvssBaseScore' not in node['modelAttributes'])
+    assert(abs(res['result']['rootScore'] - 0.98) < 0.0001)
+
+    # v4.0 vectors are scored too
+    tree['nodes'][2]['modelAttributes']['cvss'] = {'value_string': 'CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N'}
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    for node in r.json()['result']['nodes']:
+        if node['id'] == 'cvss-2':
+            assert(node['computedAttributes']['cvssBaseScore']['value_float'] == 9.3)
+            assert('cvssBaseScore' not in node['modelAttributes'])
+
+    # A score sent back with the vector removed doesn't linger as a likelihood
+    del tree['nodes'][2]['modelAttributes']['cvss']
+    tree['nodes'][2]['modelAttributes']['cvssBaseScore'] = {'value_float': 9.3}
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    for node in r.json()['result']['nodes']:
+        if node['id'] == 'cvss-2':
+            assert('cvssBaseScore' not in node['modelAttributes'])
+            assert('cvssBaseScore' not in node['computedAttributes'])
+
+    tree['nodes'][1]['modelAttributes']['cvss'] = {'value_string': 'CVSS:3.1/AV:N/AC:L'}
+    r = requests.put(url + '?lenient=true', json = tree, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == False)
+    assert(res['errors'][0]['nodeId'] == 'cvss-1')
+    assert(res['errors'][0]['field'] == 'modelAttributes.cvss')
+
+def test_config_matrix():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'matrix project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    config_ids = []
+    for internet in [True, False]:
+        r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'internet': internet}}, headers = TEST_HEADERS)
+        config_ids.append(r.json()['result']['id'])
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_ids[0]}, headers = TEST_HEADERS)
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Variants'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    tree = {
+        'title': 'Variants',
+        'nodes': [{
+            'id': "matrix-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["matrix-1", "matrix-2"],
+        }, {
+            'id': "matrix-1",
+            'title': "Remote exploit",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.8}},
+            'conditionAttribute': 'config["internet"] == true',
+            'children': [],
+        }, {
+            'id': "matrix-2",
+            'title': "Local exploit",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.3}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'matrix-0'
+    }
+
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url + '/configs/matrix', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert([column['configId'] for column in res['result']['configs']] == config_ids)
+    assert([column['rootScore'] for column in res['result']['configs']] == [0.8, 0.3])
+    assert([column['error'] for column in res['result']['configs']] == [None, None])
+
+    rows = {row['nodeId']: row for row in res['result']['nodes']}
+    assert(rows['matrix-1']['conditionResolved'] == [True, False])
+    assert(rows['matrix-1']['varies'] == True)
+    assert(rows['matrix-2']['conditionResolved'] == [True, True])
+    assert(rows['matrix-2']['varies'] == False)
+
+def test_evaluate_adhoc_config():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'what-if project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'internet': False}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+    requests.put('http://localhost:8000/projects/' + str(project_id) + '/model', json = {'modelId': 'b9ff54e0-37cf-41d4-80ea-f3a9b1e3af74'}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'What if'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    tree = {
+        'title': 'What if',
+        'nodes': [{
+            'id': "whatif-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["whatif-1"],
+        }, {
+            'id': "whatif-1",
+            'title': "Remote exploit",
+            'description': "",
+            'modelAttributes': {'likelihood': {'value_float': 0.8}},
+            'conditionAttribute': 'config["internet"] == true',
+            'children': [],
+        }],
+        'rootNodeId': 'whatif-0'
+    }
+
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post(url + '/evaluate', json = {'attributes': {'internet': True}}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert([node['conditionResolved'] for node in res['result']['nodes'] if node['id'] == 'whatif-1'] == [True])
+    assert(res['result']['rootScore'] == 0.8)
+
+    # Nothing was stored and the selected config still applies
+    r = requests.get('http://localhost:8000/projects/' + project_id + "/configs", headers = TEST_HEADERS)
+    assert(r.json()['result']['ids'] == [config_id])
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert([node['conditionResolved'] for node in r.json()['result']['nodes'] if node['id'] == 'whatif-1'] == [False])
+
+    r = requests.post(url + '/evaluate', json = {'attributes': ['internet']}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+def test_condition_nested_keys():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'condition project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {
+        'network_zone': 'dmz',
+        'network': {'zone': 'lan'},
+        'vendor [eu]': 'acme'
+    }}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Zones'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    conditions = {
+        'zones-1': 'config["network_zone"] == "dmz" && config["network"]["zone"] == "lan"',
+        'zones-2': 'config["network"]["zone"] == "dmz"',
+        'zones-3': "config['vendor [eu]'] == 'acme'",
+        'zones-4': 'config["network"] == ',
+    }
+    tree = {
+        'title': 'Zones',
+        'nodes': [{
+            'id': "zones-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': list(conditions.keys()),
+        }] + [{
+            'id': node_id,
+            'title': node_id,
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': condition,
+            'children': [],
+        } for node_id, condition in conditions.items()],
+        'rootNodeId': 'zones-0'
+    }
+
+    r = requests.put(url, json = tree, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    resolved = {node['id']: node['conditionResolved'] for node in r.json()['result']['nodes']}
+    assert(resolved == {'zones-0': True, 'zones-1': True, 'zones-2': False, 'zones-3': True, 'zones-4': False})
+
+def test_validate_condition():
+    r = requests.post('http://localhost:8000/conditions/validate', json = {'condition': 'config["zone"] == "dmz" && config["vpn"] == true'}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['valid'] == True)
+    assert([key['key'] for key in res['result']['keys']] == ['config["zone"]', 'config["vpn"]'])
+
+    r = requests.post('http://localhost:8000/conditions/validate', json = {'condition': 'config["zone"] = "dmz"'}, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['result']['valid'] == False)
+    assert(res['result']['errors'][0]['start'] == 15)
+
+    r = requests.post('http://localhost:8000/projects', json = {'title':'validation project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'zone': 'dmz'}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/conditions/validate', json = {'condition': 'config["zone"] == "dmz" && config["vpn"]', 'projectId': project_id, 'configId': config_id}, headers = TEST_HEADERS)
+    res = r.json()
+    assert([key['exists'] for key in res['result']['keys']] == [True, False])
+    assert(res['result']['resolved'] == None)
+    assert(res['result']['evaluationError']['message'] == 'config["vpn"] is not set')
+
+    r = requests.post('http://localhost:8000/conditions/validate', json = {'condition': 'config["zone"] == "dmz"', 'configId': config_id}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Typos'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+        'title': 'Typos',
+        'nodes': [{
+            'id': "typo-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': 'config["zone"] == "dmz',
+            'children': [],
+        }],
+        'rootNodeId': 'typo-0'
+    }, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert([(issue['nodeId'], issue['field']) for issue in res['warnings']] == [('typo-0', 'conditionAttribute')])
+
+def test_explain_conditions():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'explain project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'internet': False}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Explained'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    r = requests.put(url, json = {
+        'title': 'Explained',
+        'nodes': [{
+            'id': "explain-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': ["explain-1", "explain-3"],
+        }, {
+            'id': "explain-1",
+            'title': "Remote",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': "config['internet']==true",
+            'children': ["explain-2"],
+        }, {
+            'id': "explain-2",
+            'title': "Exploit",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "explain-3",
+            'title': "VPN",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': 'config["vpn"] == true',
+            'children': [],
+        }],
+        'rootNodeId': 'explain-0'
+    }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    assert(all(node['explanation'] == None for node in r.json()['result']['nodes']))
+
+    r = requests.get(url + '?explain=true', headers = TEST_HEADERS)
+    explanations = {node['id']: node['explanation'] for node in r.json()['result']['nodes']}
+
+    assert(explanations['explain-0']['prunedBy'] == None)
+    assert(explanations['explain-1']['normalized'] == 'config["internet"] == true')
+    assert(explanations['explain-1']['values'] == [{'key': 'config["internet"]', 'value': False}])
+    assert(explanations['explain-1']['prunedBy'] == {'reason': 'condition', 'nodeId': 'explain-1'})
+    assert(explanations['explain-2']['prunedBy'] == {'reason': 'ancestor', 'nodeId': 'explain-1'})
+    assert(explanations['explain-3']['missingKeys'] == ['config["vpn"]'])
+    assert(explanations['explain-3']['error']['message'] == 'config["vpn"] is not set')
+
+    # Once public, outsiders see the tree but not the config values behind it
+    requests.put(url + '/public', json = {'isPublic': True}, headers = TEST_HEADERS)
+    r = requests.get(url + '?explain=true&config_id=' + config_id, headers = OTHER_HEADERS)
+    assert(r.json()['ok'] == True)
+    assert(all(node['explanation'] == None for node in r.json()['result']['nodes']))
+
+def test_condition_collections():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'collections project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {
+        'regions': ['eu', 'us'],
+        'ports': [22, 80, 443],
+        'owner': None
+    }}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Regions'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    conditions = {
+        'regions-1': '"eu" in config["regions"]',
+        'regions-2': '"apac" in config["regions"]',
+        'regions-3': 'len(config["ports"]) > 2',
+        'regions-4': 'config["owner"] == null',
+        'regions-5': 'config["vpn"] == true',
+        'regions-6': 'default(config["vpn"], false) == false',
+    }
+    r = requests.put(url, json = {
+        'title': 'Regions',
+        'nodes': [{
+            'id': "regions-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'children': list(conditions.keys()),
+        }] + [{
+            'id': node_id,
+            'title': node_id,
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': condition,
+            'children': [],
+        } for node_id, condition in conditions.items()],
+        'rootNodeId': 'regions-0'
+    }, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+    assert(r.json()['warnings'] == [])
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    resolved = {node['id']: node['conditionResolved'] for node in r.json()['result']['nodes']}
+    assert(resolved == {
+        'regions-0': True,
+        'regions-1': True,
+        'regions-2': False,
+        'regions-3': True,
+        'regions-4': True,
+        'regions-5': False,
+        'regions-6': True,
+    })
+
+def test_condition_node_references():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'node references project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {'internet': True}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title':'Prerequisites'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+    url = 'http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id)
+
+    def node(node_id, condition, children = [], likelihood = None):
+        return {
+            'id': node_id,
+            'title': node_id,
+            'description': "",
+            'modelAttributes': {} if likelihood is None else {'likelihood': {'value_float': likelihood}},
+            'conditionAttribute': condition,
+            'children': children,
+        }
+
+    # The exfiltration branch only applies once a foothold is possible
+    nodes = [
+        node("prereq-0", '', ["prereq-1", "prereq-2", "prereq-3", "prereq-4"]),
+        node("prereq-1", 'node["prereq-2"].resolved && self.modelAttributes["likelihood"] > 0.3', likelihood = 0.5),
+        node("prereq-2", 'config["internet"]'),
+        node("prereq-3", '!node["prereq-4"].resolved'),
+        node("prereq-4", 'node["prereq-3"].resolved'),
+    ]
+    r = requests.put(url, json = {'title': 'Prerequisites', 'nodes': nodes, 'rootNodeId': 'prereq-0'}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+    warnings = sorted((warning['nodeId'], warning['message']) for warning in r.json()['warnings'])
+    assert(warnings == [
+        ('prereq-3', 'Condition depends on its own resolution through prereq-3 -> prereq-4 -> prereq-3'),
+        ('prereq-4', 'Condition depends on its own resolution through prereq-4 -> prereq-3 -> prereq-4'),
+    ])
+
+    r = requests.get(url, headers = TEST_HEADERS)
+    resolved = {node['id']: node['conditionResolved'] for node in r.json()['result']['nodes']}
+    assert(resolved == {
+        'prereq-0': True,
+        'prereq-1': True,
+        'prereq-2': True,
+        'prereq-3': False,
+        'prereq-4': False,
+    })
+
+    r = requests.post(url + '/evaluate', json = {'attributes': {'internet': False}}, headers = TEST_HEADERS)
+    resolved = {node['id']: node['conditionResolved'] for node in r.json()['result']['nodes']}
+    assert(resolved['prereq-1'] == False)
+
+def test_condition_key_report():
+    r = requests.post('http://localhost:8000/projects', json = {'title':'key report project'}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {
+        'internet': True,
+        'network': {'zone': 'dmz', 'vlan': 12},
+        'owner': 'ops'
+    }}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+
+    def node(node_id, condition, children = []):
+        return {
+            'id': node_id,
+            'title': node_id,
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': condition,
+            'children': children,
+        }
+
+    tree_ids = []
+    for title, nodes in [
+        ('Web', [node("keys-web-0", '', ["keys-web-1"]), node("keys-web-1", 'config["network"]["zone"] == "dmz" && config["internet"]')]),
+        ('Db', [node("keys-db-0", '', ["keys-db-1"]), node("keys-db-1", '!config["internet"] || config["backups"] == false')]),
+    ]:
+        r = requests.post('http://localhost:8000/projects/' + str(project_id) + '/trees', json = {'title': title}, headers = TEST_HEADERS)
+        tree_id = r.json()['result']['id']
+        requests.put('http://localhost:8000/projects/' + str(project_id) + '/trees/' + str(tree_id), json = {
+            'title': title,
+            'nodes': nodes,
+            'rootNodeId': nodes[0]['id']
+        }, headers = TEST_HEADERS)
+        tree_ids.append(tree_id)
+
+    r = requests.get('http://localhost:8000/projects/' + project_id + '/conditions/keys', headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    keys = {usage['key']: usage for usage in res['result']['keys']}
+    assert(list(keys.keys()) == ['config["backups"]', 'config["internet"]', 'config["network"]["zone"]'])
+    assert([node['nodeId'] for node in keys['config["internet"]']['nodes']] == ['keys-web-1', 'keys-db-1'])
+    assert(keys['config["backups"]']['missingFrom'] == [config_id])
+    assert(res['result']['configs'] == [{
+        'configId': config_id,
+        'name': None,
+        'unusedKeys': ['config["network"]["vlan"]', 'config["owner"]']
+    }])
+    assert(res['result']['invalidConditions'] == [])
+
+    # Limited to one tree
+    r = requests.get('http://localhost:8000/projects/' + project_id + '/conditions/keys?tree_id=' + tree_ids[1], headers = TEST_HEADERS)
+    res = r.json()
+    assert([usage['key'] for usage in res['result']['keys']] == ['config["backups"]', 'config["internet"]'])
+    assert(res['result']['configs'][0]['unusedKeys'] == ['config["network"]["vlan"]', 'config["network"]["zone"]', 'config["owner"]'])
+
 def test_get_node_response():
     r = requests.post('http://localhost:8000/projects', json = {'title':'test project'}, headers = TEST_HEADERS)
 
@@ -1139,6 +2482,101 @@ def test_create_project_with_org():
     assert(res['ok'] == False)
 
 
+def test_org_custom_models():
+    r = requests.post('http://localhost:8000/orgs', json = {'name':'Model Makers', 'plan': 'organization'}, headers = TEST_HEADERS)
+    org_id = r.json()['result']['id']
+
+    model = {
+        'title': 'Attack cost',
+        'attributes': [{'key': 'cost', 'valueType': 'number', 'min': 0}],
+        'leafFormula': 'cost',
+        'aggregationFormula': 'if(gate == "or", min(values), sum(values))',
+        'scoreAttribute': 'cost'
+    }
+
+    r = requests.post('http://localhost:8000/orgs/' + org_id + '/models', json = dict(model, aggregationFormula = 'min(values'), headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.post('http://localhost:8000/orgs/' + org_id + '/models', json = model, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    model_id = res['result']['id']
+
+    # Only org members can see the org's models
+    r = requests.get('http://localhost:8000/orgs/' + org_id + '/models', headers = OTHER_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.post('http://localhost:8000/projects', json = {'title':'custom model project', 'orgId': org_id}, headers = TEST_HEADERS)
+    project_id = r.json()['result']['id']
+
+    # Custom models are only listed for projects in the org
+    r = requests.get('http://localhost:8000/models', headers = TEST_HEADERS)
+    assert(model_id not in [m['id'] for m in r.json()['result']['models']])
+
+    r = requests.get('http://localhost:8000/models?project_id=' + project_id, headers = TEST_HEADERS)
+    assert(model_id in [m['id'] for m in r.json()['result']['models']])
+
+    r = requests.get('http://localhost:8000/models?project_id=' + project_id, headers = OTHER_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    # A project outside the org can't select the model
+    r = requests.post('http://localhost:8000/projects', json = {'title':'personal project'}, headers = TEST_HEADERS)
+    personal_project_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + personal_project_id + '/model', json = {'modelId': model_id}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == False)
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + "/configs", json = {'attributes': {}}, headers = TEST_HEADERS)
+    config_id = r.json()['result']['id']
+    requests.put('http://localhost:8000/projects/' + project_id + "/config", json = {'desiredConfig': config_id}, headers = TEST_HEADERS)
+
+    r = requests.put('http://localhost:8000/projects/' + project_id + '/model', json = {'modelId': model_id}, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.post('http://localhost:8000/projects/' + project_id + '/trees', json = {'title':'Costly'}, headers = TEST_HEADERS)
+    tree_id = r.json()['result']['id']
+
+    r = requests.put('http://localhost:8000/projects/' + project_id + '/trees/' + tree_id, json = {
+        'title': 'Costly',
+        'nodes': [{
+            'id': "costly-0",
+            'title': "Root",
+            'description': "",
+            'modelAttributes': {},
+            'conditionAttribute': '',
+            'gateType': 'and',
+            'children': ["costly-1", "costly-2"],
+        }, {
+            'id': "costly-1",
+            'title': "Buy tools",
+            'description': "",
+            'modelAttributes': {'cost': {'value_int': 300}},
+            'conditionAttribute': '',
+            'children': [],
+        }, {
+            'id': "costly-2",
+            'title': "Bribe guard",
+            'description': "",
+            'modelAttributes': {'cost': {'value_int': 200}},
+            'conditionAttribute': '',
+            'children': [],
+        }],
+        'rootNodeId': 'costly-0'
+        }, headers = TEST_HEADERS)
+    res = r.json()
+    assert(res['ok'] == True)
+    assert(res['result']['rootScore'] == 500)
+
+    model['title'] = 'Renamed'
+    r = requests.put('http://localhost:8000/orgs/' + org_id + '/models/' + model_id, json = model, headers = TEST_HEADERS)
+    assert(r.json()['result']['title'] == 'Renamed')
+
+    r = requests.delete('http://localhost:8000/orgs/' + org_id + '/models/' + model_id, headers = TEST_HEADERS)
+    assert(r.json()['ok'] == True)
+
+    r = requests.get('http://localhost:8000/orgs/' + org_id + '/models', headers = TEST_HEADERS)
+    assert(len(r.json()['result']['models']) == 0)
+
 def test_delete_orgs():
     r = requests.post('http://localhost:8000/orgs', json = {'name':'Risky Trees'}, headers = TEST_HEADERS)
 